@@ -0,0 +1,121 @@
+//! Server-side downsampling of series for charts: fixed-width buckets with
+//! min/avg/max/p95, or LTTB (Largest-Triangle-Three-Buckets), which keeps the
+//! visual shape of a series with a fraction of its points.
+
+use crate::retention::Bucket;
+use crate::types::AggregatePoint;
+
+fn bucket_start(ts: i64, bucket_ms: i64) -> i64 {
+    ts.div_euclid(bucket_ms) * bucket_ms
+}
+
+/// Nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], pct: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (pct / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+fn summarize(ts: i64, values: &mut [f64]) -> AggregatePoint {
+    values.sort_by(f64::total_cmp);
+    let sum: f64 = values.iter().sum();
+    AggregatePoint {
+        ts,
+        count: values.len() as i64,
+        min: values[0],
+        avg: sum / values.len() as f64,
+        max: values[values.len() - 1],
+        p95: percentile(values, 95.0),
+    }
+}
+
+/// Group raw samples (sorted by ts) into buckets of `bucket_ms`.
+pub fn buckets(values: &[(i64, f64)], bucket_ms: i64) -> Vec<AggregatePoint> {
+    let mut out = Vec::new();
+    let mut current: Option<i64> = None;
+    let mut pending: Vec<f64> = Vec::new();
+    for (ts, v) in values {
+        let b = bucket_start(*ts, bucket_ms);
+        if current != Some(b) {
+            if let Some(c) = current {
+                out.push(summarize(c, &mut pending));
+                pending.clear();
+            }
+            current = Some(b);
+        }
+        pending.push(*v);
+    }
+    if let Some(c) = current {
+        out.push(summarize(c, &mut pending));
+    }
+    out
+}
+
+/// Merge rollup buckets (sorted by ts) into wider buckets of `bucket_ms`.
+/// Percentiles can't be recovered from rollups, so p95 is left empty.
+pub fn merge_rollups(values: &[(i64, &Bucket)], bucket_ms: i64) -> Vec<AggregatePoint> {
+    let mut out: Vec<AggregatePoint> = Vec::new();
+    for (ts, b) in values {
+        let start = bucket_start(*ts, bucket_ms);
+        match out.last_mut() {
+            Some(p) if p.ts == start => {
+                let count = p.count + b.samples;
+                p.avg = (p.avg * p.count as f64 + b.avg * b.samples as f64) / count as f64;
+                p.count = count;
+                p.min = p.min.min(b.min);
+                p.max = p.max.max(b.max);
+            }
+            _ => out.push(AggregatePoint {
+                ts: start,
+                count: b.samples,
+                min: b.min,
+                avg: b.avg,
+                max: b.max,
+                p95: None,
+            }),
+        }
+    }
+    out
+}
+
+/// Downsample to at most `threshold` points with LTTB. The first and last points
+/// are always kept; series already small enough are returned as is.
+pub fn lttb(values: &[(i64, f64)], threshold: usize) -> Vec<(i64, f64)> {
+    if threshold < 3 || values.len() <= threshold {
+        return values.to_vec();
+    }
+    let mut out = Vec::with_capacity(threshold);
+    out.push(values[0]);
+
+    // Every bucket but the first and last point's
+    let every = (values.len() - 2) as f64 / (threshold - 2) as f64;
+    let mut a = 0;
+    for i in 0..threshold - 2 {
+        // Average of the next bucket is the third triangle vertex
+        let next_start = ((i + 1) as f64 * every) as usize + 1;
+        let next_end = (((i + 2) as f64 * every) as usize + 1).min(values.len());
+        let next = &values[next_start..next_end];
+        let avg_x = next.iter().map(|p| p.0 as f64).sum::<f64>() / next.len() as f64;
+        let avg_y = next.iter().map(|p| p.1).sum::<f64>() / next.len() as f64;
+
+        let start = (i as f64 * every) as usize + 1;
+        let end = next_start;
+        let (ax, ay) = (values[a].0 as f64, values[a].1);
+        let mut best = start;
+        let mut best_area = -1.0;
+        for (j, p) in values.iter().enumerate().take(end).skip(start) {
+            let area = ((ax - avg_x) * (p.1 - ay) - (ax - p.0 as f64) * (avg_y - ay)).abs();
+            if area > best_area {
+                best_area = area;
+                best = j;
+            }
+        }
+        out.push(values[best]);
+        a = best;
+    }
+
+    out.push(values[values.len() - 1]);
+    out
+}
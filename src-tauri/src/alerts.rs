@@ -0,0 +1,323 @@
+//! Threshold alerts evaluated on every collected sample.
+//!
+//! A rule opens an alert once its condition has held for `duration_sec` and
+//! resolves it on the first sample where it no longer holds. Both transitions
+//! are stored in `alert_event` and emitted to the frontend as "alerts://event".
+
+use crate::types::{AlertEvent, AlertRule, StatPoint};
+use log::warn;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, Row};
+use std::collections::HashMap;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+pub const EVENT: &str = "alerts://event";
+pub const COMPARATORS: &[&str] = &[">", ">=", "<", "<=", "==", "!="];
+const SEVERITIES: &[&str] = &["info", "warning", "critical"];
+
+static APP: OnceCell<AppHandle> = OnceCell::new();
+// Enabled rules, reloaded after any rule change
+static RULES: Lazy<Mutex<Option<Vec<AlertRule>>>> = Lazy::new(|| Mutex::new(None));
+// Per (rule id, device id)
+static STATE: Lazy<Mutex<HashMap<(i64, i64), RuleState>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default)]
+struct RuleState {
+    // First sample of the current breach
+    breach_since: Option<i64>,
+    open: Option<AlertEvent>,
+}
+
+pub(crate) const EVENT_COLUMNS: &str = "id, rule_id, device_id, rule_name, metric, comparator, threshold, severity, value, peak, opened_at, resolved_at";
+
+pub(crate) fn event_from_row(row: &Row) -> rusqlite::Result<AlertEvent> {
+    Ok(AlertEvent {
+        id: row.get(0)?,
+        rule_id: row.get(1)?,
+        device_id: row.get(2)?,
+        rule_name: row.get(3)?,
+        metric: row.get(4)?,
+        comparator: row.get(5)?,
+        threshold: row.get(6)?,
+        severity: row.get(7)?,
+        value: row.get(8)?,
+        peak: row.get(9)?,
+        opened_at: row.get(10)?,
+        resolved_at: row.get(11)?,
+    })
+}
+
+pub(crate) const RULE_COLUMNS: &str =
+    "id, device_id, name, metric, comparator, threshold, duration_sec, severity, notify, enabled";
+
+pub(crate) fn rule_from_row(row: &Row) -> rusqlite::Result<AlertRule> {
+    Ok(AlertRule {
+        id: row.get(0)?,
+        device_id: row.get(1)?,
+        name: row.get(2)?,
+        metric: row.get(3)?,
+        comparator: row.get(4)?,
+        threshold: row.get(5)?,
+        duration_sec: row.get(6)?,
+        severity: row.get(7)?,
+        notify: row.get(8)?,
+        enabled: row.get(9)?,
+    })
+}
+
+/// Keep the app handle for events and pick up alerts left open by the last run.
+pub fn init(app: AppHandle, conn: &Connection) {
+    let _ = APP.set(app);
+    let open = conn
+        .prepare(&format!(
+            "SELECT {} FROM alert_event WHERE resolved_at IS NULL",
+            EVENT_COLUMNS
+        ))
+        .and_then(|mut stmt| {
+            stmt.query_map([], event_from_row)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+        });
+    match open {
+        Ok(events) => {
+            let mut state = STATE.lock();
+            for ev in events {
+                state.insert(
+                    (ev.rule_id, ev.device_id),
+                    RuleState {
+                        breach_since: Some(ev.opened_at),
+                        open: Some(ev),
+                    },
+                );
+            }
+        }
+        Err(e) => warn!("failed to load open alerts: {}", e),
+    }
+}
+
+/// Drop the cached rules after they change.
+pub fn invalidate_rules() {
+    *RULES.lock() = None;
+}
+
+pub fn validate(rule: &AlertRule) -> Result<(), String> {
+    if rule.metric.trim().is_empty() {
+        return Err("metric is required".to_string());
+    }
+    if !COMPARATORS.contains(&rule.comparator.as_str()) {
+        return Err(format!("unknown comparator: {}", rule.comparator));
+    }
+    if !rule.severity.is_empty() && !SEVERITIES.contains(&rule.severity.as_str()) {
+        return Err(format!("unknown severity: {}", rule.severity));
+    }
+    if !rule.threshold.is_finite() {
+        return Err("threshold must be a number".to_string());
+    }
+    if rule.duration_sec < 0 {
+        return Err("duration must not be negative".to_string());
+    }
+    Ok(())
+}
+
+/// Value of a StatPoint field by its snake_case name, or of a named metric series.
+pub fn metric_value(point: &StatPoint, metric: &str) -> Option<f64> {
+    match metric {
+        "cpu" => Some(point.cpu),
+        "ram_used_mb" => Some(point.ram_used_mb as f64),
+        "ram_total_mb" => Some(point.ram_total_mb as f64),
+        "ram_used_pct" => (point.ram_total_mb > 0)
+            .then(|| point.ram_used_mb as f64 / point.ram_total_mb as f64 * 100.0),
+        "gpu_util" => point.gpu_util,
+        "gpu_temp_c" => point.gpu_temp_c,
+        "fan_rpm" => point.fan_rpm.map(|v| v as f64),
+        "fan_pwm" => point.fan_pwm.map(|v| v as f64),
+        name => point.metrics.get(name).copied(),
+    }
+}
+
+fn breached(comparator: &str, value: f64, threshold: f64) -> bool {
+    match comparator {
+        ">" => value > threshold,
+        ">=" => value >= threshold,
+        "<" => value < threshold,
+        "<=" => value <= threshold,
+        "==" => value == threshold,
+        "!=" => value != threshold,
+        _ => false,
+    }
+}
+
+/// The further-from-threshold of two breaching values.
+fn worse(comparator: &str, a: f64, b: f64) -> f64 {
+    match comparator {
+        ">" | ">=" => a.max(b),
+        "<" | "<=" => a.min(b),
+        _ => b,
+    }
+}
+
+fn emit(event: &AlertEvent) {
+    if let Some(app) = APP.get() {
+        let _ = app.emit(EVENT, event.clone());
+    }
+}
+
+fn notify(conn: &Connection, event: &AlertEvent) {
+    let Some(app) = APP.get() else {
+        return;
+    };
+    let device: String = conn
+        .query_row(
+            "SELECT name FROM device WHERE id = ?1",
+            [event.device_id],
+            |row| row.get(0),
+        )
+        .unwrap_or_else(|_| format!("device {}", event.device_id));
+    let title = format!(
+        "{}: {}",
+        device,
+        event.rule_name.as_deref().unwrap_or(&event.metric)
+    );
+    let body = format!(
+        "{} is {:.1} ({} {})",
+        event.metric, event.value, event.comparator, event.threshold
+    );
+    if let Err(e) = app.notification().builder().title(title).body(body).show() {
+        warn!("failed to show alert notification: {}", e);
+    }
+}
+
+fn load_rules(conn: &Connection) -> Result<Vec<AlertRule>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM alert_rule WHERE enabled = 1",
+            RULE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], rule_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn resolve(conn: &Connection, mut event: AlertEvent, ts: i64) -> Result<(), String> {
+    conn.execute(
+        "UPDATE alert_event SET resolved_at = ?1, peak = ?2 WHERE id = ?3",
+        params![ts, event.peak, event.id],
+    )
+    .map_err(|e| e.to_string())?;
+    event.resolved_at = Some(ts);
+    emit(&event);
+    Ok(())
+}
+
+/// Check a sample against every rule that applies to its device.
+pub fn evaluate(conn: &Connection, point: &StatPoint) {
+    let rules = {
+        let mut cached = RULES.lock();
+        if cached.is_none() {
+            match load_rules(conn) {
+                Ok(r) => *cached = Some(r),
+                Err(e) => {
+                    warn!("failed to load alert rules: {}", e);
+                    return;
+                }
+            }
+        }
+        cached.clone().unwrap_or_default()
+    };
+
+    let mut state = STATE.lock();
+    for rule in rules
+        .iter()
+        .filter(|r| r.device_id.is_none_or(|d| d == point.device_id))
+    {
+        let Some(rule_id) = rule.id else {
+            continue;
+        };
+        // Series that aren't reported this sample leave the alert as it is
+        let Some(value) = metric_value(point, &rule.metric) else {
+            continue;
+        };
+        let st = state.entry((rule_id, point.device_id)).or_default();
+
+        if !breached(&rule.comparator, value, rule.threshold) {
+            st.breach_since = None;
+            if let Some(event) = st.open.take() {
+                if let Err(e) = resolve(conn, event, point.ts) {
+                    warn!("failed to resolve alert: {}", e);
+                }
+            }
+            continue;
+        }
+
+        let since = *st.breach_since.get_or_insert(point.ts);
+        if let Some(event) = st.open.as_mut() {
+            event.peak = worse(&rule.comparator, event.peak, value);
+            continue;
+        }
+        if point.ts - since < rule.duration_sec * 1000 {
+            continue;
+        }
+
+        let mut event = AlertEvent {
+            id: 0,
+            rule_id,
+            device_id: point.device_id,
+            rule_name: rule.name.clone(),
+            metric: rule.metric.clone(),
+            comparator: rule.comparator.clone(),
+            threshold: rule.threshold,
+            severity: rule.severity.clone(),
+            value,
+            peak: value,
+            opened_at: point.ts,
+            resolved_at: None,
+        };
+        let inserted = conn.execute(
+            "INSERT INTO alert_event (rule_id, device_id, rule_name, metric, comparator, threshold, severity, value, peak, opened_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                event.rule_id,
+                event.device_id,
+                event.rule_name,
+                event.metric,
+                event.comparator,
+                event.threshold,
+                event.severity,
+                event.value,
+                event.peak,
+                event.opened_at
+            ],
+        );
+        if let Err(e) = inserted {
+            warn!("failed to store alert: {}", e);
+            continue;
+        }
+        event.id = conn.last_insert_rowid();
+        emit(&event);
+        if rule.notify {
+            notify(conn, &event);
+        }
+        st.open = Some(event);
+    }
+}
+
+/// Resolve a rule's open alerts and forget its state, after it was changed,
+/// disabled or deleted.
+pub fn close_rule(conn: &Connection, rule_id: i64) -> Result<(), String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let events: Vec<AlertEvent> = {
+        let mut state = STATE.lock();
+        let keys: Vec<(i64, i64)> = state.keys().filter(|k| k.0 == rule_id).copied().collect();
+        keys.into_iter()
+            .filter_map(|k| state.remove(&k).and_then(|s| s.open))
+            .collect()
+    };
+    for event in events {
+        resolve(conn, event, now)?;
+    }
+    Ok(())
+}
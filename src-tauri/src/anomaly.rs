@@ -0,0 +1,437 @@
+//! Anomaly detection on stored stats, run locally against `device_stats` and
+//! its 1-minute rollups.
+//!
+//! Detectors:
+//!   baseline  recent average far (in standard deviations) from the device's own
+//!             history for the same hour of day
+//!   trend     RAM use climbing steadily over the last day, as a leak would
+//!   fleet     recent average far from devices of the same model (median/MAD)
+//! A finding opens an event in `anomaly_event`; later runs update it while it
+//! persists and resolve it once it no longer shows up.
+
+use crate::db::db_conn;
+use crate::retention;
+use crate::types::AnomalyEvent;
+use chrono::{Local, Offset};
+use log::warn;
+use once_cell::sync::OnceCell;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{BTreeMap, HashMap};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+
+pub const EVENT: &str = "anomalies://event";
+
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+const RUN_EVERY: Duration = Duration::from_secs(15 * 60);
+
+// Recent window compared against the models
+const WINDOW_MS: i64 = 15 * 60 * 1000;
+// History the hour-of-day baseline is built from; older than the raw retention,
+// so it is read from the 1-minute rollups (kept 90 days by default)
+const BASELINE_MS: i64 = 14 * DAY_MS;
+// Samples needed in an hour-of-day slot (and the window) before it is trusted
+const MIN_SAMPLES: i64 = 30;
+const BASELINE_Z: f64 = 3.0;
+// Trend: span looked at, least span of data needed, fit quality and growth
+const TREND_MS: i64 = DAY_MS;
+const TREND_MIN_SPAN_MS: i64 = 6 * HOUR_MS;
+const TREND_MIN_R2: f64 = 0.6;
+// Growth per day, as a share of total RAM, worth flagging
+const TREND_DAILY_PCT: f64 = 5.0;
+// Fleet: devices needed per model and the robust z-score threshold
+const FLEET_MIN_DEVICES: usize = 3;
+const FLEET_Z: f64 = 3.5;
+
+// device_stats columns checked, with the smallest deviation that counts as
+// meaningful; below it a tiny stddev would flag noise
+const METRICS: &[(&str, f64)] = &[
+    ("cpu", 10.0),
+    ("gpu_util", 10.0),
+    ("gpu_temp_c", 3.0),
+    ("ram_used_mb", 200.0),
+];
+
+static APP: OnceCell<AppHandle> = OnceCell::new();
+
+pub(crate) const EVENT_COLUMNS: &str = "id, device_id, kind, metric, value, expected, score, detail, detected_at, last_seen_at, resolved_at";
+
+pub(crate) fn event_from_row(row: &Row) -> rusqlite::Result<AnomalyEvent> {
+    Ok(AnomalyEvent {
+        id: row.get(0)?,
+        device_id: row.get(1)?,
+        kind: row.get(2)?,
+        metric: row.get(3)?,
+        value: row.get(4)?,
+        expected: row.get(5)?,
+        score: row.get(6)?,
+        detail: row.get(7)?,
+        detected_at: row.get(8)?,
+        last_seen_at: row.get(9)?,
+        resolved_at: row.get(10)?,
+    })
+}
+
+struct Finding {
+    device_id: i64,
+    kind: &'static str,
+    metric: &'static str,
+    value: f64,
+    expected: f64,
+    score: f64,
+    detail: String,
+}
+
+/// Recent average and sample count per device for a column.
+fn recent(conn: &Connection, column: &str, now: i64) -> Result<HashMap<i64, (f64, i64)>, String> {
+    let sql = format!(
+        "SELECT device_id, AVG({c}), COUNT({c}) FROM device_stats WHERE ts > ?1 AND ts <= ?2 AND {c} IS NOT NULL GROUP BY device_id",
+        c = column
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![now - WINDOW_MS, now], |row| {
+            Ok((row.get(0)?, (row.get(1)?, row.get(2)?)))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn baseline(
+    conn: &Connection,
+    now: i64,
+    recent: &HashMap<i64, (f64, i64)>,
+    column: &'static str,
+    floor: f64,
+    out: &mut Vec<Finding>,
+) -> Result<(), String> {
+    // Hours of day are local, so "busy in the afternoon" lines up across days
+    let offset_ms = Local::now().offset().fix().local_minus_utc() as i64 * 1000;
+    let hour = (now + offset_ms).div_euclid(HOUR_MS).rem_euclid(24);
+    let name = retention::CORE_SERIES
+        .iter()
+        .find(|(c, _)| *c == column)
+        .map(|(_, n)| n.to_string())
+        .ok_or_else(|| format!("no rolled up series for {}", column))?;
+    for (&device_id, &(value, samples)) in recent {
+        if samples < MIN_SAMPLES {
+            continue;
+        }
+        // Raw samples are pruned after a week by default, so the history comes
+        // from the 1-minute rollups, weighted by the samples in each bucket. The
+        // spread is then that of minute averages, which suits comparing a
+        // 15-minute average against it
+        let buckets = retention::query(
+            conn,
+            device_id,
+            retention::MINUTE_MS,
+            std::slice::from_ref(&name),
+            now - BASELINE_MS,
+            now - WINDOW_MS,
+        )?;
+        let (mut n, mut sum, mut sum_sq) = (0i64, 0.0, 0.0);
+        for (ts, series) in &buckets {
+            if (ts + offset_ms).div_euclid(HOUR_MS).rem_euclid(24) != hour {
+                continue;
+            }
+            if let Some(b) = series.get(&name) {
+                n += b.samples;
+                sum += b.avg * b.samples as f64;
+                sum_sq += b.avg * b.avg * b.samples as f64;
+            }
+        }
+        if n < MIN_SAMPLES {
+            continue;
+        }
+        let mean = sum / n as f64;
+        let mean_sq = sum_sq / n as f64;
+        let std = (mean_sq - mean * mean).max(0.0).sqrt();
+        // Never judge against a spread tighter than a meaningful deviation
+        let z = (value - mean) / std.max(floor / BASELINE_Z);
+        if z.abs() >= BASELINE_Z {
+            out.push(Finding {
+                device_id,
+                kind: "baseline",
+                metric: column,
+                value,
+                expected: mean,
+                score: z,
+                detail: format!(
+                    "{:.1} vs usual {:.1} ± {:.1} at {:02}:00 ({} samples)",
+                    value, mean, std, hour, n
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn ram_trend(conn: &Connection, now: i64, out: &mut Vec<Finding>) -> Result<(), String> {
+    // Least squares over (hours since start, MB)
+    let mut stmt = conn
+        .prepare(
+            "SELECT device_id, COUNT(*), MIN(ts), MAX(ts),
+                    SUM(x), SUM(y), SUM(x * x), SUM(x * y), SUM(y * y), MAX(ram_total_mb), AVG(y)
+             FROM (SELECT device_id, ts, ram_total_mb, (ts - ?1) / 3600000.0 AS x, CAST(ram_used_mb AS REAL) AS y
+                   FROM device_stats WHERE ts >= ?1 AND ts <= ?2)
+             GROUP BY device_id",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![now - TREND_MS, now], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+                row.get::<_, i64>(3)?,
+                [
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, f64>(6)?,
+                    row.get::<_, f64>(7)?,
+                    row.get::<_, f64>(8)?,
+                ],
+                row.get::<_, i64>(9)?,
+                row.get::<_, f64>(10)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (device_id, n, first, last, [sx, sy, sxx, sxy, syy], total, avg) =
+            row.map_err(|e| e.to_string())?;
+        if n < MIN_SAMPLES || last - first < TREND_MIN_SPAN_MS || total <= 0 {
+            continue;
+        }
+        let n = n as f64;
+        let var_x = n * sxx - sx * sx;
+        let var_y = n * syy - sy * sy;
+        if var_x <= 0.0 || var_y <= 0.0 {
+            continue;
+        }
+        let cov = n * sxy - sx * sy;
+        // MB per hour
+        let slope = cov / var_x;
+        let r2 = cov * cov / (var_x * var_y);
+        let daily_pct = slope * 24.0 / total as f64 * 100.0;
+        if slope > 0.0 && r2 >= TREND_MIN_R2 && daily_pct >= TREND_DAILY_PCT {
+            let intercept = (sy - slope * sx) / n;
+            let current = intercept + slope * (TREND_MS / HOUR_MS) as f64;
+            let hours_left = (total as f64 - current).max(0.0) / slope;
+            out.push(Finding {
+                device_id,
+                kind: "trend",
+                metric: "ram_used_mb",
+                value: slope,
+                expected: 0.0,
+                score: r2,
+                detail: format!(
+                    "RAM growing {:.0} MB/h ({:.1}% of total per day, r² {:.2}, avg {:.0} MB); full in about {:.0} h",
+                    slope, daily_pct, r2, avg, hours_left
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+fn fleet(
+    recent: &HashMap<i64, (f64, i64)>,
+    models: &HashMap<i64, String>,
+    column: &'static str,
+    floor: f64,
+    out: &mut Vec<Finding>,
+) {
+    let mut groups: BTreeMap<&str, Vec<(i64, f64)>> = BTreeMap::new();
+    for (&device_id, &(value, samples)) in recent {
+        if samples < MIN_SAMPLES {
+            continue;
+        }
+        if let Some(model) = models.get(&device_id) {
+            groups.entry(model).or_default().push((device_id, value));
+        }
+    }
+    for (model, members) in groups {
+        if members.len() < FLEET_MIN_DEVICES {
+            continue;
+        }
+        let mut values: Vec<f64> = members.iter().map(|(_, v)| *v).collect();
+        values.sort_by(f64::total_cmp);
+        let med = median(&values);
+        let mut deviations: Vec<f64> = values.iter().map(|v| (v - med).abs()).collect();
+        deviations.sort_by(f64::total_cmp);
+        // Scaled to match a stddev on normal data, with the same floor as the baseline
+        let mad = (median(&deviations) * 1.4826).max(floor / FLEET_Z);
+        for (device_id, value) in members {
+            let z = (value - med) / mad;
+            if z.abs() >= FLEET_Z {
+                out.push(Finding {
+                    device_id,
+                    kind: "fleet",
+                    metric: column,
+                    value,
+                    expected: med,
+                    score: z,
+                    detail: format!(
+                        "{:.1} vs fleet median {:.1} across {} {} devices",
+                        value,
+                        med,
+                        values.len(),
+                        model
+                    ),
+                });
+            }
+        }
+    }
+}
+
+fn device_models(conn: &Connection) -> Result<HashMap<i64, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT device_id, model FROM system_info WHERE model IS NOT NULL AND model != ''")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn emit(event: &AnomalyEvent) {
+    if let Some(app) = APP.get() {
+        let _ = app.emit(EVENT, event.clone());
+    }
+}
+
+/// Store findings: update events that are still open, open new ones, and
+/// resolve open events of the checked devices that were not found again.
+fn record(
+    conn: &Connection,
+    now: i64,
+    devices: &[i64],
+    findings: &[Finding],
+) -> Result<Vec<AnomalyEvent>, String> {
+    let mut opened = Vec::new();
+    let mut seen: Vec<i64> = Vec::new();
+    for f in findings {
+        let open: Option<i64> = conn
+            .query_row(
+                "SELECT id FROM anomaly_event WHERE device_id = ?1 AND kind = ?2 AND metric = ?3 AND resolved_at IS NULL",
+                params![f.device_id, f.kind, f.metric],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        match open {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE anomaly_event SET value = ?1, expected = ?2, score = ?3, detail = ?4, last_seen_at = ?5 WHERE id = ?6",
+                    params![f.value, f.expected, f.score, f.detail, now, id],
+                )
+                .map_err(|e| e.to_string())?;
+                seen.push(id);
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO anomaly_event (device_id, kind, metric, value, expected, score, detail, detected_at, last_seen_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+                    params![f.device_id, f.kind, f.metric, f.value, f.expected, f.score, f.detail, now],
+                )
+                .map_err(|e| e.to_string())?;
+                let id = conn.last_insert_rowid();
+                seen.push(id);
+                let event = AnomalyEvent {
+                    id,
+                    device_id: f.device_id,
+                    kind: f.kind.to_string(),
+                    metric: f.metric.to_string(),
+                    value: f.value,
+                    expected: f.expected,
+                    score: f.score,
+                    detail: Some(f.detail.clone()),
+                    detected_at: now,
+                    last_seen_at: now,
+                    resolved_at: None,
+                };
+                emit(&event);
+                opened.push(event);
+            }
+        }
+    }
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM anomaly_event WHERE device_id = ?1 AND resolved_at IS NULL",
+            EVENT_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    for device_id in devices {
+        let open = stmt
+            .query_map([device_id], event_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        for mut event in open.into_iter().filter(|e| !seen.contains(&e.id)) {
+            conn.execute(
+                "UPDATE anomaly_event SET resolved_at = ?1 WHERE id = ?2",
+                params![now, event.id],
+            )
+            .map_err(|e| e.to_string())?;
+            event.resolved_at = Some(now);
+            emit(&event);
+        }
+    }
+    Ok(opened)
+}
+
+/// Run every detector over the devices with recent samples (or just `device_id`,
+/// still compared against its whole fleet). Returns the newly opened events.
+pub fn detect(conn: &Connection, device_id: Option<i64>) -> Result<Vec<AnomalyEvent>, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let models = device_models(conn)?;
+    let mut findings = Vec::new();
+    let mut devices: Vec<i64> = Vec::new();
+    for (column, floor) in METRICS {
+        let mut recent = recent(conn, column, now)?;
+        fleet(&recent, &models, column, *floor, &mut findings);
+        if let Some(id) = device_id {
+            recent.retain(|d, _| *d == id);
+        }
+        baseline(conn, now, &recent, column, *floor, &mut findings)?;
+        devices.extend(recent.keys());
+    }
+    ram_trend(conn, now, &mut findings)?;
+    if let Some(id) = device_id {
+        findings.retain(|f| f.device_id == id);
+    }
+    devices.extend(findings.iter().map(|f| f.device_id));
+    devices.sort_unstable();
+    devices.dedup();
+    // A device without recent samples keeps its events open until it reports again
+    record(conn, now, &devices, &findings)
+}
+
+/// Run detection in the background.
+pub fn start(app: AppHandle) {
+    let _ = APP.set(app);
+    thread::spawn(|| loop {
+        thread::sleep(RUN_EVERY);
+        match db_conn() {
+            Ok(conn) => {
+                if let Err(e) = detect(&conn, None) {
+                    warn!("anomaly detection failed: {}", e);
+                }
+            }
+            Err(e) => warn!("anomaly detection: {}", e),
+        }
+    });
+}
@@ -0,0 +1,717 @@
+use crate::alerts;
+use crate::commands::connection::open_dedicated_session;
+use crate::commands::system::{parse_fan_line, FAN_READ_SH};
+use crate::db::db_conn;
+use crate::metrics;
+use crate::runs;
+use crate::sinks;
+use crate::tegrastats;
+use crate::throttle;
+use crate::types::{StatPoint, Subscription};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+use tauri::ipc::Channel;
+use tauri::{AppHandle, Emitter};
+
+// Filesystems that are not backed by storage and are left out of df
+pub(crate) const DF_EXCLUDE: &str = "-x tmpfs -x devtmpfs -x squashfs -x overlay";
+
+// Active nvpmodel mode. The name is only looked up in nvpmodel.conf when the
+// status file changes; the pm_* variables survive across stream loop iterations.
+const POWER_MODE_SH: &str = "pm_s=$(cat /var/lib/nvpmodel/status 2>/dev/null); if [ \"$pm_s\" != \"$pm_l\" ]; then pm_l=$pm_s; pm_i=$(echo \"$pm_s\" | sed -n 's/.*pmode:\\([0-9]*\\).*/\\1/p'); pm_n=; [ -n \"$pm_i\" ] && pm_n=$(awk -v want=\"$pm_i\" '/POWER_MODEL/ { id = \"\"; name = \"\"; for (i = 1; i <= NF; i++) { split($i, kv, \"=\"); if (kv[1] == \"ID\") id = kv[2]; if (kv[1] == \"NAME\") name = kv[2] } if (id != \"\" && id + 0 == want + 0) { print name; exit } }' /etc/nvpmodel.conf 2>/dev/null); fi; echo P $pm_i $pm_n";
+
+// Every sample is emitted by the device as a block of tagged lines:
+//   T <tegrastats line>
+//   S <uptime seconds>
+//   C <first line of /proc/stat>
+//   M <total MB> <used MB>
+//   K <cpufreq policy0 min> <max>
+//   F <fan pwm> <rpm>
+//   P <nvpmodel mode id> <mode name>
+//   D <disk> <reads> <sectors read> <writes> <sectors written> <ms doing io>   (per disk)
+//   V <mount> <size kB> <used kB> <avail kB>                                   (per mount)
+//   N <mount> <inodes> <inodes used>                                           (per mount)
+//   I <iface> <rx bytes> <rx packets> <rx errs> <tx bytes> <tx packets> <tx errs> (per interface)
+//   W <iface> <signal dBm> <tx bitrate Mbit/s>                                 (per wireless interface)
+//   Z / O / Q <sysfs path>:<value>                                             (throttle probes, see throttle.rs)
+//   E
+fn sample_body() -> String {
+    format!(
+        "echo S $(cut -d' ' -f1 /proc/uptime); head -n1 /proc/stat | sed 's/^/C /'; free -m | awk '/Mem:/ {{print \"M\", $2, $3}}'; echo K $(cat /sys/devices/system/cpu/cpufreq/policy0/scaling_min_freq /sys/devices/system/cpu/cpufreq/policy0/scaling_max_freq 2>/dev/null); printf 'F '; {fan}; {power}; \
+        awk '$3 ~ /^(mmcblk[0-9]+|nvme[0-9]+n[0-9]+|sd[a-z]+|vd[a-z]+)$/ {{print \"D\", $3, $4, $6, $8, $10, $13}}' /proc/diskstats; \
+        df -P -k {df} 2>/dev/null | awk 'NR > 1 {{print \"V\", $6, $2, $3, $4}}'; \
+        df -P -i {df} 2>/dev/null | awk 'NR > 1 {{print \"N\", $6, $2, $3}}'; \
+        awk 'NR > 2 {{sub(\":\", \" \"); if ($1 != \"lo\") print \"I\", $1, $2, $3, $4, $10, $11, $12}}' /proc/net/dev; \
+        awk 'NR > 2 {{sub(/:$/, \"\", $1); sub(/\\.$/, \"\", $4); print $1, $4}}' /proc/net/wireless 2>/dev/null | while read -r i l; do echo W $i $l $(iw dev $i link 2>/dev/null | awk '/tx bitrate/ {{print $3}}'); done; \
+        {throttle}; \
+        echo E",
+        fan = FAN_READ_SH,
+        power = POWER_MODE_SH,
+        df = DF_EXCLUDE,
+        throttle = throttle::PROBE_SH
+    )
+}
+
+/// Script producing a single sample block (used by one-shot `record_stat`).
+pub(crate) fn oneshot_script() -> String {
+    format!(
+        "T=$(tegrastats --interval 1000 --count 1 2>/dev/null || sudo -n tegrastats --interval 1000 --count 1 2>/dev/null); echo \"T $T\"; {}",
+        sample_body()
+    )
+}
+
+/// Long-running script: tegrastats paces the loop and each of its lines
+/// triggers one sample block. Devices without tegrastats fall back to sleep.
+fn stream_script(interval_ms: u64) -> String {
+    format!(
+        "{{ tegrastats --interval {ms} 2>/dev/null || sudo -n tegrastats --interval {ms} 2>/dev/null || while :; do echo; sleep {secs}; done; }} | while read -r T; do echo \"T $T\"; {body}; done",
+        ms = interval_ms,
+        secs = interval_ms as f64 / 1000.0,
+        body = sample_body()
+    )
+}
+
+/// Lines of one sample block, collected until the closing `E`.
+#[derive(Default)]
+pub(crate) struct RawSample {
+    tegra: String,
+    uptime: String,
+    cpu: String,
+    mem: String,
+    clock: String,
+    fan: String,
+    power: String,
+    disks: Vec<String>,
+    volumes: Vec<String>,
+    inodes: Vec<String>,
+    ifaces: Vec<String>,
+    wireless: Vec<String>,
+    throttle: Vec<String>,
+}
+
+/// Values carried over from the previous sample so cumulative counters
+/// (CPU jiffies, disk sectors, ...) can be turned into usage and rates.
+#[derive(Default, Clone)]
+pub(crate) struct SampleState {
+    cpu: Option<(u64, u64)>,
+    uptime: Option<f64>,
+    counters: HashMap<String, u64>,
+}
+
+impl SampleState {
+    /// Record a cumulative counter and return its per-second rate since the
+    /// previous sample, if there was one.
+    fn rate(
+        &self,
+        next: &mut HashMap<String, u64>,
+        key: String,
+        value: u64,
+        dt: Option<f64>,
+    ) -> Option<f64> {
+        let prev = self.counters.get(&key).copied();
+        next.insert(key, value);
+        Some(value.saturating_sub(prev?) as f64 / dt?)
+    }
+}
+
+/// Series name component for a mount point: "/" -> "root", "/mnt/ssd" -> "mnt_ssd".
+fn mount_key(mount: &str) -> String {
+    let trimmed = mount.trim_matches('/');
+    if trimmed.is_empty() {
+        "root".to_string()
+    } else {
+        trimmed.replace(['/', '.'], "_")
+    }
+}
+
+impl RawSample {
+    /// Feed one tagged line; returns true once the block is complete.
+    pub(crate) fn push_line(&mut self, line: &str) -> bool {
+        let (tag, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let slot = match tag {
+            "T" => &mut self.tegra,
+            "S" => &mut self.uptime,
+            "C" => &mut self.cpu,
+            "M" => &mut self.mem,
+            "K" => &mut self.clock,
+            "F" => &mut self.fan,
+            "P" => &mut self.power,
+            "D" => {
+                self.disks.push(rest.to_string());
+                return false;
+            }
+            "V" => {
+                self.volumes.push(rest.to_string());
+                return false;
+            }
+            "N" => {
+                self.inodes.push(rest.to_string());
+                return false;
+            }
+            "I" => {
+                self.ifaces.push(rest.to_string());
+                return false;
+            }
+            "W" => {
+                self.wireless.push(rest.to_string());
+                return false;
+            }
+            "Z" | "O" | "Q" => {
+                self.throttle.push(line.to_string());
+                return false;
+            }
+            "E" => return true,
+            _ => return false,
+        };
+        *slot = rest.to_string();
+        false
+    }
+
+    /// Build a point from the block. `state` holds the previous sample's
+    /// counters so usage and rates are computed from deltas, and is updated in place.
+    pub(crate) fn to_point(
+        &self,
+        state: &mut SampleState,
+        device_id: i64,
+    ) -> Result<StatPoint, String> {
+        let (total, idle_total) =
+            parse_cpu_line(&self.cpu).ok_or_else(|| "failed to parse cpu".to_string())?;
+        let (cpu_prev_total, cpu_prev_idle) = state
+            .cpu
+            .replace((total, idle_total))
+            .unwrap_or((total, idle_total));
+        let dt = total.saturating_sub(cpu_prev_total);
+        let didle = idle_total.saturating_sub(cpu_prev_idle);
+        let cpu = if dt > 0 {
+            (dt - didle) as f64 / dt as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        // Seconds since the previous sample, measured on the device
+        let uptime = self.uptime.trim().parse::<f64>().ok();
+        let elapsed = match (state.uptime, uptime) {
+            (Some(prev), Some(now)) if now > prev => Some(now - prev),
+            _ => None,
+        };
+        state.uptime = uptime;
+
+        let (ram_total_mb, ram_used_mb) = {
+            let mut it = self.mem.split_whitespace();
+            let total = it.next().and_then(|s| s.parse::<i64>().ok()).unwrap_or(0);
+            let used = it.next().and_then(|s| s.parse::<i64>().ok()).unwrap_or(0);
+            (total, used)
+        };
+
+        // Mode name, or the bare id when nvpmodel.conf has no entry for it
+        let power_mode = {
+            let mut it = self.power.split_whitespace();
+            it.next().map(|id| {
+                let id = id.trim_start_matches('0');
+                let id = if id.is_empty() { "0" } else { id };
+                it.next().unwrap_or(id).to_string()
+            })
+        };
+
+        let clocks_locked = {
+            let freqs: Vec<i64> = self
+                .clock
+                .split_whitespace()
+                .filter_map(|s| s.parse::<i64>().ok())
+                .collect();
+            match freqs.as_slice() {
+                [min, max] => Some(min == max),
+                _ => None,
+            }
+        };
+
+        let (fan_pwm, fan_rpm) = parse_fan_line(&self.fan);
+        let tegra = if self.tegra.trim().is_empty() {
+            None
+        } else {
+            Some(tegrastats::parse(&self.tegra))
+        };
+
+        let mut metrics = BTreeMap::new();
+        metrics.insert("cpu".to_string(), cpu);
+        metrics.insert("ram.used".to_string(), ram_used_mb as f64);
+        metrics.insert("ram.total".to_string(), ram_total_mb as f64);
+        if let Some(rpm) = fan_rpm {
+            metrics.insert("fan.rpm".to_string(), rpm as f64);
+        }
+        if let Some(pwm) = fan_pwm {
+            metrics.insert("fan.pwm".to_string(), pwm as f64);
+        }
+        if let Some(t) = &tegra {
+            metrics::add_tegrastats(t, &mut metrics);
+        }
+
+        let mut counters: HashMap<String, u64> = HashMap::new();
+        for line in &self.disks {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            let nums: Vec<u64> = f.iter().skip(1).filter_map(|v| v.parse().ok()).collect();
+            let (Some(dev), [reads, rsect, writes, wsect, io_ms]) = (f.first(), nums.as_slice())
+            else {
+                continue;
+            };
+            let mut put = |what: &str, value: u64, scale: f64| {
+                let key = format!("io.{}.{}", dev, what);
+                if let Some(r) = state.rate(&mut counters, key.clone(), value, elapsed) {
+                    metrics.insert(key, r * scale);
+                }
+            };
+            // Sectors are always 512 bytes in /proc/diskstats
+            put("read.bps", *rsect, 512.0);
+            put("write.bps", *wsect, 512.0);
+            put("read.iops", *reads, 1.0);
+            put("write.iops", *writes, 1.0);
+            // ms spent doing I/O per second of wall time -> busy percentage
+            put("util.pct", *io_ms, 0.1);
+        }
+        for line in &self.ifaces {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            let nums: Vec<u64> = f.iter().skip(1).filter_map(|v| v.parse().ok()).collect();
+            let (Some(iface), [rx_b, rx_p, rx_e, tx_b, tx_p, tx_e]) = (f.first(), nums.as_slice())
+            else {
+                continue;
+            };
+            let mut put = |what: &str, value: u64| {
+                let key = format!("net.{}.{}", iface, what);
+                if let Some(r) = state.rate(&mut counters, key.clone(), value, elapsed) {
+                    metrics.insert(key, r);
+                }
+            };
+            put("rx.bps", *rx_b);
+            put("tx.bps", *tx_b);
+            put("rx.pps", *rx_p);
+            put("tx.pps", *tx_p);
+            put("rx.errs", *rx_e);
+            put("tx.errs", *tx_e);
+        }
+        let mut oc_raised = Vec::new();
+        for (source, count) in throttle::oc_counters(&self.throttle) {
+            let key = format!("oc.{}", source);
+            if state
+                .rate(&mut counters, key, count, elapsed)
+                .is_some_and(|r| r > 0.0)
+            {
+                oc_raised.push(source);
+            }
+        }
+        state.counters = counters;
+
+        for line in &self.wireless {
+            let mut f = line.split_whitespace();
+            let Some(iface) = f.next() else {
+                continue;
+            };
+            if let Some(dbm) = f.next().and_then(|v| v.parse::<f64>().ok()) {
+                metrics.insert(format!("net.{}.signal.dbm", iface), dbm);
+            }
+            if let Some(rate) = f.next().and_then(|v| v.parse::<f64>().ok()) {
+                metrics.insert(format!("net.{}.bitrate.mbps", iface), rate);
+            }
+        }
+
+        for line in &self.volumes {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            let nums: Vec<f64> = f.iter().skip(1).filter_map(|v| v.parse().ok()).collect();
+            if let (Some(mount), [size, used, avail]) = (f.first(), nums.as_slice()) {
+                let key = mount_key(mount);
+                if *size > 0.0 {
+                    metrics.insert(format!("fs.{}.used.pct", key), used / size * 100.0);
+                }
+                metrics.insert(format!("fs.{}.avail.mb", key), avail / 1024.0);
+            }
+        }
+        for line in &self.inodes {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            let nums: Vec<f64> = f.iter().skip(1).filter_map(|v| v.parse().ok()).collect();
+            if let (Some(mount), [total, used]) = (f.first(), nums.as_slice()) {
+                if *total > 0.0 {
+                    metrics.insert(
+                        format!("fs.{}.inodes.pct", mount_key(mount)),
+                        used / total * 100.0,
+                    );
+                }
+            }
+        }
+
+        let throttling = throttle::detect(&self.throttle, tegra.as_ref(), &oc_raised);
+
+        Ok(StatPoint {
+            ts: chrono::Utc::now().timestamp_millis(),
+            cpu,
+            ram_used_mb,
+            ram_total_mb,
+            gpu_util: tegra.as_ref().and_then(|t| t.gpu_util()),
+            gpu_temp_c: tegra.as_ref().and_then(|t| t.gpu_temp_c()),
+            power_mode,
+            clocks_locked,
+            fan_rpm,
+            fan_pwm,
+            tegrastats: tegra,
+            metrics,
+            resolution_ms: None,
+            metrics_min: Default::default(),
+            metrics_max: Default::default(),
+            throttling,
+            device_id,
+        })
+    }
+}
+
+fn parse_cpu_line(line: &str) -> Option<(u64, u64)> {
+    // line starts with: cpu  user nice system idle iowait irq softirq steal guest guest_nice
+    let mut parts = line.split_whitespace();
+    let _ = parts.next(); // "cpu"
+    let nums: Vec<u64> = parts.filter_map(|p| p.parse::<u64>().ok()).collect();
+    if nums.len() < 4 {
+        return None;
+    }
+    let user = nums.first().copied().unwrap_or(0);
+    let nice = nums.get(1).copied().unwrap_or(0);
+    let system = nums.get(2).copied().unwrap_or(0);
+    let idle = nums.get(3).copied().unwrap_or(0);
+    let iowait = nums.get(4).copied().unwrap_or(0);
+    let irq = nums.get(5).copied().unwrap_or(0);
+    let softirq = nums.get(6).copied().unwrap_or(0);
+    let steal = nums.get(7).copied().unwrap_or(0);
+    let total = user + nice + system + idle + iowait + irq + softirq + steal;
+    let idle_total = idle + iowait;
+    Some((total, idle_total))
+}
+
+// Most recent sample per device, for readers that must not touch SSH or the DB
+static LATEST: Lazy<Mutex<HashMap<i64, StatPoint>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The latest sample of every device that has reported one since startup.
+pub fn latest() -> Vec<StatPoint> {
+    LATEST.lock().values().cloned().collect()
+}
+
+/// Store a sample: core columns in `device_stats` and every named series in `device_metrics`.
+/// The sample also becomes the device's cached latest.
+pub(crate) fn store_point(conn: &Connection, p: &StatPoint) -> Result<(), String> {
+    LATEST.lock().insert(p.device_id, p.clone());
+    let values = metrics::resolve(conn, &p.metrics)?;
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    insert_point(&tx, p)?;
+    metrics::store(&tx, p.device_id, p.ts, &values)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn insert_point(conn: &Connection, p: &StatPoint) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO device_stats (ts, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode, clocks_locked, fan_rpm, fan_pwm, device_id, run_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            p.ts,
+            p.cpu,
+            p.ram_used_mb,
+            p.ram_total_mb,
+            p.gpu_util,
+            p.gpu_temp_c,
+            p.power_mode,
+            p.clocks_locked,
+            p.fan_rpm,
+            p.fan_pwm,
+            p.device_id,
+            runs::active(p.device_id)
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Where a subscription's samples go.
+pub enum Delivery {
+    Channel(Channel<StatPoint>),
+    // Emitted to the frontend as "tegrastats://point"
+    Event(AppHandle),
+    // In-process consumers such as fleet streams
+    Sender(Sender<StatPoint>),
+}
+
+impl Delivery {
+    fn kind(&self) -> &'static str {
+        match self {
+            Delivery::Channel(_) => "channel",
+            Delivery::Event(_) => "event",
+            Delivery::Sender(_) => "internal",
+        }
+    }
+
+    /// False once the receiving end is gone.
+    fn deliver(&self, point: &StatPoint) -> bool {
+        match self {
+            Delivery::Channel(ch) => ch.send(point.clone()).is_ok(),
+            Delivery::Event(app) => {
+                // Emit errors happen while the frontend reloads; keep the subscription
+                let _ = app.emit(POINT_EVENT, point.clone());
+                true
+            }
+            Delivery::Sender(tx) => tx.send(point.clone()).is_ok(),
+        }
+    }
+}
+
+pub const POINT_EVENT: &str = "tegrastats://point";
+
+struct Subscriber {
+    delivery: Delivery,
+    interval_ms: u64,
+    since: i64,
+    delivered: u64,
+}
+
+// One collector per device: a single persistent channel whose samples are
+// stored once and fanned out to every subscriber. The collector runs while it
+// has at least one subscriber.
+struct Collector {
+    id: u64,
+    interval_ms: u64,
+    // By subscription key
+    subscribers: HashMap<String, Subscriber>,
+}
+
+static COLLECTORS: Lazy<Mutex<HashMap<i64, Collector>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static NEXT_COLLECTOR_ID: AtomicU64 = AtomicU64::new(1);
+// Why each device's last collector stopped
+static LAST_ERROR: Lazy<Mutex<HashMap<i64, String>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Attach a subscription to the device's collector, starting it if it is not
+/// running yet. A subscription with the same key is replaced. The collector
+/// samples at the fastest rate any of its subscribers asked for.
+pub fn subscribe(
+    key: &str,
+    device_id: i64,
+    interval_ms: u64,
+    delivery: Delivery,
+) -> Result<(), String> {
+    let sub = Subscriber {
+        delivery,
+        interval_ms,
+        since: chrono::Utc::now().timestamp_millis(),
+        delivered: 0,
+    };
+    let mut map = COLLECTORS.lock();
+    if let Some(previous) = detach(&mut map, key).filter(|&d| d != device_id) {
+        settle(&mut map, previous);
+    }
+    if let Some(c) = map.get_mut(&device_id) {
+        c.subscribers.insert(key.to_string(), sub);
+        retune(device_id, c);
+        return Ok(());
+    }
+
+    let id = NEXT_COLLECTOR_ID.fetch_add(1, Ordering::Relaxed);
+    map.insert(
+        device_id,
+        Collector {
+            id,
+            interval_ms,
+            subscribers: HashMap::from([(key.to_string(), sub)]),
+        },
+    );
+    drop(map);
+    spawn(id, device_id, interval_ms);
+    Ok(())
+}
+
+fn spawn(id: u64, device_id: i64, interval_ms: u64) {
+    thread::spawn(move || {
+        info!(
+            "stats collector started for device {} at {} ms",
+            device_id, interval_ms
+        );
+        if let Err(e) = run(id, device_id, interval_ms) {
+            warn!("stats collector for device {} stopped: {}", device_id, e);
+            LAST_ERROR.lock().insert(device_id, e);
+        }
+        let mut map = COLLECTORS.lock();
+        // A collector replaced by retune() leaves the device to its successor
+        let replaced = map.get(&device_id).is_some_and(|c| c.id != id);
+        if map.get(&device_id).map(|c| c.id) == Some(id) {
+            map.remove(&device_id);
+        }
+        drop(map);
+        if !replaced {
+            if let Ok(conn) = db_conn() {
+                throttle::close_device(&conn, device_id);
+            }
+        }
+    });
+}
+
+/// Restart the collector when its subscribers now want a different rate. The
+/// old thread notices the new id on its next sample and exits without storing it.
+fn retune(device_id: i64, c: &mut Collector) {
+    let Some(wanted) = c.subscribers.values().map(|s| s.interval_ms).min() else {
+        return;
+    };
+    if wanted == c.interval_ms {
+        return;
+    }
+    c.id = NEXT_COLLECTOR_ID.fetch_add(1, Ordering::Relaxed);
+    c.interval_ms = wanted;
+    spawn(c.id, device_id, wanted);
+}
+
+fn detach(map: &mut HashMap<i64, Collector>, key: &str) -> Option<i64> {
+    let (&device_id, c) = map
+        .iter_mut()
+        .find(|(_, c)| c.subscribers.contains_key(key))?;
+    c.subscribers.remove(key);
+    Some(device_id)
+}
+
+/// Detach a subscription; its collector stops with the last one. Returns
+/// false when there was no such subscription.
+pub fn unsubscribe(key: &str) -> bool {
+    let mut map = COLLECTORS.lock();
+    let Some(device_id) = detach(&mut map, key) else {
+        return false;
+    };
+    settle(&mut map, device_id);
+    true
+}
+
+/// Stop a collector left without subscribers, or retune it to the remaining ones.
+fn settle(map: &mut HashMap<i64, Collector>, device_id: i64) {
+    let Some(c) = map.get_mut(&device_id) else {
+        return;
+    };
+    if c.subscribers.is_empty() {
+        map.remove(&device_id);
+    } else {
+        retune(device_id, c);
+    }
+}
+
+/// Every active subscription, by device.
+pub fn subscriptions() -> Vec<Subscription> {
+    let map = COLLECTORS.lock();
+    let mut out: Vec<Subscription> = map
+        .iter()
+        .flat_map(|(device_id, c)| {
+            c.subscribers.iter().map(move |(key, sub)| Subscription {
+                key: key.clone(),
+                device_id: *device_id,
+                kind: sub.delivery.kind().to_string(),
+                interval_ms: sub.interval_ms,
+                sampling_interval_ms: c.interval_ms,
+                since: sub.since,
+                delivered: sub.delivered,
+            })
+        })
+        .collect();
+    out.sort_by(|a, b| (a.device_id, &a.key).cmp(&(b.device_id, &b.key)));
+    out
+}
+
+pub fn has_subscription(key: &str) -> bool {
+    COLLECTORS
+        .lock()
+        .values()
+        .any(|c| c.subscribers.contains_key(key))
+}
+
+/// Error that stopped the device's last collector, if it failed.
+pub fn last_error(device_id: i64) -> Option<String> {
+    LAST_ERROR.lock().get(&device_id).cloned()
+}
+
+pub fn is_running(device_id: i64) -> bool {
+    COLLECTORS.lock().contains_key(&device_id)
+}
+
+/// Stop the device's collector and drop its subscriptions.
+pub fn stop(device_id: i64) {
+    COLLECTORS.lock().remove(&device_id);
+}
+
+/// Whether `id` is still the device's collector, i.e. not replaced by retune() or stopped.
+fn is_current(id: u64, device_id: i64) -> bool {
+    COLLECTORS
+        .lock()
+        .get(&device_id)
+        .is_some_and(|c| c.id == id)
+}
+
+/// Deliver a point to the collector's subscribers, dropping the ones that went
+/// away. Returns false when the collector should exit.
+fn fan_out(id: u64, device_id: i64, point: &StatPoint) -> bool {
+    let mut map = COLLECTORS.lock();
+    let Some(c) = map.get_mut(&device_id).filter(|c| c.id == id) else {
+        return false;
+    };
+    LAST_ERROR.lock().remove(&device_id);
+    c.subscribers.retain(|_, sub| {
+        sub.delivered += 1;
+        sub.delivery.deliver(point)
+    });
+    if c.subscribers.is_empty() {
+        map.remove(&device_id);
+        return false;
+    }
+    true
+}
+
+fn run(id: u64, device_id: i64, interval_ms: u64) -> Result<(), String> {
+    let sess = open_dedicated_session(device_id)?;
+    // A device that stops emitting for several intervals is treated as gone
+    sess.set_timeout((interval_ms.saturating_mul(5) as u32).max(10_000));
+    let mut channel = sess.channel_session().map_err(|e| e.to_string())?;
+    channel
+        .exec(&stream_script(interval_ms))
+        .map_err(|e| e.to_string())?;
+
+    let conn = db_conn()?;
+    let mut reader = BufReader::new(channel.stream(0));
+    let mut raw = RawSample::default();
+    let mut state = SampleState::default();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line).map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Err("stats stream closed by device".into());
+        }
+        if !raw.push_line(line.trim_end()) {
+            continue;
+        }
+        let sample = std::mem::take(&mut raw);
+        let point = match sample.to_point(&mut state, device_id) {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("dropping sample for device {}: {}", device_id, e);
+                continue;
+            }
+        };
+        // A retuned or stopped collector leaves the sample to its successor
+        if !is_current(id, device_id) {
+            break;
+        }
+        if let Err(e) = store_point(&conn, &point) {
+            warn!("failed to store sample for device {}: {}", device_id, e);
+        }
+        throttle::track(&conn, &point);
+        alerts::evaluate(&conn, &point);
+        sinks::enqueue(&conn, &point);
+        if !fan_out(id, device_id, &point) {
+            break;
+        }
+    }
+
+    drop(reader);
+    let _ = channel.close();
+    Ok(())
+}
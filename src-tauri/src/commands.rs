@@ -1,11 +1,19 @@
+pub mod alerts;
 pub mod connection;
 pub mod credentials;
 pub mod devices;
 pub mod docker;
+pub mod exec;
 pub mod files;
+pub mod fleet;
 pub mod packages;
+pub mod processes;
+pub mod prometheus;
+pub mod runs;
+pub mod sinks;
 pub mod stats;
 pub mod system;
+pub mod trt;
 pub mod wifi;
 
 #[tauri::command]
@@ -0,0 +1,146 @@
+use crate::alerts;
+use crate::anomaly;
+use crate::db::db_conn;
+use crate::types::{AlertEvent, AlertRule, AnomalyEvent};
+use rusqlite::params;
+
+/// Rules for a device, including global ones, or every rule when no device is given.
+#[tauri::command]
+pub fn list_alert_rules(device_id: Option<i64>) -> Result<Vec<AlertRule>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM alert_rule WHERE ?1 IS NULL OR device_id IS NULL OR device_id = ?1 ORDER BY id",
+            alerts::RULE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([device_id], alerts::rule_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Create a rule (no id) or replace an existing one. Alerts open under the old
+/// definition are resolved so they are re-evaluated against the new one.
+#[tauri::command]
+pub fn save_alert_rule(rule: AlertRule) -> Result<AlertRule, String> {
+    alerts::validate(&rule)?;
+    let mut rule = rule;
+    if rule.severity.is_empty() {
+        rule.severity = "warning".to_string();
+    }
+    let conn = db_conn()?;
+    match rule.id {
+        None => {
+            conn.execute(
+                "INSERT INTO alert_rule (device_id, name, metric, comparator, threshold, duration_sec, severity, notify, enabled, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    rule.device_id,
+                    rule.name,
+                    rule.metric,
+                    rule.comparator,
+                    rule.threshold,
+                    rule.duration_sec,
+                    rule.severity,
+                    rule.notify,
+                    rule.enabled,
+                    chrono::Utc::now().timestamp_millis()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            rule.id = Some(conn.last_insert_rowid());
+        }
+        Some(id) => {
+            let n = conn
+                .execute(
+                    "UPDATE alert_rule SET device_id = ?1, name = ?2, metric = ?3, comparator = ?4, threshold = ?5, duration_sec = ?6, severity = ?7, notify = ?8, enabled = ?9 WHERE id = ?10",
+                    params![
+                        rule.device_id,
+                        rule.name,
+                        rule.metric,
+                        rule.comparator,
+                        rule.threshold,
+                        rule.duration_sec,
+                        rule.severity,
+                        rule.notify,
+                        rule.enabled,
+                        id
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err("alert rule not found".to_string());
+            }
+            alerts::close_rule(&conn, id)?;
+        }
+    }
+    alerts::invalidate_rules();
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn delete_alert_rule(id: i64) -> Result<(), String> {
+    let conn = db_conn()?;
+    alerts::close_rule(&conn, id)?;
+    conn.execute("DELETE FROM alert_rule WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    alerts::invalidate_rules();
+    Ok(())
+}
+
+/// Alert history, newest first.
+#[tauri::command]
+pub fn list_alert_events(
+    device_id: Option<i64>,
+    open_only: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Vec<AlertEvent>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM alert_event WHERE (?1 IS NULL OR device_id = ?1) AND (?2 = 0 OR resolved_at IS NULL) ORDER BY opened_at DESC LIMIT ?3",
+            alerts::EVENT_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![device_id, open_only.unwrap_or(false), limit.unwrap_or(200)],
+            alerts::event_from_row,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Run anomaly detection now instead of waiting for the background run.
+/// Returns the anomalies it newly found.
+#[tauri::command]
+pub fn detect_anomalies(device_id: Option<i64>) -> Result<Vec<AnomalyEvent>, String> {
+    let conn = db_conn()?;
+    anomaly::detect(&conn, device_id)
+}
+
+/// Anomaly history, newest first.
+#[tauri::command]
+pub fn list_anomalies(
+    device_id: Option<i64>,
+    open_only: Option<bool>,
+    limit: Option<i64>,
+) -> Result<Vec<AnomalyEvent>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM anomaly_event WHERE (?1 IS NULL OR device_id = ?1) AND (?2 = 0 OR resolved_at IS NULL) ORDER BY detected_at DESC LIMIT ?3",
+            anomaly::EVENT_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![device_id, open_only.unwrap_or(false), limit.unwrap_or(200)],
+            anomaly::event_from_row,
+        )
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
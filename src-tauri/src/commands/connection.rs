@@ -30,7 +30,26 @@ pub fn connect_device(device_id: i64) -> Result<String, String> {
         return Ok(device_id.to_string());
     }
 
-    // Fetch credential for device
+    let val = load_credential(device_id)?;
+
+    // Attempt connection
+    let result = connect(device_id.to_string(), val);
+
+    // Update last_connected_at timestamp on successful connection
+    if result.is_ok() {
+        let now = chrono::Utc::now().timestamp_millis();
+        let conn = db_conn()?;
+        let _ = conn.execute(
+            "UPDATE device SET last_connected_at = ?1 WHERE id = ?2",
+            rusqlite::params![now, device_id],
+        );
+    }
+
+    result
+}
+
+/// Fetch the stored SSH credential for a device.
+fn load_credential(device_id: i64) -> Result<SshConfig, String> {
     let conn = db_conn()?;
     let mut stmt = conn
         .prepare(
@@ -47,33 +66,26 @@ pub fn connect_device(device_id: i64) -> Result<String, String> {
         let password: Option<String> = row.get(4).map_err(|e| e.to_string())?;
         let private_key_path: Option<String> = row.get(5).map_err(|e| e.to_string())?;
 
-        let val: SshConfig = SshConfig {
+        return Ok(SshConfig {
             host,
             port: (port as u16),
             username,
             auth_type,
             private_key_path,
             password,
-        };
-
-        // Attempt connection
-        let result = connect(device_id.to_string(), val);
-
-        // Update last_connected_at timestamp on successful connection
-        if result.is_ok() {
-            let now = chrono::Utc::now().timestamp_millis();
-            let _ = conn.execute(
-                "UPDATE device SET last_connected_at = ?1 WHERE id = ?2",
-                rusqlite::params![now, device_id],
-            );
-        }
-
-        return result;
+        });
     }
 
     Err("no credential for device".into())
 }
 
+/// Open an SSH session for the device that is separate from the shared one in
+/// SESSIONS. Long-running readers use it so they never hold the shared session busy.
+pub(crate) fn open_dedicated_session(device_id: i64) -> Result<SshSession, String> {
+    let cfg = load_credential(device_id)?;
+    create_authenticated_session(&cfg)
+}
+
 #[tauri::command]
 pub fn disconnect_device(device_id: i64) -> Result<String, String> {
     info!("disconnect_device requested for device_id={}", device_id);
@@ -82,6 +94,7 @@ pub fn disconnect_device(device_id: i64) -> Result<String, String> {
 
     // Stop any active stats streams for this device
     let _ = crate::commands::stats::stop_stats_stream(&device_id_str);
+    crate::collector::stop(device_id);
 
     let mut map = SESSIONS.lock();
     if map.remove(device_id_str.as_str()).is_some() {
@@ -43,9 +43,11 @@ pub fn add_device(
 
 #[tauri::command]
 pub fn remove_device(device_id: i64) -> Result<(), String> {
-    // Remove any active session
+    // Remove any active session and stop its stats collection
     let mut map = SESSIONS.lock();
     map.remove(device_id.to_string().as_str());
+    drop(map);
+    crate::collector::stop(device_id);
 
     // Remove device and its associated credentials
     let conn = db_conn()?;
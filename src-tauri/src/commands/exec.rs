@@ -0,0 +1,89 @@
+use crate::remote::{self, ExecOptions, ExecOutput};
+use crate::types::{CommandOutput, CommandResult};
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+use tauri::ipc::Channel;
+
+impl From<ExecOutput> for CommandResult {
+    fn from(out: ExecOutput) -> Self {
+        CommandResult {
+            stdout: out.stdout,
+            stderr: out.stderr,
+            exit_status: out.exit_status,
+            duration_ms: out.duration_ms,
+            timed_out: out.timed_out,
+        }
+    }
+}
+
+fn exec_options(
+    command: &str,
+    timeout_ms: Option<u64>,
+    env: Option<BTreeMap<String, String>>,
+    stdin: Option<String>,
+) -> Result<ExecOptions, String> {
+    if command.trim().is_empty() {
+        return Err("command is empty".to_string());
+    }
+    Ok(ExecOptions {
+        timeout: timeout_ms.map(Duration::from_millis),
+        env: env.unwrap_or_default().into_iter().collect(),
+        stdin: stdin.map(String::into_bytes),
+    })
+}
+
+/// Run an ad-hoc command on the device. A non-zero exit is reported in the
+/// result rather than as an error.
+#[tauri::command]
+pub fn run_command(
+    device_id: i64,
+    command: String,
+    timeout_ms: Option<u64>,
+    env: Option<BTreeMap<String, String>>,
+    stdin: Option<String>,
+) -> Result<CommandResult, String> {
+    let opts = exec_options(&command, timeout_ms, env, stdin)?;
+    Ok(remote::exec_with(device_id, &command, &opts)?.into())
+}
+
+/// `run_command` in the background, streaming stdout and stderr lines over
+/// `on_output` as they arrive and then the result. Returns once the command has
+/// started; it runs over its own SSH session so long-running commands don't
+/// hold up the shared one.
+#[tauri::command]
+pub fn run_command_streaming(
+    device_id: i64,
+    command: String,
+    timeout_ms: Option<u64>,
+    env: Option<BTreeMap<String, String>>,
+    stdin: Option<String>,
+    on_output: Channel<CommandOutput>,
+) -> Result<(), String> {
+    let opts = exec_options(&command, timeout_ms, env, stdin)?;
+    remote::session_handle(device_id)?;
+
+    thread::spawn(move || {
+        let out = remote::exec_streaming(device_id, &command, &opts, |stream, line| {
+            let _ = on_output.send(CommandOutput {
+                ts: chrono::Utc::now().timestamp_millis(),
+                stream: Some(stream.as_str().to_string()),
+                line: Some(line.to_string()),
+                result: None,
+                error: None,
+            });
+        });
+        let (result, error) = match out {
+            Ok(out) => (Some(out.into()), None),
+            Err(e) => (None, Some(e)),
+        };
+        let _ = on_output.send(CommandOutput {
+            ts: chrono::Utc::now().timestamp_millis(),
+            stream: None,
+            line: None,
+            result,
+            error,
+        });
+    });
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use crate::collector::{self, Delivery};
+use crate::remote;
+use crate::types::{FleetDevice, FleetPoint, FleetUpdate, StatPoint};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+
+// A device without a sample for this many ticks is reported offline
+const STALE_TICKS: i64 = 3;
+// Grace period for the first sample while the collector connects
+const CONNECT_GRACE_MS: i64 = 15_000;
+// How often the collector of an offline device is restarted, while it stays connected
+const RETRY_MS: i64 = 10_000;
+
+static FLEET_FLAGS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static FLEET_HANDLES: Lazy<Mutex<HashMap<String, thread::JoinHandle<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct Member {
+    key: String,
+    subscribed_at: i64,
+    point: Option<FleetPoint>,
+}
+
+fn fleet_key(token: &str, device_id: i64) -> String {
+    format!("fleet:{}:{}", token, device_id)
+}
+
+fn compact(p: &StatPoint) -> FleetPoint {
+    let mut throttling: Vec<String> = p.throttling.iter().map(|c| c.cause.clone()).collect();
+    throttling.dedup();
+    FleetPoint {
+        ts: p.ts,
+        cpu: p.cpu,
+        ram_used_mb: p.ram_used_mb,
+        ram_total_mb: p.ram_total_mb,
+        gpu_util: p.gpu_util,
+        gpu_temp_c: p.gpu_temp_c,
+        fan_rpm: p.fan_rpm,
+        power_mode: p.power_mode.clone(),
+        throttling,
+    }
+}
+
+/// Stream several devices over one channel: one message per tick holding the
+/// latest point of every device. Each device attaches to its shared collector,
+/// so fleet views and per-device streams don't sample twice. Devices whose
+/// collector fails or goes quiet are reported as offline, and their collector
+/// restarted in the background for as long as the device stays connected.
+/// Disconnected devices are left alone and reported offline.
+#[tauri::command]
+pub fn stream_fleet(
+    token: String,
+    device_ids: Vec<i64>,
+    interval_ms: Option<u64>,
+    on_update: Channel<FleetUpdate>,
+) -> Result<(), String> {
+    let interval = interval_ms.unwrap_or(1000).max(250);
+    stop_fleet_stream(token.clone())?;
+
+    let (tx, rx) = mpsc::channel::<StatPoint>();
+    let mut members: HashMap<i64, Member> = HashMap::new();
+    let now = chrono::Utc::now().timestamp_millis();
+    for id in device_ids {
+        let key = fleet_key(&token, id);
+        if remote::session_handle(id).is_ok() {
+            collector::subscribe(&key, id, interval, Delivery::Sender(tx.clone()))?;
+        }
+        members.insert(
+            id,
+            Member {
+                key,
+                subscribed_at: now,
+                point: None,
+            },
+        );
+    }
+
+    let flag = Arc::new(AtomicBool::new(true));
+    FLEET_FLAGS
+        .lock()
+        .unwrap()
+        .insert(token.clone(), flag.clone());
+
+    let handle = thread::spawn(move || {
+        let tick = Duration::from_millis(interval);
+        let stale_ms = (interval as i64 * STALE_TICKS).max(5000);
+        let mut next_tick = Instant::now() + tick;
+        while flag.load(Ordering::Relaxed) {
+            // Keep only the newest point per device until the tick is due
+            match rx.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+                Ok(point) => {
+                    if let Some(m) = members.get_mut(&point.device_id) {
+                        m.point = Some(compact(&point));
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+            next_tick += tick;
+
+            let now = chrono::Utc::now().timestamp_millis();
+            let mut devices: Vec<FleetDevice> = Vec::with_capacity(members.len());
+            for (&device_id, m) in members.iter_mut() {
+                let attached = collector::has_subscription(&m.key);
+                // The collector opens its own session, so resubscribing would
+                // reconnect a device the user disconnected
+                let connected = remote::session_handle(device_id).is_ok();
+                let last_seen = m.point.as_ref().map(|p| p.ts);
+                let fresh = last_seen.is_some_and(|ts| now - ts <= stale_ms);
+                let status = if attached && fresh {
+                    "online"
+                } else if attached
+                    && last_seen.is_none()
+                    && now - m.subscribed_at < CONNECT_GRACE_MS
+                {
+                    "pending"
+                } else {
+                    "offline"
+                };
+                let error = if attached {
+                    None
+                } else if !connected {
+                    Some("not connected".to_string())
+                } else {
+                    collector::last_error(device_id)
+                };
+                if !attached && connected && now - m.subscribed_at >= RETRY_MS {
+                    m.subscribed_at = now;
+                    let _ = collector::subscribe(
+                        &m.key,
+                        device_id,
+                        interval,
+                        Delivery::Sender(tx.clone()),
+                    );
+                }
+                devices.push(FleetDevice {
+                    device_id,
+                    status: status.to_string(),
+                    point: m.point.clone(),
+                    last_seen,
+                    error,
+                });
+            }
+            devices.sort_by_key(|d| d.device_id);
+            // If send fails, client disconnected - stop streaming
+            if on_update.send(FleetUpdate { ts: now, devices }).is_err() {
+                break;
+            }
+        }
+        for m in members.values() {
+            collector::unsubscribe(&m.key);
+        }
+    });
+    FLEET_HANDLES.lock().unwrap().insert(token, handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_fleet_stream(token: String) -> Result<(), String> {
+    if let Some(flag) = FLEET_FLAGS.lock().unwrap().remove(&token) {
+        flag.store(false, Ordering::Relaxed);
+    }
+    if let Some(handle) = FLEET_HANDLES.lock().unwrap().remove(&token) {
+        let _ = handle.join();
+    }
+    Ok(())
+}
@@ -1,14 +1,446 @@
+use crate::remote::{self, shell_quote};
+use crate::types::{OfflineInstallReport, PackageInfo, VenvInfo};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+// Virtualenvs managed by orion live under this directory on the device
+const VENV_ROOT: &str = "$HOME/.orion/venvs";
+
+fn validate_venv_name(name: &str) -> Result<(), String> {
+    let ok = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("invalid virtualenv name: {}", name))
+    }
+}
+
+/// Interpreter used to drive pip: system python3 or the venv's own python.
+fn pip_python(venv: Option<&str>) -> Result<String, String> {
+    match venv {
+        Some(name) => {
+            validate_venv_name(name)?;
+            Ok(format!("\"{}/{}/bin/python\"", VENV_ROOT, name))
+        }
+        None => Ok("python3".to_string()),
+    }
+}
+
+/// Build a pip invocation. System-wide changes go through sudo when it is
+/// available without a password, otherwise they fall back to `--user`.
+fn pip_cmd(venv: Option<&str>, args: &str, user_fallback: bool) -> Result<String, String> {
+    let python = pip_python(venv)?;
+    if venv.is_some() {
+        return Ok(format!("{} -m pip {} 2>&1", python, args));
+    }
+    let user = if user_fallback { " --user" } else { "" };
+    Ok(format!(
+        "if sudo -n true 2>/dev/null; then sudo -n {py} -m pip {args}; else {py} -m pip {args}{user}; fi 2>&1",
+        py = python,
+        args = args,
+        user = user
+    ))
+}
+
+fn pip_list(
+    device_id: i64,
+    query: Option<&str>,
+    venv: Option<&str>,
+) -> Result<Vec<PackageInfo>, String> {
+    let cmd = format!(
+        "{} -m pip list --format=json --disable-pip-version-check",
+        pip_python(venv)?
+    );
+    let out = remote::exec(device_id, &cmd)?.into_result()?;
+    let items: Vec<serde_json::Value> =
+        serde_json::from_str(out.trim()).map_err(|e| e.to_string())?;
+    let needle = query.map(|q| q.to_lowercase());
+    Ok(items
+        .iter()
+        .filter_map(|item| {
+            let name = item.get("name")?.as_str()?.to_string();
+            let version = item.get("version")?.as_str()?.to_string();
+            Some(PackageInfo {
+                name,
+                version,
+                kind: "pip".into(),
+            })
+        })
+        .filter(|p| match &needle {
+            Some(q) => p.name.to_lowercase().contains(q),
+            None => true,
+        })
+        .collect())
+}
+
+fn pip_install(device_id: i64, pkg: &str, venv: Option<&str>) -> Result<String, String> {
+    let specs = pkg
+        .split_whitespace()
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if specs.is_empty() {
+        return Err("no package given".into());
+    }
+    let args = format!("install --disable-pip-version-check {}", specs);
+    remote::exec(device_id, &pip_cmd(venv, &args, true)?)?.into_result()
+}
+
+fn pip_remove(device_id: i64, pkg: &str, venv: Option<&str>) -> Result<String, String> {
+    let specs = pkg
+        .split_whitespace()
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if specs.is_empty() {
+        return Err("no package given".into());
+    }
+    let args = format!("uninstall -y {}", specs);
+    remote::exec(device_id, &pip_cmd(venv, &args, false)?)?.into_result()
+}
+
+#[tauri::command]
+pub fn packages_list(
+    device_id: i64,
+    kind: &str,
+    query: Option<&str>,
+    venv: Option<&str>,
+) -> Result<Vec<PackageInfo>, String> {
+    match kind {
+        "pip" => pip_list(device_id, query, venv),
+        other => Err(format!("unsupported package kind: {}", other)),
+    }
+}
+
+#[tauri::command]
+pub fn packages_install(
+    device_id: i64,
+    kind: &str,
+    pkg: &str,
+    venv: Option<&str>,
+) -> Result<String, String> {
+    match kind {
+        "pip" => pip_install(device_id, pkg, venv),
+        other => Err(format!("unsupported package kind: {}", other)),
+    }
+}
+
+#[tauri::command]
+pub fn packages_remove(
+    device_id: i64,
+    kind: &str,
+    pkg: &str,
+    venv: Option<&str>,
+) -> Result<String, String> {
+    match kind {
+        "pip" => pip_remove(device_id, pkg, venv),
+        other => Err(format!("unsupported package kind: {}", other)),
+    }
+}
+
+/// Expand the given local files and folders into the list of files with `ext`.
+fn collect_local_files(paths: &[String], ext: &str) -> Result<Vec<PathBuf>, String> {
+    let has_ext = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.eq_ignore_ascii_case(ext))
+            .unwrap_or(false)
+    };
+    let mut files = Vec::new();
+    for p in paths {
+        let path = PathBuf::from(p);
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(&path)
+                .map_err(|e| format!("failed to read {}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && has_ext(p))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else if path.is_file() && has_ext(&path) {
+            files.push(path);
+        } else {
+            return Err(format!("not a .{} file or folder: {}", ext, p));
+        }
+    }
+    if files.is_empty() {
+        return Err(format!("no .{} files found", ext));
+    }
+    Ok(files)
+}
+
+/// Upload files into a fresh staging directory on the device and return its path
+/// together with the uploaded file names.
+fn stage_files(device_id: i64, files: &[PathBuf]) -> Result<(String, Vec<String>), String> {
+    let dir = format!("/tmp/orion-offline-{}", uuid::Uuid::new_v4());
+    remote::exec(device_id, &format!("mkdir -p {}", shell_quote(&dir)))?.into_result()?;
+    let upload_all = || -> Result<Vec<String>, String> {
+        let mut names = Vec::new();
+        for f in files {
+            let name = f
+                .file_name()
+                .and_then(|n| n.to_str())
+                .ok_or_else(|| format!("invalid file name: {}", f.display()))?
+                .to_string();
+            remote::upload(device_id, f, &format!("{}/{}", dir, name))?;
+            names.push(name);
+        }
+        Ok(names)
+    };
+    match upload_all() {
+        Ok(names) => Ok((dir, names)),
+        Err(e) => {
+            // Don't leave a partial upload behind
+            let _ = remote::exec(device_id, &format!("rm -rf {}", shell_quote(&dir)));
+            Err(e)
+        }
+    }
+}
+
+/// Package name of a single Depends alternative, e.g. `libfoo1:any (>= 1.2)` -> `libfoo1`.
+fn deb_dep_name(alt: &str) -> &str {
+    let name = alt.trim().split([' ', '(']).next().unwrap_or("");
+    name.split(':').next().unwrap_or(name)
+}
+
+/// Dependencies of the uploaded .debs not satisfied by the uploaded set or by
+/// what is already installed on the device. Version constraints are not checked.
+fn deb_missing_dependencies(device_id: i64, dir: &str) -> Result<Vec<String>, String> {
+    let cmd = format!(
+        r#"for f in {dir}/*.deb; do dpkg-deb -f "$f" Package Provides Depends Pre-Depends; echo "--"; done
+        echo "=="
+        dpkg-query -W -f='${{db:Status-Abbrev}}|${{Package}}|${{Provides}}\n' 2>/dev/null"#,
+        dir = shell_quote(dir)
+    );
+    let out = remote::exec(device_id, &cmd)?.into_result()?;
+    let (uploaded, installed) = out.split_once("\n==").unwrap_or((out.as_str(), ""));
+
+    let mut available: HashSet<String> = HashSet::new();
+    for line in installed.lines() {
+        let mut parts = line.split('|');
+        if !parts.next().unwrap_or("").starts_with("ii") {
+            continue;
+        }
+        available.insert(parts.next().unwrap_or("").trim().to_string());
+        for p in parts.next().unwrap_or("").split(',') {
+            available.insert(deb_dep_name(p).to_string());
+        }
+    }
+
+    let mut deps: Vec<String> = Vec::new();
+    let mut key = "";
+    for line in uploaded.lines() {
+        // Long fields may be folded onto continuation lines starting with whitespace
+        let value = if line.starts_with([' ', '\t']) {
+            line
+        } else if let Some((k, v)) = line.split_once(':') {
+            key = k.trim();
+            v
+        } else {
+            key = "";
+            continue;
+        };
+        match key {
+            "Package" => {
+                available.insert(value.trim().to_string());
+            }
+            "Provides" => {
+                for p in value.split(',') {
+                    available.insert(deb_dep_name(p).to_string());
+                }
+            }
+            "Depends" | "Pre-Depends" => {
+                deps.extend(value.split(',').map(|d| d.trim().to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    let mut missing: Vec<String> = deps
+        .into_iter()
+        .filter(|group| !group.is_empty())
+        .filter(|group| {
+            !group
+                .split('|')
+                .any(|alt| available.contains(deb_dep_name(alt)))
+        })
+        .collect();
+    missing.sort();
+    missing.dedup();
+    Ok(missing)
+}
+
+fn deb_install_offline(device_id: i64, dir: &str) -> Result<(bool, Vec<String>, String), String> {
+    let missing = deb_missing_dependencies(device_id, dir)?;
+    if !missing.is_empty() {
+        return Ok((false, missing, String::new()));
+    }
+    let cmd = format!("sudo -n dpkg -i {}/*.deb 2>&1", shell_quote(dir));
+    let out = remote::exec(device_id, &cmd)?;
+    Ok((out.exit_status == 0, missing, out.stdout))
+}
+
+fn pip_install_offline(
+    device_id: i64,
+    dir: &str,
+    names: &[String],
+    venv: Option<&str>,
+) -> Result<(bool, Vec<String>, String), String> {
+    let wheels = names
+        .iter()
+        .map(|n| shell_quote(&format!("{}/{}", dir, n)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let args = format!(
+        "install --disable-pip-version-check --no-index --find-links {} {}",
+        shell_quote(dir),
+        wheels
+    );
+    let out = remote::exec(device_id, &pip_cmd(venv, &args, true)?)?;
+
+    // pip reports unresolved requirements as "No matching distribution found for X"
+    let mut missing: Vec<String> = out
+        .stdout
+        .lines()
+        .filter_map(|l| l.split_once("No matching distribution found for "))
+        .map(|(_, req)| req.trim().to_string())
+        .collect();
+    missing.sort();
+    missing.dedup();
+    Ok((out.exit_status == 0, missing, out.stdout))
+}
+
+/// Install local .deb files or Python wheels (or folders of them) on a device
+/// without network access. Dependencies are resolved among the uploaded set;
+/// anything still unresolved is reported in `missingDependencies`.
+#[tauri::command]
+pub fn packages_install_offline(
+    device_id: i64,
+    kind: &str,
+    paths: Vec<String>,
+    venv: Option<&str>,
+) -> Result<OfflineInstallReport, String> {
+    let ext = match kind {
+        "deb" => "deb",
+        "pip" => "whl",
+        other => return Err(format!("unsupported package kind: {}", other)),
+    };
+    let files = collect_local_files(&paths, ext)?;
+    let (dir, uploaded) = stage_files(device_id, &files)?;
+
+    let result = match kind {
+        "deb" => deb_install_offline(device_id, &dir),
+        _ => pip_install_offline(device_id, &dir, &uploaded, venv),
+    };
+    let _ = remote::exec(device_id, &format!("rm -rf {}", shell_quote(&dir)));
+    let (installed, missing_dependencies, output) = result?;
+
+    Ok(OfflineInstallReport {
+        kind: kind.to_string(),
+        uploaded,
+        installed,
+        missing_dependencies,
+        output,
+    })
+}
+
+/// Upload a local requirements file and `pip install -r` it on the device.
 #[tauri::command]
-pub fn packages_list(_kind: &str, _query: Option<&str>) -> String {
-    "".into()
+pub fn pip_install_requirements(
+    device_id: i64,
+    local_path: &str,
+    venv: Option<&str>,
+) -> Result<String, String> {
+    let remote_path = format!("/tmp/orion-requirements-{}.txt", uuid::Uuid::new_v4());
+    remote::upload(device_id, Path::new(local_path), &remote_path)?;
+
+    let args = format!(
+        "install --disable-pip-version-check -r {}",
+        shell_quote(&remote_path)
+    );
+    let result = remote::exec(device_id, &pip_cmd(venv, &args, true)?);
+    let _ = remote::exec(device_id, &format!("rm -f {}", shell_quote(&remote_path)));
+    result?.into_result()
+}
+
+/// Create a named virtualenv. `system_site_packages` exposes the JetPack-provided
+/// torch/TensorRT bindings installed in the system interpreter.
+#[tauri::command]
+pub fn pip_venv_create(
+    device_id: i64,
+    name: &str,
+    system_site_packages: Option<bool>,
+) -> Result<VenvInfo, String> {
+    validate_venv_name(name)?;
+    let flag = if system_site_packages.unwrap_or(false) {
+        " --system-site-packages"
+    } else {
+        ""
+    };
+    let cmd = format!(
+        "mkdir -p \"{root}\" || exit 1; if [ -e \"{root}/{name}\" ]; then echo \"virtualenv {name} already exists\" >&2; exit 1; fi; python3 -m venv{flag} \"{root}/{name}\"",
+        root = VENV_ROOT,
+        name = name,
+        flag = flag
+    );
+    remote::exec(device_id, &cmd)?.into_result()?;
+
+    pip_venv_list(device_id)?
+        .into_iter()
+        .find(|v| v.name == name)
+        .ok_or_else(|| format!("virtualenv {} not found after creation", name))
 }
 
 #[tauri::command]
-pub fn packages_install(_kind: &str, _pkg: &str) -> String {
-    "ok".into()
+pub fn pip_venv_delete(device_id: i64, name: &str) -> Result<(), String> {
+    validate_venv_name(name)?;
+    let cmd = format!(
+        "test -f \"{root}/{name}/pyvenv.cfg\" && rm -rf \"{root}/{name}\" || {{ echo \"virtualenv {name} not found\" >&2; exit 1; }}",
+        root = VENV_ROOT,
+        name = name
+    );
+    remote::exec(device_id, &cmd)?.into_result()?;
+    Ok(())
 }
 
 #[tauri::command]
-pub fn packages_remove(_kind: &str, _pkg: &str) -> String {
-    "ok".into()
+pub fn pip_venv_list(device_id: i64) -> Result<Vec<VenvInfo>, String> {
+    // One line per venv: name|path|python version|include-system-site-packages
+    let cmd = format!(
+        r#"for d in "{root}"/*/; do
+            [ -f "$d/pyvenv.cfg" ] || continue
+            d=${{d%/}}
+            ver=$("$d/bin/python" --version 2>&1 | awk '{{print $2}}')
+            ssp=$(awk -F= '/include-system-site-packages/ {{gsub(/ /, "", $2); print $2}}' "$d/pyvenv.cfg")
+            echo "$(basename "$d")|$d|$ver|$ssp"
+        done"#,
+        root = VENV_ROOT
+    );
+    let out = remote::exec(device_id, &cmd)?.into_result()?;
+
+    let venvs = out
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split('|');
+            let name = parts.next()?.to_string();
+            let path = parts.next()?.to_string();
+            let version = parts.next().unwrap_or("").trim();
+            let ssp = parts.next().unwrap_or("").trim();
+            Some(VenvInfo {
+                name,
+                path,
+                python_version: if version.is_empty() {
+                    None
+                } else {
+                    Some(version.to_string())
+                },
+                system_site_packages: ssp.eq_ignore_ascii_case("true"),
+            })
+        })
+        .collect();
+    Ok(venvs)
 }
@@ -0,0 +1,391 @@
+use crate::remote;
+use crate::types::ProcessInfo;
+use log::warn;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+
+// One snapshot of the process table. Tagged lines:
+//   H <clock ticks/s> <page size> <uptime s> <MemTotal kB>
+//   P <raw /proc/<pid>/stat>        (per process)
+// A process that exits before its stat is read is skipped, and the trailing
+// `true` keeps that from becoming the exit status.
+const SNAPSHOT_SH: &str = "echo H $(getconf CLK_TCK) $(getconf PAGESIZE) $(cut -d' ' -f1 /proc/uptime) $(awk '/^MemTotal:/ {print $2}' /proc/meminfo); \
+for p in /proc/[0-9]*; do { read -r s < $p/stat; } 2>/dev/null && echo \"P $s\"; done; true";
+
+// Owner and command line from ps, nvmap GPU allocations where the kernel exposes them:
+//   A <pid> <user> <args>
+//   G <client> <process> <pid> <size>K
+const DETAILS_SH: &str = "ps -eo pid=,user:32=,args= | sed 's/^/A /'; \
+{ cat /sys/kernel/debug/nvmap/iovmm/clients 2>/dev/null || sudo -n cat /sys/kernel/debug/nvmap/iovmm/clients 2>/dev/null; } | sed 's/^/G /'";
+
+// CPU usage needs two snapshots; this is the window for one-off listings
+const ONESHOT_WINDOW: &str = "0.5";
+
+const SIGNALS: &[&str] = &["TERM", "KILL", "INT", "HUP", "STOP", "CONT"];
+
+static STREAM_FLAGS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static STREAM_HANDLES: Lazy<Mutex<HashMap<String, thread::JoinHandle<()>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+struct ProcStat {
+    ppid: i64,
+    name: String,
+    state: String,
+    nice: i32,
+    threads: u32,
+    // utime + stime, in clock ticks
+    ticks: u64,
+    start: u64,
+    rss_pages: u64,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    clk_tck: f64,
+    page_kb: u64,
+    uptime: f64,
+    mem_total_kb: u64,
+    procs: HashMap<i64, ProcStat>,
+}
+
+#[derive(Default)]
+struct Listing {
+    snapshots: Vec<Snapshot>,
+    // pid -> (user, command line)
+    details: HashMap<i64, (String, String)>,
+    gpu_kb: HashMap<i64, u64>,
+}
+
+fn oneshot_script() -> String {
+    format!(
+        "{}; {}; sleep {}; {}",
+        SNAPSHOT_SH, DETAILS_SH, ONESHOT_WINDOW, SNAPSHOT_SH
+    )
+}
+
+fn tick_script() -> String {
+    format!("{}; {}", SNAPSHOT_SH, DETAILS_SH)
+}
+
+/// "1234 (my prog) S 1 ..." -- the name is parenthesised and may itself contain
+/// spaces or parentheses, so fields are counted from the last ')'.
+fn parse_stat(line: &str) -> Option<(i64, ProcStat)> {
+    let (head, tail) = line.rsplit_once(')')?;
+    let (pid, name) = head.split_once(" (")?;
+    let f: Vec<&str> = tail.split_whitespace().collect();
+    let num = |i: usize| f.get(i).and_then(|v| v.parse::<i64>().ok());
+    let stat = ProcStat {
+        ppid: num(1)?,
+        name: name.to_string(),
+        state: f.first()?.to_string(),
+        ticks: (num(11)? + num(12)?) as u64,
+        nice: num(16)? as i32,
+        threads: num(17)? as u32,
+        start: num(19)? as u64,
+        rss_pages: num(21)? as u64,
+    };
+    Some((pid.trim().parse().ok()?, stat))
+}
+
+fn parse_listing(out: &str) -> Listing {
+    let mut listing = Listing::default();
+    for line in out.lines() {
+        let Some((tag, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        match tag {
+            "H" => {
+                let f: Vec<&str> = rest.split_whitespace().collect();
+                let num = |i: usize| f.get(i).and_then(|v| v.parse::<f64>().ok());
+                listing.snapshots.push(Snapshot {
+                    clk_tck: num(0).unwrap_or(100.0),
+                    page_kb: (num(1).unwrap_or(4096.0) / 1024.0) as u64,
+                    uptime: num(2).unwrap_or(0.0),
+                    mem_total_kb: num(3).unwrap_or(0.0) as u64,
+                    procs: HashMap::new(),
+                });
+            }
+            "P" => {
+                if let (Some(snap), Some((pid, stat))) =
+                    (listing.snapshots.last_mut(), parse_stat(rest))
+                {
+                    snap.procs.insert(pid, stat);
+                }
+            }
+            "A" => {
+                let rest = rest.trim_start();
+                let Some((pid, rest)) = rest.split_once(char::is_whitespace) else {
+                    continue;
+                };
+                let rest = rest.trim_start();
+                let (user, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                if let Ok(pid) = pid.parse() {
+                    listing
+                        .details
+                        .insert(pid, (user.to_string(), args.trim().to_string()));
+                }
+            }
+            "G" => {
+                // "user  gnome-shell  6917  39844K"; the header and total rows don't parse
+                let f: Vec<&str> = rest.split_whitespace().collect();
+                if f.len() < 3 {
+                    continue;
+                }
+                let pid = f[f.len() - 2].parse::<i64>();
+                let size = f[f.len() - 1]
+                    .strip_suffix('K')
+                    .and_then(|s| s.parse::<u64>().ok());
+                if let (Ok(pid), Some(size)) = (pid, size) {
+                    *listing.gpu_kb.entry(pid).or_default() += size;
+                }
+            }
+            _ => {}
+        }
+    }
+    listing
+}
+
+/// Build the process list from the latest snapshot. CPU usage is the tick delta
+/// against `prev`; without one it falls back to the average since process start.
+fn build(listing: &Listing, prev: Option<&Snapshot>) -> Vec<ProcessInfo> {
+    let Some(cur) = listing.snapshots.last() else {
+        return Vec::new();
+    };
+    let has_gpu = !listing.gpu_kb.is_empty();
+    let hz = cur.clk_tck.max(1.0);
+
+    cur.procs
+        .iter()
+        .map(|(pid, p)| {
+            let delta = prev.and_then(|prev| {
+                let before = prev.procs.get(pid).filter(|b| b.start == p.start)?;
+                let dt = cur.uptime - prev.uptime;
+                (dt > 0.0).then(|| p.ticks.saturating_sub(before.ticks) as f64 / hz / dt)
+            });
+            let cpu_pct = match delta {
+                Some(share) => share * 100.0,
+                None => {
+                    let alive = cur.uptime - p.start as f64 / hz;
+                    if alive > 0.0 {
+                        p.ticks as f64 / hz / alive * 100.0
+                    } else {
+                        0.0
+                    }
+                }
+            };
+            let rss_kb = p.rss_pages * cur.page_kb;
+            let (user, command) = listing
+                .details
+                .get(pid)
+                .cloned()
+                .unwrap_or_else(|| (String::new(), format!("[{}]", p.name)));
+            ProcessInfo {
+                pid: *pid,
+                ppid: p.ppid,
+                user,
+                name: p.name.clone(),
+                command,
+                state: p.state.clone(),
+                nice: p.nice,
+                threads: p.threads,
+                cpu_pct,
+                rss_kb,
+                mem_pct: if cur.mem_total_kb > 0 {
+                    rss_kb as f64 / cur.mem_total_kb as f64 * 100.0
+                } else {
+                    0.0
+                },
+                gpu_mem_kb: if has_gpu {
+                    Some(listing.gpu_kb.get(pid).copied().unwrap_or(0))
+                } else {
+                    None
+                },
+            }
+        })
+        .collect()
+}
+
+/// Filter on pid, name, user or command line (case-insensitive), then sort and truncate.
+/// Sort keys: cpu (default), mem, gpu, pid, name, user.
+fn select(
+    mut procs: Vec<ProcessInfo>,
+    sort_by: Option<&str>,
+    descending: Option<bool>,
+    filter: Option<&str>,
+    limit: Option<usize>,
+) -> Result<Vec<ProcessInfo>, String> {
+    if let Some(filter) = filter.map(str::trim).filter(|f| !f.is_empty()) {
+        let needle = filter.to_lowercase();
+        procs.retain(|p| {
+            p.pid.to_string() == needle
+                || p.name.to_lowercase().contains(&needle)
+                || p.user.to_lowercase().contains(&needle)
+                || p.command.to_lowercase().contains(&needle)
+        });
+    }
+
+    let key = sort_by.unwrap_or("cpu");
+    let numeric =
+        |f: fn(&ProcessInfo) -> f64| move |a: &ProcessInfo, b: &ProcessInfo| f(a).total_cmp(&f(b));
+    match key {
+        "cpu" => procs.sort_by(numeric(|p| p.cpu_pct)),
+        "mem" => procs.sort_by_key(|p| p.rss_kb),
+        "gpu" => procs.sort_by_key(|p| p.gpu_mem_kb.unwrap_or(0)),
+        "pid" => procs.sort_by_key(|p| p.pid),
+        "name" => procs.sort_by_key(|p| p.name.to_lowercase()),
+        "user" => procs.sort_by(|a, b| a.user.cmp(&b.user).then(a.pid.cmp(&b.pid))),
+        other => return Err(format!("unknown sort key: {}", other)),
+    }
+    // Usage columns read top-down, identifiers alphabetically
+    let default_desc = matches!(key, "cpu" | "mem" | "gpu");
+    if descending.unwrap_or(default_desc) {
+        procs.reverse();
+    }
+    if let Some(limit) = limit {
+        procs.truncate(limit);
+    }
+    Ok(procs)
+}
+
+#[tauri::command]
+pub fn list_processes(
+    device_id: i64,
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    filter: Option<String>,
+    limit: Option<usize>,
+) -> Result<Vec<ProcessInfo>, String> {
+    let out = remote::exec(device_id, &oneshot_script())?.into_result()?;
+    let listing = parse_listing(&out);
+    let prev = listing
+        .snapshots
+        .first()
+        .filter(|_| listing.snapshots.len() > 1);
+    select(
+        build(&listing, prev),
+        sort_by.as_deref(),
+        descending,
+        filter.as_deref(),
+        limit,
+    )
+}
+
+/// Stream the process list over a channel, refreshed every `interval_ms`.
+/// Mirrors `stream_stats`: one stream per token, a new call replaces the old one.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub fn stream_processes(
+    token: String,
+    device_id: i64,
+    interval_ms: Option<u64>,
+    sort_by: Option<String>,
+    descending: Option<bool>,
+    filter: Option<String>,
+    limit: Option<usize>,
+    on_update: Channel<Vec<ProcessInfo>>,
+) -> Result<(), String> {
+    let interval = Duration::from_millis(interval_ms.unwrap_or(2000).max(500));
+    // Reject a bad sort key up front rather than ending the stream on the first tick
+    select(Vec::new(), sort_by.as_deref(), descending, None, None)?;
+    remote::session_handle(device_id)?;
+
+    stop_process_stream(token.clone())?;
+    let flag = Arc::new(AtomicBool::new(true));
+    STREAM_FLAGS
+        .lock()
+        .unwrap()
+        .insert(token.clone(), flag.clone());
+
+    let handle = thread::spawn(move || {
+        let mut prev: Option<Snapshot> = None;
+        while flag.load(Ordering::Relaxed) {
+            let started = Instant::now();
+            // The first tick samples twice so it can report CPU usage straight away
+            let script = if prev.is_none() {
+                oneshot_script()
+            } else {
+                tick_script()
+            };
+            let out = match remote::exec(device_id, &script).and_then(|o| o.into_result()) {
+                Ok(out) => out,
+                Err(e) => {
+                    warn!("process stream for device {} stopped: {}", device_id, e);
+                    break;
+                }
+            };
+            let mut listing = parse_listing(&out);
+            let base = match prev.take() {
+                Some(p) => Some(p),
+                None if listing.snapshots.len() > 1 => Some(listing.snapshots.remove(0)),
+                None => None,
+            };
+            let procs = select(
+                build(&listing, base.as_ref()),
+                sort_by.as_deref(),
+                descending,
+                filter.as_deref(),
+                limit,
+            )
+            .unwrap_or_default();
+            // If send fails, client disconnected - stop streaming
+            if on_update.send(procs).is_err() {
+                break;
+            }
+            prev = listing.snapshots.pop();
+
+            while flag.load(Ordering::Relaxed) && started.elapsed() < interval {
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+    });
+    STREAM_HANDLES.lock().unwrap().insert(token, handle);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn stop_process_stream(token: String) -> Result<(), String> {
+    if let Some(flag) = STREAM_FLAGS.lock().unwrap().remove(&token) {
+        flag.store(false, Ordering::Relaxed);
+    }
+    if let Some(handle) = STREAM_HANDLES.lock().unwrap().remove(&token) {
+        let _ = handle.join();
+    }
+    Ok(())
+}
+
+/// Run as the login user first and retry with sudo for processes owned by someone else.
+fn run_privileged(device_id: i64, cmd: &str) -> Result<(), String> {
+    let script = format!("{} 2>/dev/null || sudo -n {}", cmd, cmd);
+    remote::exec(device_id, &script)?.into_result().map(|_| ())
+}
+
+/// Send TERM, KILL, INT, HUP, STOP or CONT to a process.
+#[tauri::command]
+pub fn signal_process(device_id: i64, pid: i64, signal: String) -> Result<(), String> {
+    let signal = signal.trim_start_matches("SIG").to_uppercase();
+    if !SIGNALS.contains(&signal.as_str()) {
+        return Err(format!("unsupported signal: {}", signal));
+    }
+    if pid <= 1 {
+        return Err(format!("refusing to signal pid {}", pid));
+    }
+    run_privileged(device_id, &format!("kill -s {} {}", signal, pid))
+}
+
+/// Set a process's nice value (-20 to 19). Raising priority needs sudo.
+#[tauri::command]
+pub fn renice_process(device_id: i64, pid: i64, nice: i32) -> Result<(), String> {
+    if !(-20..=19).contains(&nice) {
+        return Err(format!("nice value out of range: {}", nice));
+    }
+    if pid <= 0 {
+        return Err(format!("invalid pid: {}", pid));
+    }
+    run_privileged(device_id, &format!("renice -n {} -p {}", nice, pid))
+}
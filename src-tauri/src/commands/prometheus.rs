@@ -0,0 +1,26 @@
+use crate::db::db_conn;
+use crate::prometheus;
+use crate::types::{PrometheusConfig, PrometheusStatus};
+
+fn status(config: PrometheusConfig) -> PrometheusStatus {
+    PrometheusStatus {
+        config,
+        listening: prometheus::listening().map(|a| a.to_string()),
+    }
+}
+
+#[tauri::command]
+pub fn get_prometheus_config() -> Result<PrometheusStatus, String> {
+    let conn = db_conn()?;
+    Ok(status(prometheus::load_config(&conn)?))
+}
+
+/// Apply the endpoint settings right away and keep them for the next launch.
+/// Nothing is saved when the endpoint can't listen on the new address.
+#[tauri::command]
+pub fn set_prometheus_config(config: PrometheusConfig) -> Result<PrometheusStatus, String> {
+    let conn = db_conn()?;
+    prometheus::apply(&config)?;
+    prometheus::save_config(&conn, &config)?;
+    Ok(status(config))
+}
@@ -0,0 +1,231 @@
+use crate::collector::{self, Delivery};
+use crate::commands::stats::raw_stats;
+use crate::db::db_conn;
+use crate::remote;
+use crate::runs::{self, Outcome, OutputTail, Recorder};
+use crate::types::{BenchmarkRun, RunComparison, RunOutput, RunSummary, StatPoint};
+use log::warn;
+use once_cell::sync::Lazy;
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::ipc::Channel;
+
+// Cancel flags of runs in progress
+static RUN_FLAGS: Lazy<Mutex<HashMap<i64, Arc<AtomicBool>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn run_key(run_id: i64) -> String {
+    format!("run:{}", run_id)
+}
+
+/// Held by a run's thread: however it ends, including a panic, the device,
+/// the run's collector subscription and its cancel flag are released. After a
+/// panic the run is recorded as failed and the final message still sent.
+struct RunGuard {
+    run_id: i64,
+    on_output: Channel<RunOutput>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let run_id = self.run_id;
+        collector::unsubscribe(&run_key(run_id));
+        if let Ok(mut flags) = RUN_FLAGS.lock() {
+            flags.remove(&run_id);
+        }
+        if thread::panicking() {
+            warn!("run {} aborted", run_id);
+            let finished = db_conn().and_then(|conn| {
+                runs::finish(
+                    &conn,
+                    run_id,
+                    &Outcome::Failed("aborted by an internal error".to_string()),
+                    &OutputTail::default(),
+                    &RunSummary::default(),
+                )
+            });
+            if let Ok(run) = finished {
+                let _ = self.on_output.send(RunOutput {
+                    run_id,
+                    ts: chrono::Utc::now().timestamp_millis(),
+                    line: None,
+                    run: Some(run),
+                });
+            }
+        }
+        runs::release(run_id);
+    }
+}
+
+/// Run a benchmark command on the device and return its run id. Output lines
+/// are streamed over `on_output` as they arrive, followed by one message with
+/// the finished run. Meanwhile stats are sampled every `sample_interval_ms`
+/// (default 250) and tagged with the run. One run per device at a time.
+#[tauri::command]
+pub fn start_run(
+    device_id: i64,
+    command: String,
+    name: Option<String>,
+    sample_interval_ms: Option<u64>,
+    on_output: Channel<RunOutput>,
+) -> Result<i64, String> {
+    let command = command.trim().to_string();
+    if command.is_empty() {
+        return Err("command is empty".to_string());
+    }
+    let name = name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+    launch(
+        device_id,
+        command,
+        name,
+        sample_interval_ms,
+        on_output,
+        |_, _, _| {},
+    )
+}
+
+/// Start a run in the background; see `start_run`. `on_finish` gets the
+/// recorded run and its output before the final message is sent, so whatever it
+/// stores is in place when the frontend hears the run is done.
+pub(crate) fn launch<F>(
+    device_id: i64,
+    command: String,
+    name: Option<String>,
+    sample_interval_ms: Option<u64>,
+    on_output: Channel<RunOutput>,
+    on_finish: F,
+) -> Result<i64, String>
+where
+    F: FnOnce(&Connection, &BenchmarkRun, &str) + Send + 'static,
+{
+    remote::session_handle(device_id)?;
+    let interval = sample_interval_ms.unwrap_or(250).max(100);
+
+    let conn = db_conn()?;
+    let run_id = runs::begin(&conn, device_id, name.as_deref(), &command, interval)?;
+    let (tx, rx) = mpsc::channel::<StatPoint>();
+    if let Err(e) =
+        collector::subscribe(&run_key(run_id), device_id, interval, Delivery::Sender(tx))
+    {
+        let _ = runs::finish(
+            &conn,
+            run_id,
+            &Outcome::Failed(e.clone()),
+            &OutputTail::default(),
+            &RunSummary::default(),
+        );
+        return Err(e);
+    }
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    RUN_FLAGS.lock().unwrap().insert(run_id, cancel.clone());
+
+    thread::spawn(move || {
+        let _guard = RunGuard {
+            run_id,
+            on_output: on_output.clone(),
+        };
+        let mut output = OutputTail::default();
+        let mut recorder = Recorder::default();
+        let outcome = runs::execute(
+            device_id,
+            &command,
+            &cancel,
+            |line| {
+                output.push_line(line);
+                // Keep running if the window went away; the run is still recorded
+                let _ = on_output.send(RunOutput {
+                    run_id,
+                    ts: chrono::Utc::now().timestamp_millis(),
+                    line: Some(line.to_string()),
+                    run: None,
+                });
+            },
+            || rx.try_iter().for_each(|p| recorder.add(&p)),
+        );
+        collector::unsubscribe(&run_key(run_id));
+        rx.try_iter().for_each(|p| recorder.add(&p));
+        RUN_FLAGS.lock().unwrap().remove(&run_id);
+
+        let finished = db_conn().and_then(|conn| {
+            let run = runs::finish(&conn, run_id, &outcome, &output, &recorder.summary())?;
+            on_finish(&conn, &run, output.text());
+            Ok(run)
+        });
+        match finished {
+            Ok(run) => {
+                let _ = on_output.send(RunOutput {
+                    run_id,
+                    ts: chrono::Utc::now().timestamp_millis(),
+                    line: None,
+                    run: Some(run),
+                });
+            }
+            Err(e) => warn!("failed to record run {}: {}", run_id, e),
+        }
+    });
+    Ok(run_id)
+}
+
+/// Stop a run in progress. The command is sent SIGHUP and the run is recorded
+/// as cancelled.
+#[tauri::command]
+pub fn cancel_run(run_id: i64) -> Result<(), String> {
+    let flags = RUN_FLAGS.lock().unwrap();
+    let flag = flags
+        .get(&run_id)
+        .ok_or_else(|| format!("run {} is not in progress", run_id))?;
+    flag.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Runs, newest first (default 50), for one device or all of them.
+#[tauri::command]
+pub fn list_runs(device_id: Option<i64>, limit: Option<i64>) -> Result<Vec<BenchmarkRun>, String> {
+    let conn = db_conn()?;
+    runs::list(&conn, device_id, limit.unwrap_or(50))
+}
+
+/// One run with its captured output.
+#[tauri::command]
+pub fn get_run(run_id: i64) -> Result<BenchmarkRun, String> {
+    let conn = db_conn()?;
+    runs::load(&conn, run_id, true)
+}
+
+/// Samples recorded during a run, chronological, with the requested named
+/// series (see `get_stats`). Empty once retention has pruned them.
+#[tauri::command]
+pub fn get_run_stats(run_id: i64, series: Option<Vec<String>>) -> Result<Vec<StatPoint>, String> {
+    let conn = db_conn()?;
+    let run = runs::load(&conn, run_id, false)?;
+    let Some((first, last, count)) = runs::sample_span(&conn, run_id)? else {
+        return Ok(Vec::new());
+    };
+    raw_stats(
+        &conn,
+        run.device_id,
+        Some(count),
+        Some(first),
+        Some(last),
+        series,
+    )
+}
+
+/// Two runs side by side: both runs and the change of each summary metric
+/// from `run_a` to `run_b`.
+#[tauri::command]
+pub fn compare_runs(run_a: i64, run_b: i64) -> Result<RunComparison, String> {
+    let conn = db_conn()?;
+    runs::compare(&conn, run_a, run_b)
+}
+
+#[tauri::command]
+pub fn delete_run(run_id: i64) -> Result<(), String> {
+    let conn = db_conn()?;
+    runs::delete(&conn, run_id)
+}
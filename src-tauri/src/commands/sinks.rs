@@ -0,0 +1,142 @@
+use crate::collector;
+use crate::db::db_conn;
+use crate::sinks;
+use crate::types::{MetricSink, SinkStatus, StatPoint};
+use rusqlite::params;
+
+/// Sinks for a device, including ones for every device, or all sinks when no device is given.
+#[tauri::command]
+pub fn list_metric_sinks(device_id: Option<i64>) -> Result<Vec<MetricSink>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM metric_sink WHERE ?1 IS NULL OR device_id IS NULL OR device_id = ?1 ORDER BY id",
+            sinks::SINK_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([device_id], sinks::sink_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Create a sink (no id) or replace an existing one. Messages already queued
+/// are sent with the new settings.
+#[tauri::command]
+pub fn save_metric_sink(sink: MetricSink) -> Result<MetricSink, String> {
+    sinks::validate(&sink)?;
+    let mut sink = sink;
+    let conn = db_conn()?;
+    match sink.id {
+        None => {
+            conn.execute(
+                "INSERT INTO metric_sink (device_id, name, kind, url, token, username, password, topic, qos, batch_size, flush_interval_ms, enabled, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                params![
+                    sink.device_id,
+                    sink.name,
+                    sink.kind,
+                    sink.url,
+                    sink.token,
+                    sink.username,
+                    sink.password,
+                    sink.topic,
+                    sink.qos,
+                    sink.batch_size,
+                    sink.flush_interval_ms,
+                    sink.enabled,
+                    chrono::Utc::now().timestamp_millis()
+                ],
+            )
+            .map_err(|e| e.to_string())?;
+            sink.id = Some(conn.last_insert_rowid());
+        }
+        Some(id) => {
+            let n = conn
+                .execute(
+                    "UPDATE metric_sink SET device_id = ?1, name = ?2, kind = ?3, url = ?4, token = ?5, username = ?6, password = ?7, topic = ?8, qos = ?9, batch_size = ?10, flush_interval_ms = ?11, enabled = ?12 WHERE id = ?13",
+                    params![
+                        sink.device_id,
+                        sink.name,
+                        sink.kind,
+                        sink.url,
+                        sink.token,
+                        sink.username,
+                        sink.password,
+                        sink.topic,
+                        sink.qos,
+                        sink.batch_size,
+                        sink.flush_interval_ms,
+                        sink.enabled,
+                        id
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+            if n == 0 {
+                return Err("metric sink not found".to_string());
+            }
+            // Retry right away with the new settings
+            sinks::forget(id);
+        }
+    }
+    sinks::invalidate_sinks();
+    Ok(sink)
+}
+
+/// Delete a sink along with any messages still queued for it.
+#[tauri::command]
+pub fn delete_metric_sink(id: i64) -> Result<(), String> {
+    let conn = db_conn()?;
+    conn.execute("DELETE FROM metric_sink WHERE id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    conn.execute("DELETE FROM sink_outbox WHERE sink_id = ?1", [id])
+        .map_err(|e| e.to_string())?;
+    sinks::invalidate_sinks();
+    sinks::forget(id);
+    Ok(())
+}
+
+/// Send one sample to a sink right away, without saving it or using the outbox.
+/// Uses the device's latest sample when there is one, otherwise an empty sample.
+#[tauri::command]
+pub fn test_metric_sink(sink: MetricSink) -> Result<(), String> {
+    let conn = db_conn()?;
+    let latest = collector::latest()
+        .into_iter()
+        .filter(|p| sink.device_id.is_none_or(|d| d == p.device_id))
+        .max_by_key(|p| p.ts);
+    let point = latest.unwrap_or_else(|| StatPoint {
+        ts: chrono::Utc::now().timestamp_millis(),
+        cpu: 0.0,
+        ram_used_mb: 0,
+        ram_total_mb: 0,
+        gpu_util: None,
+        gpu_temp_c: None,
+        power_mode: None,
+        clocks_locked: None,
+        fan_rpm: None,
+        fan_pwm: None,
+        tegrastats: None,
+        metrics: Default::default(),
+        resolution_ms: None,
+        metrics_min: Default::default(),
+        metrics_max: Default::default(),
+        throttling: Vec::new(),
+        device_id: sink.device_id.unwrap_or(0),
+    });
+    let device: String = conn
+        .query_row(
+            "SELECT name FROM device WHERE id = ?1",
+            [point.device_id],
+            |row| row.get(0),
+        )
+        .unwrap_or_else(|_| "test".to_string());
+    sinks::test(&sink, &point, &device)
+}
+
+/// Queue length and delivery state of every sink.
+#[tauri::command]
+pub fn get_sink_status() -> Result<Vec<SinkStatus>, String> {
+    let conn = db_conn()?;
+    sinks::status(&conn)
+}
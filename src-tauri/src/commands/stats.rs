@@ -1,113 +1,42 @@
+use crate::aggregate;
+use crate::alerts;
+use crate::collector::{self, oneshot_script, store_point, RawSample, SampleState, DF_EXCLUDE};
 use crate::db::db_conn;
-use crate::session::SESSIONS;
-use crate::types::StatPoint;
+use crate::export;
+use crate::metrics;
+use crate::remote::{self, ExecOptions};
+use crate::retention;
+use crate::sinks;
+use crate::throttle;
+use crate::types::{
+    AggregatePoint, AggregatedSeries, AggregatedStats, BlockDeviceInfo, CompactionReport,
+    ExportReport, FilesystemInfo, FilesystemOverview, ImportReport, MetricSeries, PowerModeTime,
+    PowerSegment, PowerSegments, RetentionPolicy, StatPoint, Subscription, ThrottleEvent,
+};
 use once_cell::sync::Lazy;
-use rusqlite::params;
-use std::collections::HashMap;
-use std::io::Read;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Duration;
+use rusqlite::Connection;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
 use tauri::ipc::Channel;
 
-// Track previous CPU totals per session token to compute usage delta
-static LAST_CPU: Lazy<Mutex<HashMap<String, (u64, u64)>>> =
+// Track previous sample counters per session token to compute usage deltas
+static LAST_SAMPLE: Lazy<Mutex<HashMap<String, SampleState>>> =
     Lazy::new(|| Mutex::new(HashMap::new()));
 
-fn parse_cpu_line(line: &str) -> Option<(u64, u64)> {
-    // line starts with: cpu  user nice system idle iowait irq softirq steal guest guest_nice
-    let mut parts = line.split_whitespace();
-    let _ = parts.next(); // "cpu"
-    let nums: Vec<u64> = parts.filter_map(|p| p.parse::<u64>().ok()).collect();
-    if nums.len() < 4 {
-        return None;
-    }
-    let user = nums.get(0).copied().unwrap_or(0);
-    let nice = nums.get(1).copied().unwrap_or(0);
-    let system = nums.get(2).copied().unwrap_or(0);
-    let idle = nums.get(3).copied().unwrap_or(0);
-    let iowait = nums.get(4).copied().unwrap_or(0);
-    let irq = nums.get(5).copied().unwrap_or(0);
-    let softirq = nums.get(6).copied().unwrap_or(0);
-    let steal = nums.get(7).copied().unwrap_or(0);
-    let total = user + nice + system + idle + iowait + irq + softirq + steal;
-    let idle_total = idle + iowait;
-    Some((total, idle_total))
-}
-
+/// Take a single sample over the shared session and store it. Streaming goes
+/// through the per-device collector instead.
 #[tauri::command]
 pub fn record_stat(token: &str, device_id: Option<i64>) -> Result<StatPoint, String> {
-    // get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(token).cloned()
-    }
-    .ok_or_else(|| "session not found".to_string())?;
-
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
-    };
+    let handle = remote::session_by_key(token)?;
+    let out = remote::exec_on(&handle, &oneshot_script(), &ExecOptions::default())?.stdout;
 
-    // Run a combined command to fetch CPU line and memory totals
-    let cmd = "sh -lc 'cat /proc/stat | head -n1; free -m | awk \"/Mem:/ {print $2, $3}\"'";
-    channel.exec(cmd).map_err(|e| e.to_string())?;
-    let mut out = String::new();
-    channel
-        .read_to_string(&mut out)
-        .map_err(|e| e.to_string())?;
-    let _ = channel.wait_close();
-
-    let mut lines = out.lines();
-    let cpu_line = lines.next().unwrap_or("");
-    let mem_line = lines.next().unwrap_or("");
-
-    let (total, idle_total) =
-        parse_cpu_line(cpu_line).ok_or_else(|| "failed to parse cpu".to_string())?;
-    let (cpu_prev_total, cpu_prev_idle) = {
-        let mut map = LAST_CPU.lock().unwrap();
-        let prev = map.insert(token.to_string(), (total, idle_total));
-        prev.unwrap_or((total, idle_total))
-    };
-    let dt = total.saturating_sub(cpu_prev_total);
-    let didle = idle_total.saturating_sub(cpu_prev_idle);
-    let cpu = if dt > 0 {
-        (dt - didle) as f64 / dt as f64 * 100.0
-    } else {
-        0.0
-    };
-
-    let (ram_total_mb, ram_used_mb) = {
-        let mut it = mem_line.split_whitespace();
-        let total_s = it.next().unwrap_or("0");
-        let used_s = it.next().unwrap_or("0");
-        let total = total_s.parse::<i64>().unwrap_or(0);
-        let used = used_s.parse::<i64>().unwrap_or(0);
-        (total, used)
-    };
-
-    let ts = chrono::Utc::now().timestamp_millis();
-
-    // Try to fetch tegrastats one-shot for GPU util and temperature (best-effort)
-    let (gpu_util, gpu_temp_c): (Option<f64>, Option<f64>) = {
-        let sess = handle.session.lock();
-        if let Ok(mut ch2) = sess.channel_session() {
-            if ch2
-                .exec("sh -lc 'tegrastats --interval 1000 --count 1 2>/dev/null || sudo -n tegrastats --interval 1000 --count 1 2>/dev/null'")
-                .is_ok()
-            {
-                let mut out = String::new();
-                let _ = ch2.read_to_string(&mut out);
-                let _ = ch2.wait_close();
-                parse_tegrastats_line(out.lines().next().unwrap_or(""))
-            } else {
-                (None, None)
-            }
-        } else {
-            (None, None)
+    let mut raw = RawSample::default();
+    for line in out.lines() {
+        if raw.push_line(line) {
+            break;
         }
-    };
+    }
+
     let conn = db_conn()?;
     let did = if let Some(id) = device_id {
         id
@@ -120,34 +49,78 @@ pub fn record_stat(token: &str, device_id: Option<i64>) -> Result<StatPoint, Str
         )
         .unwrap_or(1)
     };
-    conn.execute(
-        "INSERT INTO device_stats (ts, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode, device_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, NULL, ?7)",
-        params![ts, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, did],
-    )
-    .map_err(|e| e.to_string())?;
-
-    Ok(StatPoint {
-        ts,
-        cpu,
-        ram_used_mb,
-        ram_total_mb,
-        gpu_util,
-        gpu_temp_c,
-        power_mode: None,
-        device_id: did,
-    })
+
+    let point = {
+        let mut map = LAST_SAMPLE.lock().unwrap();
+        let state = map.entry(token.to_string()).or_default();
+        raw.to_point(state, did)?
+    };
+    store_point(&conn, &point)?;
+    throttle::track(&conn, &point);
+    alerts::evaluate(&conn, &point);
+    sinks::enqueue(&conn, &point);
+
+    Ok(point)
 }
 
+/// Stored samples for a device. `series` selects named metrics to attach to each
+/// point (exact names, or prefixes ending in `*` such as `temp.*`).
+///
+/// `resolution` is "raw", "1m", "1h" or "auto" (default). Auto serves short,
+/// recent ranges from raw samples and longer ones from the rollups; without a
+/// `start_ts` it returns the latest raw samples.
 #[tauri::command]
 pub fn get_stats(
     device_id: i64,
     limit: Option<i64>,
     start_ts: Option<i64>,
     end_ts: Option<i64>,
+    series: Option<Vec<String>>,
+    resolution: Option<String>,
 ) -> Result<Vec<StatPoint>, String> {
     let conn = db_conn()?;
+    let end = end_ts.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let resolution_ms = match resolution.as_deref().unwrap_or("auto") {
+        "raw" => None,
+        "1m" => Some(retention::MINUTE_MS),
+        "1h" => Some(retention::HOUR_MS),
+        "auto" => match start_ts {
+            Some(start) => retention::pick_resolution(
+                &conn,
+                device_id,
+                start,
+                end,
+                retention::RAW_MAX_SPAN_MS,
+            )?,
+            None => None,
+        },
+        other => return Err(format!("unknown resolution: {}", other)),
+    };
+    let mut points = match resolution_ms {
+        None => raw_stats(&conn, device_id, limit, start_ts, end_ts, series)?,
+        Some(res) => {
+            let start = start_ts.unwrap_or(end - limit.unwrap_or(120) * res);
+            rollup_stats(&conn, device_id, res, limit, start, end, series)?
+        }
+    };
+    if let (Some(first), Some(last)) = (points.first(), points.last()) {
+        let last_end = last.ts + last.resolution_ms.unwrap_or(0);
+        let events = throttle::query(&conn, device_id, first.ts, last_end)?;
+        throttle::annotate(&mut points, &events);
+    }
+    Ok(points)
+}
+
+pub(crate) fn raw_stats(
+    conn: &Connection,
+    device_id: i64,
+    limit: Option<i64>,
+    start_ts: Option<i64>,
+    end_ts: Option<i64>,
+    series: Option<Vec<String>>,
+) -> Result<Vec<StatPoint>, String> {
     let lim = limit.unwrap_or(120);
-    let mut query = String::from("SELECT ts, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode FROM device_stats WHERE device_id = ?1");
+    let mut query = String::from("SELECT ts, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode, clocks_locked, fan_rpm, fan_pwm FROM device_stats WHERE device_id = ?1");
     let mut bind: Vec<rusqlite::types::Value> = vec![rusqlite::types::Value::from(device_id)];
     if let Some(s) = start_ts {
         query.push_str(" AND ts >= ?2");
@@ -170,6 +143,15 @@ pub fn get_stats(
                 gpu_util: row.get(4)?,
                 gpu_temp_c: row.get(5)?,
                 power_mode: row.get(6)?,
+                clocks_locked: row.get(7)?,
+                fan_rpm: row.get(8)?,
+                fan_pwm: row.get(9)?,
+                tegrastats: None,
+                metrics: Default::default(),
+                resolution_ms: None,
+                metrics_min: Default::default(),
+                metrics_max: Default::default(),
+                throttling: Vec::new(),
                 device_id,
             })
         })
@@ -181,102 +163,525 @@ pub fn get_stats(
         }
     }
     v.reverse(); // chronological
+
+    if let (Some(names), Some(first), Some(last)) = (series, v.first(), v.last()) {
+        if !names.is_empty() {
+            let mut by_ts = metrics::query(conn, device_id, &names, first.ts, last.ts)?;
+            for point in v.iter_mut() {
+                point.metrics = by_ts.remove(&point.ts).unwrap_or_default();
+            }
+        }
+    }
+    Ok(v)
+}
+
+/// Downsampled points: core fields hold bucket averages, `metrics` the averages of
+/// the requested series and `metrics_min` / `metrics_max` the extremes of both.
+fn rollup_stats(
+    conn: &Connection,
+    device_id: i64,
+    resolution_ms: i64,
+    limit: Option<i64>,
+    start_ts: i64,
+    end_ts: i64,
+    series: Option<Vec<String>>,
+) -> Result<Vec<StatPoint>, String> {
+    let requested = series.unwrap_or_default();
+    let mut names: Vec<String> = retention::CORE_SERIES
+        .iter()
+        .map(|(_, name)| name.to_string())
+        .collect();
+    names.extend(requested.iter().cloned());
+
+    let buckets = retention::query(conn, device_id, resolution_ms, &names, start_ts, end_ts)?;
+    let mut v: Vec<StatPoint> = buckets
+        .into_iter()
+        .map(|(ts, by_name)| {
+            let avg = |name: &str| by_name.get(name).map(|b| b.avg);
+            let mut point = StatPoint {
+                ts,
+                cpu: avg("cpu").unwrap_or(0.0),
+                ram_used_mb: avg("ram.used").unwrap_or(0.0).round() as i64,
+                ram_total_mb: avg("ram.total").unwrap_or(0.0).round() as i64,
+                gpu_util: avg("gpu.load"),
+                gpu_temp_c: avg("temp.gpu"),
+                power_mode: None,
+                clocks_locked: None,
+                fan_rpm: avg("fan.rpm").map(|v| v.round() as i64),
+                fan_pwm: avg("fan.pwm").map(|v| v.round() as i64),
+                tegrastats: None,
+                metrics: Default::default(),
+                resolution_ms: Some(resolution_ms),
+                metrics_min: Default::default(),
+                metrics_max: Default::default(),
+                throttling: Vec::new(),
+                device_id,
+            };
+            for (name, b) in &by_name {
+                if metrics::matches(name, &requested) {
+                    point.metrics.insert(name.clone(), b.avg);
+                }
+                point.metrics_min.insert(name.clone(), b.min);
+                point.metrics_max.insert(name.clone(), b.max);
+            }
+            point
+        })
+        .collect();
+    if let Some(lim) = limit {
+        let skip = v.len().saturating_sub(lim.max(0) as usize);
+        v.drain(..skip);
+    }
     Ok(v)
 }
 
-fn parse_tegrastats_line(line: &str) -> (Option<f64>, Option<f64>) {
-    // Very loose parsing: look for "GR3D_FREQ <num>%" and "GPU@<num>C"
-    let mut gpu_util: Option<f64> = None;
-    let mut gpu_temp: Option<f64> = None;
-    if let Some(idx) = line.find("GR3D_FREQ") {
-        let s = &line[idx..];
-        // find first number before %
-        let pct = s
-            .split('%')
-            .next()
-            .and_then(|part| part.rsplit_once(' '))
-            .and_then(|(_, n)| n.parse::<f64>().ok());
-        gpu_util = pct;
+// Ranges up to this long are aggregated from raw samples (with p95), longer ones from rollups
+const AGG_RAW_MAX_SPAN_MS: i64 = 24 * retention::HOUR_MS;
+
+/// Downsampled series for charting a time range. Either `bucket_ms` or a target
+/// number of `points` (default 500) sets the bucket width. `method` is "buckets"
+/// (min/avg/max/p95 per bucket, the default) or "lttb" (a visually faithful
+/// subset of the samples). `series` defaults to the core metrics.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub fn get_stats_aggregated(
+    device_id: i64,
+    start_ts: i64,
+    end_ts: Option<i64>,
+    series: Option<Vec<String>>,
+    points: Option<usize>,
+    bucket_ms: Option<i64>,
+    method: Option<String>,
+) -> Result<AggregatedStats, String> {
+    let conn = db_conn()?;
+    let end = end_ts.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    if end <= start_ts {
+        return Err("end_ts must be after start_ts".to_string());
+    }
+    let method = method.unwrap_or_else(|| "buckets".to_string());
+    if method != "buckets" && method != "lttb" {
+        return Err(format!("unknown aggregation method: {}", method));
     }
-    if let Some(idx) = line.find("GPU@") {
-        let s = &line[idx + 4..];
-        let c_str: String = s.chars().take_while(|ch| ch.is_ascii_digit()).collect();
-        if let Ok(v) = c_str.parse::<f64>() {
-            gpu_temp = Some(v);
+    let names: Vec<String> = match series {
+        Some(s) if !s.is_empty() => s,
+        _ => retention::CORE_SERIES
+            .iter()
+            .map(|(_, name)| name.to_string())
+            .collect(),
+    };
+    let points = points.unwrap_or(500).max(3);
+    let span = end - start_ts;
+    let mut bucket = bucket_ms
+        .unwrap_or((span + points as i64 - 1) / points as i64)
+        .max(1);
+
+    let mut resolution =
+        retention::pick_resolution(&conn, device_id, start_ts, end, AGG_RAW_MAX_SPAN_MS)?;
+    if resolution == Some(retention::MINUTE_MS) && bucket >= retention::HOUR_MS {
+        resolution = Some(retention::HOUR_MS);
+    }
+
+    let mut out: Vec<AggregatedSeries> = Vec::new();
+    match resolution {
+        None => {
+            let by_ts = metrics::query(&conn, device_id, &names, start_ts, end)?;
+            let mut by_name: BTreeMap<String, Vec<(i64, f64)>> = BTreeMap::new();
+            for (ts, values) in by_ts {
+                for (name, v) in values {
+                    by_name.entry(name).or_default().push((ts, v));
+                }
+            }
+            for (name, mut values) in by_name {
+                values.sort_by_key(|(ts, _)| *ts);
+                let points = if method == "lttb" {
+                    single_points(aggregate::lttb(&values, points))
+                } else {
+                    aggregate::buckets(&values, bucket)
+                };
+                out.push(series_out(name, points));
+            }
+        }
+        Some(res) => {
+            // Rollup buckets can only be merged, not split
+            bucket = (bucket + res - 1) / res * res;
+            let by_ts = retention::query(&conn, device_id, res, &names, start_ts, end)?;
+            let mut by_name: BTreeMap<&str, Vec<(i64, &retention::Bucket)>> = BTreeMap::new();
+            for (ts, values) in &by_ts {
+                for (name, b) in values {
+                    by_name.entry(name.as_str()).or_default().push((*ts, b));
+                }
+            }
+            for (name, values) in by_name {
+                let points = if method == "lttb" {
+                    let avgs: Vec<(i64, f64)> = values.iter().map(|(ts, b)| (*ts, b.avg)).collect();
+                    single_points(aggregate::lttb(&avgs, points))
+                } else {
+                    aggregate::merge_rollups(&values, bucket)
+                };
+                out.push(series_out(name.to_string(), points));
+            }
         }
     }
-    (gpu_util, gpu_temp)
+
+    let throttle_events = throttle::query(&conn, device_id, start_ts, end)?;
+    Ok(AggregatedStats {
+        start_ts,
+        end_ts: end,
+        bucket_ms: (method == "buckets").then_some(bucket),
+        method,
+        source: match resolution {
+            None => "raw",
+            Some(retention::MINUTE_MS) => "1m",
+            Some(_) => "1h",
+        }
+        .to_string(),
+        series: out,
+        throttle_events,
+    })
 }
 
-// Background streaming management (old event-based approach - kept for compatibility)
-static STREAM_FLAGS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static STREAM_HANDLES: Lazy<Mutex<HashMap<String, thread::JoinHandle<()>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+/// Throttle events overlapping a time range (default: the last 24 hours).
+#[tauri::command]
+pub fn list_throttle_events(
+    device_id: i64,
+    start_ts: Option<i64>,
+    end_ts: Option<i64>,
+) -> Result<Vec<ThrottleEvent>, String> {
+    let conn = db_conn()?;
+    let end = end_ts.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let start = start_ts.unwrap_or(end - 24 * retention::HOUR_MS);
+    throttle::query(&conn, device_id, start, end)
+}
 
-// Channel-based streaming management (new, efficient approach)
-static CHANNEL_STREAM_FLAGS: Lazy<Mutex<HashMap<String, Arc<AtomicBool>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-static CHANNEL_STREAM_HANDLES: Lazy<Mutex<HashMap<String, thread::JoinHandle<()>>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
+// Samples further apart than this are treated as a gap in collection
+const SEGMENT_GAP_MS: i64 = 60_000;
 
-/// Stream stats using Tauri channels (efficient, designed for streaming)
+/// Split a time range (default: the last 24 hours) into stretches of constant
+/// power mode and jetson_clocks state, and total the time spent in each. Only
+/// raw samples carry the power configuration, so the range is limited to what
+/// raw retention keeps.
 #[tauri::command]
-pub fn stream_stats(
-    token: String,
+pub fn get_power_segments(
     device_id: i64,
-    interval_ms: Option<u64>,
-    on_stat: Channel<StatPoint>,
-) -> Result<(), String> {
-    let interval = interval_ms.unwrap_or(1000);
+    start_ts: Option<i64>,
+    end_ts: Option<i64>,
+) -> Result<PowerSegments, String> {
+    let conn = db_conn()?;
+    let end = end_ts.unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+    let start = start_ts.unwrap_or(end - 24 * retention::HOUR_MS);
+    if end <= start {
+        return Err("end_ts must be after start_ts".to_string());
+    }
+    let mut stmt = conn
+        .prepare("SELECT ts, power_mode, clocks_locked FROM device_stats WHERE device_id = ?1 AND ts >= ?2 AND ts <= ?3 ORDER BY ts")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params![device_id, start, end], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, Option<bool>>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    // Each sample stands for the time until the next one, unless that is a gap
+    let mut segments: Vec<PowerSegment> = Vec::new();
+    let mut durations: Vec<i64> = Vec::new();
+    for r in rows {
+        let (ts, power_mode, clocks_locked) = r.map_err(|e| e.to_string())?;
+        if let (Some(seg), Some(d)) = (segments.last_mut(), durations.last_mut()) {
+            let gap = ts - seg.end_ts;
+            if gap <= SEGMENT_GAP_MS {
+                *d += gap;
+                if seg.power_mode == power_mode && seg.clocks_locked == clocks_locked {
+                    seg.end_ts = ts;
+                    seg.samples += 1;
+                    continue;
+                }
+            }
+        }
+        segments.push(PowerSegment {
+            power_mode,
+            clocks_locked,
+            start_ts: ts,
+            end_ts: ts,
+            samples: 1,
+        });
+        durations.push(0);
+    }
 
-    // Stop any existing stream for this token
-    if let Some(flag) = CHANNEL_STREAM_FLAGS.lock().unwrap().remove(&token) {
-        flag.store(false, Ordering::Relaxed);
+    let covered_ms: i64 = durations.iter().sum();
+    let mut totals: Vec<PowerModeTime> = Vec::new();
+    for (seg, d) in segments.iter().zip(&durations) {
+        match totals
+            .iter_mut()
+            .find(|t| t.power_mode == seg.power_mode && t.clocks_locked == seg.clocks_locked)
+        {
+            Some(t) => {
+                t.duration_ms += d;
+                t.samples += seg.samples;
+            }
+            None => totals.push(PowerModeTime {
+                power_mode: seg.power_mode.clone(),
+                clocks_locked: seg.clocks_locked,
+                duration_ms: *d,
+                share: 0.0,
+                samples: seg.samples,
+            }),
+        }
     }
-    if let Some(handle) = CHANNEL_STREAM_HANDLES.lock().unwrap().remove(&token) {
-        let _ = handle.join();
+    for t in totals.iter_mut() {
+        if covered_ms > 0 {
+            t.share = t.duration_ms as f64 / covered_ms as f64;
+        }
     }
+    totals.sort_by_key(|t| std::cmp::Reverse(t.duration_ms));
+
+    Ok(PowerSegments {
+        start_ts: start,
+        end_ts: end,
+        covered_ms,
+        segments,
+        totals,
+    })
+}
+
+fn single_points(values: Vec<(i64, f64)>) -> Vec<AggregatePoint> {
+    values
+        .into_iter()
+        .map(|(ts, v)| AggregatePoint {
+            ts,
+            count: 1,
+            min: v,
+            avg: v,
+            max: v,
+            p95: None,
+        })
+        .collect()
+}
+
+fn series_out(name: String, points: Vec<AggregatePoint>) -> AggregatedSeries {
+    AggregatedSeries {
+        unit: metrics::unit_of(&name).to_string(),
+        name,
+        points,
+    }
+}
+
+#[tauri::command]
+pub fn get_retention_policy() -> Result<RetentionPolicy, String> {
+    let conn = db_conn()?;
+    retention::load_policy(&conn)
+}
+
+/// Takes effect at the next compaction run.
+#[tauri::command]
+pub fn set_retention_policy(policy: RetentionPolicy) -> Result<(), String> {
+    let conn = db_conn()?;
+    retention::save_policy(&conn, &policy)
+}
+
+/// Run the rollup and pruning job now instead of waiting for the background run.
+#[tauri::command]
+pub fn compact_stats() -> Result<CompactionReport, String> {
+    let mut conn = db_conn()?;
+    retention::compact(&mut conn)
+}
+
+/// Write a device's stats in a time range to a local file. `table` is "stats"
+/// (default) or "metrics"; the format defaults to the file extension.
+#[tauri::command]
+pub fn export_stats(
+    device_id: i64,
+    path: String,
+    start_ts: Option<i64>,
+    end_ts: Option<i64>,
+    table: Option<String>,
+    format: Option<String>,
+) -> Result<ExportReport, String> {
+    let conn = db_conn()?;
+    export::export(
+        &conn,
+        device_id,
+        start_ts.unwrap_or(0),
+        end_ts.unwrap_or(i64::MAX),
+        table.as_deref().unwrap_or("stats"),
+        format.as_deref(),
+        std::path::Path::new(&path),
+    )
+}
+
+/// Load a file written by `export_stats`, optionally into a different device.
+#[tauri::command]
+pub fn import_stats(
+    path: String,
+    format: Option<String>,
+    device_id: Option<i64>,
+) -> Result<ImportReport, String> {
+    let mut conn = db_conn()?;
+    export::import(
+        &mut conn,
+        std::path::Path::new(&path),
+        format.as_deref(),
+        device_id,
+    )
+}
+
+/// Named series recorded for a device, with units, for use with `get_stats`.
+#[tauri::command]
+pub fn list_metric_series(device_id: i64) -> Result<Vec<MetricSeries>, String> {
+    let conn = db_conn()?;
+    metrics::list_series(&conn, device_id)
+}
+
+/// Mounted filesystems with space and inode usage, plus the block devices behind
+/// them with whatever health data the device exposes (eMMC wear, NVMe smart-log).
+#[tauri::command]
+pub fn get_filesystem_overview(device_id: i64) -> Result<FilesystemOverview, String> {
+    let cmd = format!(
+        r#"df -P -kT {df} 2>/dev/null | awk 'NR > 1 {{print "FS", $1, $2, $7, $3, $4, $5}}'
+        df -P -i {df} 2>/dev/null | awk 'NR > 1 {{print "IN", $6, $2, $3}}'
+        r() {{ v=$(cat "$1" 2>/dev/null | tr -s ' ' '_'); echo "${{v:--}}"; }}
+        for b in /sys/block/*; do
+            n=$(basename "$b")
+            case "$n" in loop*|ram*|zram*|mmcblk*boot*|mmcblk*rpmb) continue ;; esac
+            echo "BLK $n $(r $b/size) $(r $b/device/type) $(r $b/device/life_time) $(r $b/device/pre_eol_info) $(r $b/device/model)"
+        done
+        for n in /dev/nvme[0-9]; do
+            [ -e "$n" ] || continue
+            echo "NVME $(basename $n) $( (sudo -n nvme smart-log $n -o json 2>/dev/null || nvme smart-log $n -o json 2>/dev/null) | tr -d '\n')"
+        done"#,
+        df = DF_EXCLUDE
+    );
+    let out = remote::exec(device_id, &cmd)?;
+
+    let mut filesystems: Vec<FilesystemInfo> = Vec::new();
+    let mut inodes: HashMap<String, (u64, u64)> = HashMap::new();
+    let mut disks: Vec<BlockDeviceInfo> = Vec::new();
+    let mut smart: HashMap<String, serde_json::Value> = HashMap::new();
+    let opt = |s: &str| {
+        if s == "-" {
+            None
+        } else {
+            Some(s.replace('_', " ").trim().to_string())
+        }
+    };
 
-    let flag = Arc::new(AtomicBool::new(true));
-    let token_clone = token.clone();
-    CHANNEL_STREAM_FLAGS
-        .lock()
-        .unwrap()
-        .insert(token_clone, flag.clone());
-
-    let token_for_thread = token.clone();
-    let handle = thread::spawn(move || {
-        while flag.load(Ordering::Relaxed) {
-            // Record stat and send through channel
-            if let Ok(point) = record_stat(&token_for_thread, Some(device_id)) {
-                // If send fails, client disconnected - stop streaming
-                if on_stat.send(point).is_err() {
-                    break;
+    for line in out.stdout.lines() {
+        let f: Vec<&str> = line.split_whitespace().collect();
+        match f.as_slice() {
+            ["FS", source, fstype, mount, size, used, avail, ..] => {
+                filesystems.push(FilesystemInfo {
+                    source: source.to_string(),
+                    fstype: fstype.to_string(),
+                    mount: mount.to_string(),
+                    size_kb: size.parse().unwrap_or(0),
+                    used_kb: used.parse().unwrap_or(0),
+                    avail_kb: avail.parse().unwrap_or(0),
+                    inodes_total: None,
+                    inodes_used: None,
+                });
+            }
+            ["IN", mount, total, used, ..] => {
+                if let (Ok(t), Ok(u)) = (total.parse(), used.parse()) {
+                    inodes.insert(mount.to_string(), (t, u));
+                }
+            }
+            ["BLK", name, sectors, dev_type, life, eol, model, ..] => {
+                let kind = if name.starts_with("mmcblk") {
+                    if *dev_type == "SD" {
+                        "sd"
+                    } else {
+                        "emmc"
+                    }
+                } else if name.starts_with("nvme") {
+                    "nvme"
+                } else {
+                    "disk"
+                };
+                disks.push(BlockDeviceInfo {
+                    name: name.to_string(),
+                    kind: kind.to_string(),
+                    model: opt(model),
+                    // /sys/block/*/size is in 512-byte sectors
+                    size_bytes: sectors.parse::<u64>().unwrap_or(0) * 512,
+                    life_time_est: opt(life),
+                    pre_eol: opt(eol),
+                    percent_used: None,
+                    temp_c: None,
+                    media_errors: None,
+                });
+            }
+            ["NVME", ctrl, ..] => {
+                let json = line.splitn(3, ' ').nth(2).unwrap_or("");
+                if let Ok(v) = serde_json::from_str::<serde_json::Value>(json) {
+                    smart.insert(ctrl.to_string(), v);
                 }
             }
-            thread::sleep(Duration::from_millis(interval));
+            _ => {}
         }
-    });
+    }
 
-    CHANNEL_STREAM_HANDLES.lock().unwrap().insert(token, handle);
+    for fs in filesystems.iter_mut() {
+        if let Some((t, u)) = inodes.get(&fs.mount) {
+            fs.inodes_total = Some(*t);
+            fs.inodes_used = Some(*u);
+        }
+    }
+    for disk in disks.iter_mut() {
+        // nvme0n1 -> controller nvme0
+        let ctrl = disk
+            .name
+            .rsplit_once('n')
+            .map_or(disk.name.as_str(), |(c, _)| c);
+        if let Some(v) = smart.get(ctrl) {
+            let num = |k: &str| v.get(k).and_then(|x| x.as_f64());
+            disk.percent_used = num("percent_used");
+            // smart-log reports the composite temperature in Kelvin
+            disk.temp_c = num("temperature").map(|k| k - 273.15);
+            disk.media_errors = num("media_errors").map(|e| e as u64);
+        }
+    }
 
-    Ok(())
+    Ok(FilesystemOverview { filesystems, disks })
+}
+
+fn channel_key(token: &str) -> String {
+    format!("channel:{}", token)
+}
+
+fn event_key(token: &str) -> String {
+    format!("event:{}", token)
+}
+
+/// Stream stats using Tauri channels (efficient, designed for streaming).
+/// Samples come from the device's shared collector, so several streams on one
+/// device cost a single SSH channel and store each sample once.
+#[tauri::command]
+pub fn stream_stats(
+    token: String,
+    device_id: i64,
+    interval_ms: Option<u64>,
+    on_stat: Channel<StatPoint>,
+) -> Result<(), String> {
+    // Replaces any existing stream for this token
+    collector::subscribe(
+        &channel_key(&token),
+        device_id,
+        interval_ms.unwrap_or(1000),
+        collector::Delivery::Channel(on_stat),
+    )
 }
 
 /// Stop channel-based stats streaming
 #[tauri::command]
 pub fn stop_stream_stats(token: String) -> Result<(), String> {
-    if let Some(flag) = CHANNEL_STREAM_FLAGS.lock().unwrap().remove(&token) {
-        flag.store(false, Ordering::Relaxed);
-    }
-    if let Some(handle) = CHANNEL_STREAM_HANDLES.lock().unwrap().remove(&token) {
-        let _ = handle.join();
-    }
+    collector::unsubscribe(&channel_key(&token));
     Ok(())
 }
 
-// Old event-based streaming (kept for backward compatibility, but channels are preferred)
+// Old event-based streaming (kept for backward compatibility, but channels are preferred).
+// Samples are emitted as "tegrastats://point" from the same shared collector.
 #[tauri::command]
 pub fn start_stats_stream(
     app: tauri::AppHandle,
@@ -284,49 +689,30 @@ pub fn start_stats_stream(
     device_id: Option<i64>,
     interval_ms: Option<u64>,
 ) -> Result<(), String> {
-    use tauri::Emitter;
-
-    let t = token.to_string();
-    let interval = interval_ms.unwrap_or(1000);
+    let key = event_key(token);
     // if already running, no-op
-    if STREAM_HANDLES.lock().unwrap().contains_key(&t) {
+    if collector::subscriptions().iter().any(|s| s.key == key) {
         return Ok(());
     }
-    let flag = Arc::new(AtomicBool::new(true));
-    STREAM_FLAGS.lock().unwrap().insert(t.clone(), flag.clone());
-    let app_handle = app.clone();
-    let tok = t.clone();
-    let did = device_id;
-    let handle = thread::spawn(move || {
-        loop {
-            if let Some(f) = STREAM_FLAGS.lock().unwrap().get(&tok) {
-                if !f.load(Ordering::Relaxed) {
-                    break;
-                }
-            } else {
-                break;
-            }
-            // best-effort: record stat and emit
-            if let Ok(point) = record_stat(&tok, did) {
-                // Silently ignore emit errors (happens when frontend reloads)
-                let _ = app_handle.emit("tegrastats://point", point);
-            }
-            thread::sleep(Duration::from_millis(interval));
-        }
-    });
-    STREAM_HANDLES.lock().unwrap().insert(t, handle);
-    Ok(())
+    let did = device_id
+        .or_else(|| token.parse::<i64>().ok())
+        .ok_or_else(|| "device id required".to_string())?;
+    collector::subscribe(
+        &key,
+        did,
+        interval_ms.unwrap_or(1000),
+        collector::Delivery::Event(app),
+    )
 }
 
 #[tauri::command]
 pub fn stop_stats_stream(token: &str) -> Result<(), String> {
-    let t = token.to_string();
-    if let Some(flag) = STREAM_FLAGS.lock().unwrap().remove(&t) {
-        flag.store(false, Ordering::Relaxed);
-    }
-    if let Some(handle) = STREAM_HANDLES.lock().unwrap().remove(&t) {
-        // Don't wait for join on disconnect - thread will stop on its own
-        let _ = handle.join();
-    }
+    collector::unsubscribe(&event_key(token));
     Ok(())
 }
+
+/// Active stats subscriptions across all devices, with the collector each one shares.
+#[tauri::command]
+pub fn list_stream_subscriptions() -> Result<Vec<Subscription>, String> {
+    Ok(collector::subscriptions())
+}
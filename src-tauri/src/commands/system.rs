@@ -1,32 +1,18 @@
 use crate::db::db_conn;
-use crate::session::SESSIONS;
-use crate::types::SystemInfo;
+use crate::remote;
+use crate::types::{
+    ClockDomain, ClockState, FanState, PowerModeChange, PowerModeDef, SetPowerModeResult,
+    SystemInfo,
+};
 use rusqlite::params;
-use std::io::Read;
 
 #[tauri::command]
 pub fn get_power_mode(device_id: i64) -> Result<String, String> {
-    // Get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(&device_id.to_string()).cloned()
-    }
-    .ok_or_else(|| "session not found".to_string())?;
-
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
-    };
-
-    // Query nvpmodel
-    let cmd =
-        "sh -lc 'sudo -n nvpmodel -q 2>/dev/null || nvpmodel -q 2>/dev/null || echo \"unknown\"'";
-    channel.exec(cmd).map_err(|e| e.to_string())?;
-    let mut out = String::new();
-    channel
-        .read_to_string(&mut out)
-        .map_err(|e| e.to_string())?;
-    let _ = channel.wait_close();
+    let out = remote::exec(
+        device_id,
+        "sh -lc 'sudo -n nvpmodel -q 2>/dev/null || nvpmodel -q'",
+    )?
+    .into_result()?;
 
     // Parse output - typically "NV Power Mode: MODE_NAME"
     if let Some(line) = out.lines().find(|l| l.contains("Power Mode")) {
@@ -38,141 +24,506 @@ pub fn get_power_mode(device_id: i64) -> Result<String, String> {
     Ok(out.trim().to_string())
 }
 
+/// Parse the power mode definitions out of `/etc/nvpmodel.conf`.
+pub(crate) fn parse_nvpmodel_conf(conf: &str) -> Vec<PowerModeDef> {
+    let mut modes: Vec<PowerModeDef> = Vec::new();
+    let mut default_id: Option<i32> = None;
+    let mut in_model = false;
+
+    for raw in conf.lines() {
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Section header, e.g. "< POWER_MODEL ID=0 NAME=MAXN >" or "< PM_CONFIG DEFAULT=2 >"
+        if line.starts_with('<') && line.ends_with('>') {
+            let inner = line.trim_start_matches('<').trim_end_matches('>');
+            let mut tokens = inner.split_whitespace();
+            let section = tokens.next().unwrap_or("");
+            let attr = |key: &str| {
+                inner
+                    .split_whitespace()
+                    .find_map(|t| t.strip_prefix(key).and_then(|v| v.strip_prefix('=')))
+                    .map(|v| v.to_string())
+            };
+            in_model = false;
+            match section {
+                "POWER_MODEL" => {
+                    if let Some(id) = attr("ID").and_then(|v| v.parse::<i32>().ok()) {
+                        let name = attr("NAME").unwrap_or_else(|| format!("MODE_{}", id));
+                        modes.push(PowerModeDef {
+                            id,
+                            power_budget_w: power_budget_from_name(&name),
+                            name,
+                            online_cpus: 0,
+                            cpu_max_freq_khz: None,
+                            gpu_max_freq_hz: None,
+                            emc_max_freq_hz: None,
+                            is_default: false,
+                        });
+                        in_model = true;
+                    }
+                }
+                "PM_CONFIG" => {
+                    default_id = attr("DEFAULT").and_then(|v| v.parse::<i32>().ok());
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        if !in_model {
+            continue;
+        }
+        let Some(mode) = modes.last_mut() else {
+            continue;
+        };
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 3 {
+            continue;
+        }
+        let (group, param, value) = (parts[0], parts[1], parts[2]);
+        if group == "CPU_ONLINE" {
+            if value == "1" {
+                mode.online_cpus += 1;
+            }
+            continue;
+        }
+        if param != "MAX_FREQ" {
+            continue;
+        }
+        // 0 and -1 both mean "no cap" in nvpmodel.conf
+        let freq = match value.parse::<i64>() {
+            Ok(v) if v > 0 => v,
+            _ => continue,
+        };
+        let slot = if group.starts_with("CPU") {
+            &mut mode.cpu_max_freq_khz
+        } else if group == "GPU" {
+            &mut mode.gpu_max_freq_hz
+        } else if group == "EMC" {
+            &mut mode.emc_max_freq_hz
+        } else {
+            continue;
+        };
+        *slot = Some(slot.map_or(freq, |cur| cur.max(freq)));
+    }
+
+    if let Some(id) = default_id {
+        for m in modes.iter_mut() {
+            m.is_default = m.id == id;
+        }
+    }
+    modes
+}
+
+/// Mode names usually carry their budget, e.g. "MODE_15W" or "10W_DESKTOP".
+fn power_budget_from_name(name: &str) -> Option<f64> {
+    name.split('_')
+        .filter_map(|t| t.strip_suffix('W').or_else(|| t.strip_suffix('w')))
+        .find_map(|n| n.parse::<f64>().ok())
+}
+
+/// Numeric id of the active mode, from the second line of `nvpmodel -q`.
+fn current_power_mode_id(device_id: i64) -> Result<i32, String> {
+    let out = remote::exec(
+        device_id,
+        "sudo -n nvpmodel -q 2>/dev/null || nvpmodel -q 2>/dev/null",
+    )?;
+    out.stdout
+        .lines()
+        .skip_while(|l| !l.contains("Power Mode"))
+        .find_map(|l| l.trim().parse::<i32>().ok())
+        .ok_or_else(|| "failed to read current power mode".to_string())
+}
+
 #[tauri::command]
-pub fn set_power_mode(device_id: i64, mode: i32) -> Result<(), String> {
-    // Get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(&device_id.to_string()).cloned()
+pub fn list_power_modes(device_id: i64) -> Result<Vec<PowerModeDef>, String> {
+    let out = remote::exec(device_id, "cat /etc/nvpmodel.conf")?.into_result()?;
+    let modes = parse_nvpmodel_conf(&out);
+    if modes.is_empty() {
+        return Err("no power modes defined in /etc/nvpmodel.conf".into());
     }
-    .ok_or_else(|| "session not found".to_string())?;
+    Ok(modes)
+}
 
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
+/// Switch nvpmodel mode. Some modes (e.g. ones changing the online CPU set) ask
+/// for a reboot; `reboot` answers that prompt, otherwise the change is left pending.
+#[tauri::command]
+pub fn set_power_mode(
+    device_id: i64,
+    mode: i32,
+    reboot: Option<bool>,
+) -> Result<SetPowerModeResult, String> {
+    let modes = list_power_modes(device_id)?;
+    let def = modes
+        .iter()
+        .find(|m| m.id == mode)
+        .cloned()
+        .ok_or_else(|| {
+            let ids: Vec<String> = modes
+                .iter()
+                .map(|m| format!("{} ({})", m.id, m.name))
+                .collect();
+            format!(
+                "power mode {} is not available on this device; valid modes: {}",
+                mode,
+                ids.join(", ")
+            )
+        })?;
+    let previous = current_power_mode_id(device_id).ok();
+
+    let confirm = reboot.unwrap_or(false);
+    let answer = if confirm { "YES" } else { "NO" };
+    let cmd = format!(
+        "if sudo -n true 2>/dev/null; then echo {a} | sudo -n nvpmodel -m {m}; else echo {a} | nvpmodel -m {m}; fi 2>&1",
+        a = answer,
+        m = mode
+    );
+    let result = remote::exec_tolerating_drop(device_id, &cmd)?;
+    let (output, reboot_required, applied) = match result {
+        Some(out) => {
+            let output = out.stdout.trim().to_string();
+            let reboot_required = output.to_lowercase().contains("reboot");
+            if out.exit_status != 0 && !reboot_required {
+                return Err(format!("nvpmodel failed: {}", output));
+            }
+            let applied = if reboot_required {
+                confirm
+            } else {
+                out.exit_status == 0
+            };
+            (output, reboot_required, applied)
+        }
+        // With a confirmed reboot nvpmodel usually takes the connection down
+        // before its output arrives; the mode is applied on the way down
+        None if confirm => (
+            "connection closed while the device rebooted".to_string(),
+            true,
+            true,
+        ),
+        None => return Err("connection lost while setting the power mode".to_string()),
     };
 
-    // Set power mode
+    if applied {
+        let conn = db_conn()?;
+        conn.execute(
+            "INSERT INTO power_mode_change (device_id, ts, mode_id, mode_name, previous_mode_id, reboot_required) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                device_id,
+                chrono::Utc::now().timestamp_millis(),
+                def.id,
+                &def.name,
+                previous,
+                reboot_required
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    Ok(SetPowerModeResult {
+        mode: def,
+        applied,
+        reboot_required,
+        output,
+    })
+}
+
+#[tauri::command]
+pub fn get_power_mode_history(
+    device_id: i64,
+    limit: Option<i64>,
+) -> Result<Vec<PowerModeChange>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare("SELECT id, device_id, ts, mode_id, mode_name, previous_mode_id, reboot_required FROM power_mode_change WHERE device_id = ?1 ORDER BY ts DESC LIMIT ?2")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![device_id, limit.unwrap_or(100)], |row| {
+            Ok(PowerModeChange {
+                id: row.get(0)?,
+                device_id: row.get(1)?,
+                ts: row.get(2)?,
+                mode_id: row.get(3)?,
+                mode_name: row.get(4)?,
+                previous_mode_id: row.get(5)?,
+                reboot_required: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// jetson_clocks keeps its saved state here so `--restore` can undo a lock
+const JETSON_CLOCKS_STORE: &str = "$HOME/.orion/jetson_clocks.conf";
+
+fn jetson_clocks(device_id: i64, args: &str) -> Result<String, String> {
     let cmd = format!(
-        "sh -lc 'sudo -n nvpmodel -m {} 2>/dev/null || nvpmodel -m {} 2>/dev/null'",
-        mode, mode
+        "mkdir -p \"$HOME/.orion\" && sudo -n jetson_clocks {} 2>&1",
+        args
     );
-    channel.exec(&cmd).map_err(|e| e.to_string())?;
-    let mut out = String::new();
-    channel
-        .read_to_string(&mut out)
-        .map_err(|e| e.to_string())?;
-    let _ = channel.wait_close();
+    remote::exec(device_id, &cmd)?.into_result()
+}
 
-    // Check exit status
-    if let Ok(exit) = channel.exit_status() {
-        if exit != 0 {
-            return Err(format!("nvpmodel failed: {}", out));
+/// Pin CPU, GPU and EMC clocks to their maximum. The pre-lock state is stored
+/// first (unless a store already exists) so it can be restored later.
+#[tauri::command]
+pub fn jetson_clocks_enable(device_id: i64) -> Result<ClockState, String> {
+    let store = format!(
+        "test -f \"{0}\" || sudo -n jetson_clocks --store \"{0}\" 2>&1",
+        JETSON_CLOCKS_STORE
+    );
+    remote::exec(
+        device_id,
+        &format!("mkdir -p \"$HOME/.orion\" && {}", store),
+    )?
+    .into_result()?;
+    jetson_clocks(device_id, "")?;
+    get_clock_state(device_id)
+}
+
+#[tauri::command]
+pub fn jetson_clocks_store(device_id: i64) -> Result<String, String> {
+    jetson_clocks(device_id, &format!("--store \"{}\"", JETSON_CLOCKS_STORE))
+}
+
+#[tauri::command]
+pub fn jetson_clocks_restore(device_id: i64) -> Result<ClockState, String> {
+    jetson_clocks(device_id, &format!("--restore \"{}\"", JETSON_CLOCKS_STORE))?;
+    get_clock_state(device_id)
+}
+
+/// Current versus max frequencies for each CPU cluster, the GPU and EMC, read from sysfs.
+#[tauri::command]
+pub fn get_clock_state(device_id: i64) -> Result<ClockState, String> {
+    // One line per domain: kind name cur min max hwmax ("-" when unreadable)
+    let cmd = r#"r() { v=$(cat "$1" 2>/dev/null || sudo -n cat "$1" 2>/dev/null); echo "${v:--}"; }
+        for p in /sys/devices/system/cpu/cpufreq/policy*; do
+            [ -d "$p" ] || continue
+            echo "cpu $(basename "$p") $(r $p/scaling_cur_freq) $(r $p/scaling_min_freq) $(r $p/scaling_max_freq) $(r $p/cpuinfo_max_freq)"
+        done
+        for d in /sys/class/devfreq/*; do
+            case "$(basename "$d")" in *gpu*|*gp10b*|*gv11b*|*ga10b*) ;; *) continue ;; esac
+            hw=$(tr " " "\n" < "$d/available_frequencies" 2>/dev/null | sort -n | tail -n1)
+            echo "gpu $(basename "$d") $(r $d/cur_freq) $(r $d/min_freq) $(r $d/max_freq) ${hw:--}"
+            break
+        done
+        e=/sys/kernel/debug/bpmp/debug/clk/emc
+        if sudo -n test -d $e 2>/dev/null; then
+            echo "emc emc $(r $e/rate) $(r $e/min_rate) $(r $e/max_rate) -"
+        else
+            e=/sys/kernel/debug/clk/emc
+            sudo -n test -d $e 2>/dev/null && echo "emc emc $(r $e/clk_rate) - $(r $e/clk_max_rate) -"
+        fi"#;
+    let out = remote::exec(device_id, cmd)?;
+
+    let mut cpu: Vec<ClockDomain> = Vec::new();
+    let mut gpu: Option<ClockDomain> = None;
+    let mut emc: Option<ClockDomain> = None;
+    for line in out.stdout.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 6 {
+            continue;
         }
+        // cpufreq reports kHz; devfreq and the clk debugfs report Hz
+        let scale = if parts[0] == "cpu" { 1000 } else { 1 };
+        let num = |s: &str| s.parse::<i64>().ok().map(|v| v * scale);
+        let domain = ClockDomain {
+            name: parts[1].to_string(),
+            cur_hz: num(parts[2]),
+            min_hz: num(parts[3]),
+            max_hz: num(parts[4]),
+            hw_max_hz: num(parts[5]),
+        };
+        match parts[0] {
+            "cpu" => cpu.push(domain),
+            "gpu" => gpu = Some(domain),
+            "emc" => emc = Some(domain),
+            _ => {}
+        }
+    }
+    if cpu.is_empty() {
+        return Err("no cpufreq policies found".into());
     }
 
-    Ok(())
+    // jetson_clocks pins min to max on every domain it manages
+    let pinned = |d: &ClockDomain| d.min_hz.is_some() && d.min_hz == d.max_hz;
+    let locked = cpu.iter().all(pinned) && gpu.as_ref().is_none_or(pinned);
+
+    Ok(ClockState {
+        locked,
+        cpu,
+        gpu,
+        emc,
+    })
+}
+
+/// Prints "<pwm> <rpm>" ("-" when unreadable) for the pwm-fan on Orin
+/// (hwmon pwm1) and Xavier/Nano (cur_pwm). Contains no single quotes so it
+/// can be embedded in `sh -lc '...'`.
+pub(crate) const FAN_READ_SH: &str = "p=$(ls /sys/devices/platform/pwm-fan/hwmon/hwmon*/pwm1 /sys/devices/pwm-fan/cur_pwm 2>/dev/null | head -n1); r=$(ls /sys/class/hwmon/hwmon*/rpm /sys/devices/pwm-fan/rpm_measured 2>/dev/null | head -n1); echo $(cat ${p:-/nonexistent} 2>/dev/null || echo -) $(cat ${r:-/nonexistent} 2>/dev/null || echo -)";
+
+// Manual PWM limits: never stop the fan outright, and require at least half
+// speed while any thermal zone is above HOT_TEMP_C.
+const MIN_MANUAL_PWM: i64 = 40;
+const HOT_MIN_PWM: i64 = 128;
+const HOT_TEMP_C: f64 = 70.0;
+
+/// Parse the output of FAN_READ_SH into (pwm, rpm).
+pub(crate) fn parse_fan_line(line: &str) -> (Option<i64>, Option<i64>) {
+    let mut it = line.split_whitespace().map(|s| s.parse::<i64>().ok());
+    (it.next().flatten(), it.next().flatten())
 }
 
 #[tauri::command]
-pub fn shutdown(device_id: i64) -> Result<String, String> {
-    // Get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(&device_id.to_string()).cloned()
+pub fn get_fan_state(device_id: i64) -> Result<FanState, String> {
+    let cmd = format!(
+        r#"{}
+        echo "active=$(systemctl is-active nvfancontrol 2>/dev/null)"
+        awk '$1 == "FAN_PROFILE" {{print "status=" $2}}' /var/lib/nvfancontrol/status 2>/dev/null
+        awk '$1 == "FAN_PROFILE" && $3 == "{{" {{print "profile=" $2}}' /etc/nvfancontrol.conf 2>/dev/null"#,
+        FAN_READ_SH
+    );
+    let out = remote::exec(device_id, &cmd)?;
+
+    let mut lines = out.stdout.lines();
+    let (pwm, rpm) = parse_fan_line(lines.next().unwrap_or(""));
+    let mut active = false;
+    let mut profile: Option<String> = None;
+    let mut profiles: Vec<String> = Vec::new();
+    for line in lines {
+        if let Some(v) = line.strip_prefix("active=") {
+            active = v.trim() == "active";
+        } else if let Some(v) = line.strip_prefix("status=") {
+            profile = Some(v.trim().to_string());
+        } else if let Some(v) = line.strip_prefix("profile=") {
+            profiles.push(v.trim().to_string());
+        }
+    }
+    if pwm.is_none() && rpm.is_none() {
+        return Err("no pwm fan found on device".into());
     }
-    .ok_or_else(|| "session not found".to_string())?;
-
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
-    };
 
-    let cmd = "sudo -n shutdown";
+    Ok(FanState {
+        pwm,
+        rpm,
+        profile: if active { profile } else { None },
+        profiles,
+        control: if active { "auto" } else { "manual" }.to_string(),
+    })
+}
 
-    channel.exec(cmd).map_err(|e| e.to_string())?;
+/// Switch the nvfancontrol profile (e.g. "quiet" or "cool"). This also hands
+/// control back to nvfancontrol after a manual PWM override.
+#[tauri::command]
+pub fn set_fan_profile(device_id: i64, profile: &str) -> Result<FanState, String> {
+    let state = get_fan_state(device_id)?;
+    if !state.profiles.iter().any(|p| p == profile) {
+        return Err(format!(
+            "unknown fan profile {}; available: {}",
+            profile,
+            state.profiles.join(", ")
+        ));
+    }
+    let cmd = format!(
+        "sudo -n systemctl stop nvfancontrol && sudo -n sed -i 's/^FAN_DEFAULT_PROFILE .*/FAN_DEFAULT_PROFILE {}/' /etc/nvfancontrol.conf && sudo -n rm -f /var/lib/nvfancontrol/status && sudo -n systemctl start nvfancontrol",
+        profile
+    );
+    remote::exec(device_id, &cmd)?.into_result()?;
+    get_fan_state(device_id)
+}
 
-    // Read stdout, which will contain the broadcast message on success
-    let mut stdout = String::new();
-    channel
-        .read_to_string(&mut stdout)
-        .map_err(|e| e.to_string())?;
+/// Stop nvfancontrol and drive the fan at a fixed PWM (0-255), within safety limits.
+#[tauri::command]
+pub fn set_fan_pwm(device_id: i64, pwm: i64) -> Result<FanState, String> {
+    if !(MIN_MANUAL_PWM..=255).contains(&pwm) {
+        return Err(format!(
+            "fan pwm must be between {} and 255",
+            MIN_MANUAL_PWM
+        ));
+    }
 
-    // Read stderr to capture any errors (e.g., "sudo: a password is required")
-    let mut stderr = String::new();
-    channel
-        .stderr()
-        .read_to_string(&mut stderr)
-        .map_err(|e| e.to_string())?;
+    // Hottest thermal zone; PMIC zones report a fixed placeholder and are skipped
+    let temps = remote::exec(
+        device_id,
+        r#"for z in /sys/class/thermal/thermal_zone*; do case "$(cat $z/type)" in PMIC*) continue ;; esac; cat $z/temp 2>/dev/null; done"#,
+    )?;
+    let max_temp_c = temps
+        .stdout
+        .lines()
+        .filter_map(|l| l.trim().parse::<f64>().ok())
+        .map(|t| t / 1000.0)
+        .reduce(f64::max);
+    // An unreadable temperature is treated as hot
+    match max_temp_c {
+        Some(t) if t > HOT_TEMP_C && pwm < HOT_MIN_PWM => {
+            return Err(format!(
+                "device is at {:.1}C; fan pwm must be at least {} above {}C",
+                t, HOT_MIN_PWM, HOT_TEMP_C
+            ));
+        }
+        None if pwm < HOT_MIN_PWM => {
+            return Err(format!(
+                "could not read the device temperature; fan pwm must be at least {}",
+                HOT_MIN_PWM
+            ));
+        }
+        _ => {}
+    }
 
-    // Wait for the channel to close.
-    // This will work fine now, as the command returns immediately
-    // while the shutdown is scheduled in the background.
-    let _ = channel.wait_close();
+    let cmd = format!(
+        r#"t=$(ls /sys/devices/platform/pwm-fan/hwmon/hwmon*/pwm1 /sys/devices/pwm-fan/target_pwm 2>/dev/null | head -n1)
+        [ -n "$t" ] || {{ echo "no pwm fan found on device" >&2; exit 1; }}
+        sudo -n systemctl stop nvfancontrol 2>/dev/null
+        echo {} | sudo -n tee "$t" >/dev/null"#,
+        pwm
+    );
+    remote::exec(device_id, &cmd)?.into_result()?;
+    get_fan_state(device_id)
+}
 
-    // Get the command's exit status
-    let exit_status = channel
-        .exit_status()
-        .map_err(|e| format!("Failed to get exit status: {}", e.to_string()))?;
+#[tauri::command]
+pub fn shutdown(device_id: i64) -> Result<String, String> {
+    // stdout holds the broadcast message on success, stderr the reason on
+    // failure (e.g. "sudo: a password is required")
+    let out = remote::exec(device_id, "sudo -n shutdown")?;
 
     // Check if the command failed
-    if exit_status != 0 {
+    if out.exit_status != 0 {
         // If stderr has an error message, return it
-        if !stderr.is_empty() {
-            return Err(stderr.trim().to_string());
+        if !out.stderr.trim().is_empty() {
+            return Err(out.stderr.trim().to_string());
         }
         // Otherwise, return a generic error
         return Err(format!(
             "Shutdown command failed with exit status: {}",
-            exit_status
+            out.exit_status
         ));
     }
 
     // Success! Return the message from stdout.
     // If stdout is empty, return a default success message.
-    if stdout.is_empty() {
+    if out.stdout.trim().is_empty() {
         Ok("Shutdown scheduled successfully.".to_string())
     } else {
-        Ok(stdout.trim().to_string())
+        Ok(out.stdout.trim().to_string())
     }
 }
 
 #[tauri::command]
 pub fn reboot(device_id: i64) -> Result<(), String> {
-    // Get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(&device_id.to_string()).cloned()
+    match remote::exec_tolerating_drop(device_id, "sh -lc 'sudo -n reboot || reboot'")? {
+        Some(out) => out.into_result().map(|_| ()),
+        // The connection usually drops before the exit status arrives
+        None => Ok(()),
     }
-    .ok_or_else(|| "session not found".to_string())?;
-
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
-    };
-
-    let cmd = "sh -lc 'sudo -n reboot 2>/dev/null || reboot 2>/dev/null'";
-    channel.exec(cmd).map_err(|e| e.to_string())?;
-    let _ = channel.wait_close();
-
-    Ok(())
 }
 
 // Fetch live data and save it.
 #[tauri::command]
 pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
-    // Get session
-    let handle = {
-        let map = SESSIONS.lock();
-        map.get(&device_id.to_string()).cloned()
-    }
-    .ok_or_else(|| "session not found".to_string())?;
-
-    let mut channel = {
-        let sess = handle.session.lock();
-        sess.channel_session().map_err(|e| e.to_string())?
-    };
-
     // Run command to fetch system info
     let cmd = r#"sh -lc '
         hostname=$(hostname)
@@ -181,6 +532,7 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
         cuda=$(nvcc --version 2>/dev/null | grep "release" | sed -E "s/.*release ([0-9.]+).*/\1/" || echo "")
         jetpack=$(dpkg -l | grep nvidia-jetpack | awk "{print \$3}" | head -1 || echo "")
         uptime_sec=$(cat /proc/uptime | awk "{print int(\$1)}")
+        model=$(tr -d "\0" < /proc/device-tree/model 2>/dev/null || echo "")
         
         echo "$hostname"
         echo "$os"
@@ -188,16 +540,10 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
         echo "$cuda"
         echo "$jetpack"
         echo "$uptime_sec"
+        echo "$model"
     '"#;
 
-    // --- THIS IS THE MISSING BLOCK ---
-    channel.exec(cmd).map_err(|e| e.to_string())?;
-    let mut out = String::new();
-    channel
-        .read_to_string(&mut out)
-        .map_err(|e| e.to_string())?;
-    let _ = channel.wait_close();
-    // ---------------------------------
+    let out = remote::exec(device_id, cmd)?.stdout;
 
     let lines: Vec<&str> = out.lines().collect();
     let now = chrono::Utc::now().timestamp_millis();
@@ -232,6 +578,10 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
             .get(5)
             .and_then(|s| s.parse::<u64>().ok())
             .unwrap_or(0),
+        model: lines
+            .get(6)
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty()),
     };
 
     // Save to database
@@ -242,7 +592,7 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
 
         // Try to update existing record first
         let updated = conn.execute(
-                "UPDATE system_info SET hostname = ?1, os = ?2, kernel = ?3, cuda = ?4, jetpack = ?5, uptime_sec = ?6, updated_at = ?7 WHERE device_id = ?8",
+                "UPDATE system_info SET hostname = ?1, os = ?2, kernel = ?3, cuda = ?4, jetpack = ?5, uptime_sec = ?6, updated_at = ?7, model = ?8 WHERE device_id = ?9",
                 params![
                     &sys_info.hostname,
                     &sys_info.os,
@@ -251,6 +601,7 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
                     &sys_info.jetpack,
                     uptime_i64,
                     updated_at_i64,
+                    &sys_info.model,
                     device_id
                 ],
             ).unwrap_or(0);
@@ -258,7 +609,7 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
         // If no rows updated, insert new record
         if updated == 0 {
             let _ = conn.execute(
-                    "INSERT INTO system_info (device_id, hostname, os, kernel, cuda, jetpack, uptime_sec, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                    "INSERT INTO system_info (device_id, hostname, os, kernel, cuda, jetpack, uptime_sec, updated_at, model) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
                     params![
                         device_id,
                         &sys_info.hostname,
@@ -267,7 +618,8 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
                         &sys_info.cuda,
                         &sys_info.jetpack,
                         uptime_i64,
-                        updated_at_i64
+                        updated_at_i64,
+                        &sys_info.model
                     ],
                 );
         }
@@ -281,7 +633,7 @@ pub fn fetch_and_store_sys_info(device_id: i64) -> Result<SystemInfo, String> {
 pub fn get_stored_sys_info(device_id: i64) -> Result<Option<SystemInfo>, String> {
     let conn = db_conn()?;
     let mut stmt = conn
-        .prepare("SELECT id, device_id, hostname, os, kernel, cuda, jetpack, uptime_sec, updated_at FROM system_info WHERE device_id = ?1")
+        .prepare("SELECT id, device_id, hostname, os, kernel, cuda, jetpack, uptime_sec, updated_at, model FROM system_info WHERE device_id = ?1")
         .map_err(|e| e.to_string())?;
 
     let result = stmt.query_row([device_id], |row| {
@@ -298,6 +650,7 @@ pub fn get_stored_sys_info(device_id: i64) -> Result<Option<SystemInfo>, String>
             cuda: row.get("cuda")?,
             jetpack: row.get("jetpack")?,
             uptime_sec: row.get::<_, i64>("uptime_sec")? as u64, // Cast back to u64
+            model: row.get("model")?,
         })
     });
 
@@ -0,0 +1,379 @@
+use crate::commands::runs::launch;
+use crate::db::db_conn;
+use crate::remote::{self, shell_quote};
+use crate::trtexec;
+use crate::types::{BenchmarkRun, OnnxModel, RunOutput, TrtBuildOptions, TrtLatency, TrtResult};
+use log::warn;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::path::Path;
+use tauri::ipc::Channel;
+
+// Models and engines live under the login user's home, where exec and scp both start
+const TRT_ROOT: &str = ".orion/trt";
+// Where JetPack installs trtexec; it is usually not on PATH
+const TRTEXEC_FALLBACK: &str = "/usr/src/tensorrt/bin/trtexec";
+
+/// Model names become directory names on the device: keep them to a safe charset.
+fn model_name(raw: &str) -> Result<String, String> {
+    let name: String = raw
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.is_empty() || name.starts_with('.') {
+        return Err(format!("invalid model name: {:?}", raw));
+    }
+    Ok(name)
+}
+
+fn model_dir(name: &str) -> String {
+    format!("{}/{}", TRT_ROOT, name)
+}
+
+fn onnx_path(name: &str) -> String {
+    format!("{}/model.onnx", model_dir(name))
+}
+
+/// Upload a local ONNX file as `name` (default: the file name without extension).
+/// Uploading under an existing name replaces that model.
+#[tauri::command]
+pub fn upload_onnx_model(
+    device_id: i64,
+    local_path: String,
+    name: Option<String>,
+) -> Result<OnnxModel, String> {
+    let path = Path::new(&local_path);
+    if !path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("onnx"))
+    {
+        return Err(format!("not an .onnx file: {}", local_path));
+    }
+    let size_bytes = std::fs::metadata(path)
+        .map_err(|e| format!("failed to read {}: {}", local_path, e))?
+        .len();
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let name = model_name(name.as_deref().unwrap_or(stem))?;
+
+    remote::exec(
+        device_id,
+        &format!("mkdir -p {}", shell_quote(&model_dir(&name))),
+    )?
+    .into_result()?;
+    let remote_path = onnx_path(&name);
+    remote::upload(device_id, path, &remote_path)?;
+    Ok(OnnxModel {
+        device_id,
+        name,
+        remote_path,
+        size_bytes,
+    })
+}
+
+/// Models uploaded to the device.
+#[tauri::command]
+pub fn list_onnx_models(device_id: i64) -> Result<Vec<OnnxModel>, String> {
+    let cmd = format!(
+        "for f in {}/*/model.onnx; do [ -f \"$f\" ] && echo \"$(stat -c %s \"$f\") $f\"; done; true",
+        TRT_ROOT
+    );
+    let out = remote::exec(device_id, &cmd)?.into_result()?;
+    let mut models: Vec<OnnxModel> = out
+        .lines()
+        .filter_map(|l| {
+            let (size, path) = l.trim().split_once(' ')?;
+            let name = path
+                .strip_prefix(TRT_ROOT)?
+                .trim_start_matches('/')
+                .strip_suffix("/model.onnx")?;
+            Some(OnnxModel {
+                device_id,
+                name: name.to_string(),
+                remote_path: path.to_string(),
+                size_bytes: size.parse().ok()?,
+            })
+        })
+        .collect();
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+fn precision_of(options: &TrtBuildOptions) -> Result<String, String> {
+    let precision = options
+        .precision
+        .as_deref()
+        .unwrap_or("fp32")
+        .trim()
+        .to_lowercase();
+    match precision.as_str() {
+        "fp32" | "fp16" | "int8" | "best" => Ok(precision),
+        other => Err(format!(
+            "unknown precision {:?}: use fp32, fp16, int8 or best",
+            other
+        )),
+    }
+}
+
+/// trtexec invocation for a build followed by its default profiling pass.
+fn build_command(onnx: &str, engine: &str, precision: &str, options: &TrtBuildOptions) -> String {
+    let mut args = vec![
+        format!("--onnx={}", shell_quote(onnx)),
+        format!("--saveEngine={}", shell_quote(engine)),
+    ];
+    match precision {
+        "fp16" => args.push("--fp16".to_string()),
+        // Without a calibration cache trtexec uses placeholder scales: fine for
+        // timing, not for accuracy
+        "int8" => args.push("--int8".to_string()),
+        "best" => args.push("--best".to_string()),
+        _ => {}
+    }
+    // --memPoolSize arrived in TensorRT 8.4; JetPack 4 releases only take
+    // --workspace, so ask trtexec which one it knows
+    let mut probe = String::new();
+    if let Some(mb) = options.workspace_mb {
+        probe = format!(
+            "W=--workspace={mb}; \"$T\" --help 2>&1 | grep -q -- --memPoolSize && W=--memPoolSize=workspace:{mb}; ",
+            mb = mb
+        );
+        args.push("\"$W\"".to_string());
+    }
+    let shapes = [
+        ("minShapes", &options.min_shapes),
+        ("optShapes", &options.opt_shapes),
+        ("maxShapes", &options.max_shapes),
+    ];
+    for (flag, value) in shapes {
+        if let Some(v) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+            args.push(format!("--{}={}", flag, shell_quote(v)));
+        }
+    }
+    if let Some(core) = options.dla_core {
+        args.push(format!("--useDLACore={}", core));
+        args.push("--allowGPUFallback".to_string());
+    }
+    format!(
+        "T=$(command -v trtexec || echo {}); {}\"$T\" {}",
+        TRTEXEC_FALLBACK,
+        probe,
+        args.join(" ")
+    )
+}
+
+/// Build a TensorRT engine from an uploaded model with trtexec and profile it.
+/// The build is recorded as a benchmark run: progress streams over `on_output`
+/// and the returned run id tags the stats sampled meanwhile. When it ends, the
+/// parsed performance summary is stored and listed by `list_trt_results`.
+#[tauri::command]
+pub fn build_trt_engine(
+    device_id: i64,
+    model: String,
+    options: Option<TrtBuildOptions>,
+    on_output: Channel<RunOutput>,
+) -> Result<i64, String> {
+    let options = options.unwrap_or_default();
+    let name = model_name(&model)?;
+    let precision = precision_of(&options)?;
+    let onnx = onnx_path(&name);
+    remote::exec(device_id, &format!("test -f {}", shell_quote(&onnx)))?
+        .into_result()
+        .map_err(|_| format!("model {} has not been uploaded to this device", name))?;
+
+    let dla = options
+        .dla_core
+        .map(|c| format!("_dla{}", c))
+        .unwrap_or_default();
+    let engine = format!(
+        "{}/{}_{}{}_{}.engine",
+        model_dir(&name),
+        name,
+        precision,
+        dla,
+        chrono::Utc::now().format("%Y%m%d-%H%M%S")
+    );
+    let command = build_command(&onnx, &engine, &precision, &options);
+    let run_name = format!("trtexec {} {}{}", name, precision, dla);
+
+    launch(
+        device_id,
+        command,
+        Some(run_name),
+        options.sample_interval_ms,
+        on_output,
+        move |conn, run, output| {
+            let build = Build {
+                model: &name,
+                onnx: &onnx,
+                engine: &engine,
+                precision: &precision,
+                options: &options,
+            };
+            if let Err(e) = store_result(conn, run, &build, output) {
+                warn!("failed to store trtexec result of run {}: {}", run.id, e);
+            }
+        },
+    )
+}
+
+struct Build<'a> {
+    model: &'a str,
+    onnx: &'a str,
+    engine: &'a str,
+    precision: &'a str,
+    options: &'a TrtBuildOptions,
+}
+
+fn store_result(
+    conn: &Connection,
+    run: &BenchmarkRun,
+    build: &Build,
+    output: &str,
+) -> Result<(), String> {
+    let summary = trtexec::parse(output);
+    let built = run.status == "succeeded";
+    let status = if built { "built" } else { run.status.as_str() };
+    let (l, g) = (&summary.latency, &summary.gpu_compute);
+    conn.execute(
+        "INSERT INTO trt_result (device_id, run_id, model, onnx_path, engine_path, precision, workspace_mb, min_shapes, opt_shapes, max_shapes, dla_core, status, trt_version, throughput_qps,
+            latency_min, latency_mean, latency_median, latency_p90, latency_p95, latency_p99, latency_max,
+            gpu_min, gpu_mean, gpu_median, gpu_p90, gpu_p95, gpu_p99, gpu_max, h2d_mean_ms, d2h_mean_ms, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31)",
+        params![
+            run.device_id,
+            run.id,
+            build.model,
+            build.onnx,
+            built.then_some(build.engine),
+            build.precision,
+            build.options.workspace_mb,
+            build.options.min_shapes,
+            build.options.opt_shapes,
+            build.options.max_shapes,
+            build.options.dla_core,
+            status,
+            summary.trt_version,
+            summary.throughput_qps,
+            l.min,
+            l.mean,
+            l.median,
+            l.p90,
+            l.p95,
+            l.p99,
+            l.max,
+            g.min,
+            g.mean,
+            g.median,
+            g.p90,
+            g.p95,
+            g.p99,
+            g.max,
+            summary.h2d_mean_ms,
+            summary.d2h_mean_ms,
+            run.ended_at.unwrap_or(run.started_at)
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+const RESULT_COLUMNS: &str = "id, device_id, run_id, model, onnx_path, engine_path, precision, workspace_mb, min_shapes, opt_shapes, max_shapes, dla_core, status, trt_version, throughput_qps,
+    latency_min, latency_mean, latency_median, latency_p90, latency_p95, latency_p99, latency_max,
+    gpu_min, gpu_mean, gpu_median, gpu_p90, gpu_p95, gpu_p99, gpu_max, h2d_mean_ms, d2h_mean_ms, created_at";
+
+fn latency_at(row: &Row, first: usize) -> rusqlite::Result<TrtLatency> {
+    Ok(TrtLatency {
+        min: row.get(first)?,
+        mean: row.get(first + 1)?,
+        median: row.get(first + 2)?,
+        p90: row.get(first + 3)?,
+        p95: row.get(first + 4)?,
+        p99: row.get(first + 5)?,
+        max: row.get(first + 6)?,
+    })
+}
+
+fn result_from_row(row: &Row) -> rusqlite::Result<TrtResult> {
+    Ok(TrtResult {
+        id: row.get(0)?,
+        device_id: row.get(1)?,
+        run_id: row.get(2)?,
+        model: row.get(3)?,
+        onnx_path: row.get(4)?,
+        engine_path: row.get(5)?,
+        precision: row.get(6)?,
+        workspace_mb: row.get(7)?,
+        min_shapes: row.get(8)?,
+        opt_shapes: row.get(9)?,
+        max_shapes: row.get(10)?,
+        dla_core: row.get(11)?,
+        status: row.get(12)?,
+        trt_version: row.get(13)?,
+        throughput_qps: row.get(14)?,
+        latency: latency_at(row, 15)?,
+        gpu_compute: latency_at(row, 22)?,
+        h2d_mean_ms: row.get(29)?,
+        d2h_mean_ms: row.get(30)?,
+        created_at: row.get(31)?,
+    })
+}
+
+fn load_result(conn: &Connection, result_id: i64) -> Result<TrtResult, String> {
+    conn.query_row(
+        &format!("SELECT {} FROM trt_result WHERE id = ?1", RESULT_COLUMNS),
+        [result_id],
+        result_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("TensorRT result {} not found", result_id))
+}
+
+/// Stored builds, newest first, filtered by device and/or model.
+#[tauri::command]
+pub fn list_trt_results(
+    device_id: Option<i64>,
+    model: Option<String>,
+) -> Result<Vec<TrtResult>, String> {
+    let conn = db_conn()?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM trt_result WHERE (?1 IS NULL OR device_id = ?1) AND (?2 IS NULL OR model = ?2) ORDER BY created_at DESC",
+            RESULT_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![device_id, model], result_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Copy a built engine from the device to `local_path`. Returns its size in bytes.
+#[tauri::command]
+pub fn download_trt_engine(result_id: i64, local_path: String) -> Result<u64, String> {
+    let conn = db_conn()?;
+    let result = load_result(&conn, result_id)?;
+    let engine = result
+        .engine_path
+        .ok_or_else(|| format!("build {} did not produce an engine", result_id))?;
+    remote::download(result.device_id, &engine, Path::new(&local_path))
+}
+
+/// Forget a build; `delete_engine` also removes the engine file from the device.
+#[tauri::command]
+pub fn delete_trt_result(result_id: i64, delete_engine: Option<bool>) -> Result<(), String> {
+    let conn = db_conn()?;
+    let result = load_result(&conn, result_id)?;
+    if let (true, Some(engine)) = (delete_engine.unwrap_or(false), &result.engine_path) {
+        remote::exec(result.device_id, &format!("rm -f {}", shell_quote(engine)))?.into_result()?;
+    }
+    conn.execute("DELETE FROM trt_result WHERE id = ?1", [result_id])
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
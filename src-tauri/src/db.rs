@@ -53,14 +53,227 @@ pub fn init_db() {
                 gpu_util REAL,
                 gpu_temp_c REAL,
                 power_mode TEXT,
+                clocks_locked INTEGER,
+                fan_rpm INTEGER,
+                fan_pwm INTEGER,
                 device_id INTEGER NOT NULL
             )",
             [],
         );
+        // Columns added after the initial schema; errors mean they already exist
+        let _ = conn.execute(
+            "ALTER TABLE device_stats ADD COLUMN clocks_locked INTEGER",
+            [],
+        );
+        let _ = conn.execute("ALTER TABLE device_stats ADD COLUMN fan_rpm INTEGER", []);
+        let _ = conn.execute("ALTER TABLE device_stats ADD COLUMN fan_pwm INTEGER", []);
+        // Benchmark run the sample was taken during, see runs.rs
+        let _ = conn.execute("ALTER TABLE device_stats ADD COLUMN run_id INTEGER", []);
         let _ = conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_device_stats_device_ts ON device_stats(device_id, ts)",
             [],
         );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_device_stats_run ON device_stats(run_id) WHERE run_id IS NOT NULL",
+            [],
+        );
+
+        // metric_series + device_metrics - long/narrow storage for any named series,
+        // so new metrics don't need schema changes
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS metric_series (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL UNIQUE,
+                unit TEXT NOT NULL
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS device_metrics (
+                device_id INTEGER NOT NULL,
+                ts INTEGER NOT NULL,
+                series_id INTEGER NOT NULL,
+                value REAL NOT NULL
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_device_metrics_device_series_ts ON device_metrics(device_id, series_id, ts)",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_device_metrics_device_ts ON device_metrics(device_id, ts)",
+            [],
+        );
+
+        // metric_rollup - 1-minute and 1-hour min/avg/max per series, built from the
+        // raw tables by the compaction job in retention.rs
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS metric_rollup (
+                resolution_ms INTEGER NOT NULL,
+                device_id INTEGER NOT NULL,
+                series_id INTEGER NOT NULL,
+                ts INTEGER NOT NULL,
+                samples INTEGER NOT NULL,
+                min REAL NOT NULL,
+                avg REAL NOT NULL,
+                max REAL NOT NULL,
+                PRIMARY KEY (resolution_ms, device_id, series_id, ts)
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_metric_rollup_resolution_ts ON metric_rollup(resolution_ms, ts)",
+            [],
+        );
+        // rollup_watermark - everything before rolled_until has been rolled up
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS rollup_watermark (
+                resolution_ms INTEGER PRIMARY KEY,
+                rolled_until INTEGER NOT NULL
+            )",
+            [],
+        );
+        // retention_policy - single row, see retention::DEFAULT_POLICY
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS retention_policy (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                raw_days INTEGER NOT NULL,
+                minute_days INTEGER,
+                hour_days INTEGER
+            )",
+            [],
+        );
+
+        // prometheus_exporter - single row, see prometheus::default_config
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS prometheus_exporter (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                enabled INTEGER NOT NULL,
+                bind_address TEXT NOT NULL,
+                port INTEGER NOT NULL
+            )",
+            [],
+        );
+
+        // metric_sink - push destinations; device_id NULL forwards every device
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS metric_sink (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER,
+                name TEXT,
+                kind TEXT NOT NULL,
+                url TEXT NOT NULL,
+                token TEXT,
+                username TEXT,
+                password TEXT,
+                topic TEXT,
+                qos INTEGER NOT NULL DEFAULT 1,
+                batch_size INTEGER NOT NULL DEFAULT 500,
+                flush_interval_ms INTEGER NOT NULL DEFAULT 5000,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER
+            )",
+            [],
+        );
+        // sink_outbox - encoded messages not yet accepted by their sink
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS sink_outbox (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                sink_id INTEGER NOT NULL,
+                device_id INTEGER NOT NULL,
+                topic TEXT,
+                payload TEXT NOT NULL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_sink_outbox_sink ON sink_outbox(sink_id, id)",
+            [],
+        );
+
+        // anomaly_event table - findings of anomaly.rs, open until a run no longer finds them
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS anomaly_event (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                metric TEXT NOT NULL,
+                value REAL NOT NULL,
+                expected REAL NOT NULL,
+                score REAL NOT NULL,
+                detail TEXT,
+                detected_at INTEGER NOT NULL,
+                last_seen_at INTEGER NOT NULL,
+                resolved_at INTEGER
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_anomaly_event_device ON anomaly_event(device_id, detected_at)",
+            [],
+        );
+
+        // alert_rule table - threshold rules; device_id NULL applies to every device
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_rule (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER,
+                name TEXT,
+                metric TEXT NOT NULL,
+                comparator TEXT NOT NULL,
+                threshold REAL NOT NULL,
+                duration_sec INTEGER NOT NULL DEFAULT 0,
+                severity TEXT NOT NULL DEFAULT 'warning',
+                notify INTEGER NOT NULL DEFAULT 0,
+                enabled INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER
+            )",
+            [],
+        );
+        // alert_event table - one row per alert, resolved_at NULL while open.
+        // Rule fields are copied so history survives rule edits and deletion.
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_event (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                rule_id INTEGER NOT NULL,
+                device_id INTEGER NOT NULL,
+                rule_name TEXT,
+                metric TEXT NOT NULL,
+                comparator TEXT NOT NULL,
+                threshold REAL NOT NULL,
+                severity TEXT NOT NULL,
+                value REAL NOT NULL,
+                peak REAL NOT NULL,
+                opened_at INTEGER NOT NULL,
+                resolved_at INTEGER
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_alert_event_device_opened ON alert_event(device_id, opened_at)",
+            [],
+        );
+
+        // throttle_event table - detected throttling; start_ts/end_ts are device_stats
+        // timestamps of the first and last affected samples, end_ts NULL while ongoing
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS throttle_event (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER NOT NULL,
+                cause TEXT NOT NULL,
+                source TEXT NOT NULL,
+                detail TEXT,
+                start_ts INTEGER NOT NULL,
+                end_ts INTEGER
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_throttle_event_device_start ON throttle_event(device_id, start_ts)",
+            [],
+        );
 
         // system_info table - stores system information per device
         // This is the source of truth for hardware/OS details
@@ -78,9 +291,114 @@ pub fn init_db() {
             )",
             [],
         );
+        let _ = conn.execute("ALTER TABLE system_info ADD COLUMN model TEXT", []);
         let _ = conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_system_info_device ON system_info(device_id)",
             [],
         );
+
+        // power_mode_change table - history of nvpmodel mode switches made from orion
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS power_mode_change (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER NOT NULL,
+                ts INTEGER NOT NULL,
+                mode_id INTEGER NOT NULL,
+                mode_name TEXT NOT NULL,
+                previous_mode_id INTEGER,
+                reboot_required INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_power_mode_change_device_ts ON power_mode_change(device_id, ts)",
+            [],
+        );
+
+        // benchmark_run table - commands run through runs.rs with their outcome and
+        // a telemetry summary; the samples themselves are tagged in device_stats
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS benchmark_run (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER NOT NULL,
+                name TEXT,
+                command TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at INTEGER NOT NULL,
+                ended_at INTEGER,
+                exit_status INTEGER,
+                output TEXT,
+                output_truncated INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                sample_interval_ms INTEGER NOT NULL,
+                samples INTEGER,
+                avg_power_mw REAL,
+                peak_power_mw REAL,
+                energy_j REAL,
+                avg_cpu REAL,
+                peak_cpu REAL,
+                avg_gpu_util REAL,
+                peak_gpu_util REAL,
+                avg_gpu_temp_c REAL,
+                peak_gpu_temp_c REAL,
+                peak_temp_c REAL,
+                avg_cpu_freq_mhz REAL,
+                avg_gpu_freq_mhz REAL,
+                avg_emc_freq_mhz REAL,
+                peak_ram_used_mb REAL,
+                throttled INTEGER,
+                power_mode TEXT,
+                clocks_locked INTEGER
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_benchmark_run_device_started ON benchmark_run(device_id, started_at)",
+            [],
+        );
+
+        // trt_result table - trtexec builds per device and model, with the parsed
+        // performance summary; latencies are in ms
+        let _ = conn.execute(
+            "CREATE TABLE IF NOT EXISTS trt_result (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id INTEGER NOT NULL,
+                run_id INTEGER NOT NULL,
+                model TEXT NOT NULL,
+                onnx_path TEXT NOT NULL,
+                engine_path TEXT,
+                precision TEXT NOT NULL,
+                workspace_mb INTEGER,
+                min_shapes TEXT,
+                opt_shapes TEXT,
+                max_shapes TEXT,
+                dla_core INTEGER,
+                status TEXT NOT NULL,
+                trt_version TEXT,
+                throughput_qps REAL,
+                latency_min REAL,
+                latency_mean REAL,
+                latency_median REAL,
+                latency_p90 REAL,
+                latency_p95 REAL,
+                latency_p99 REAL,
+                latency_max REAL,
+                gpu_min REAL,
+                gpu_mean REAL,
+                gpu_median REAL,
+                gpu_p90 REAL,
+                gpu_p95 REAL,
+                gpu_p99 REAL,
+                gpu_max REAL,
+                h2d_mean_ms REAL,
+                d2h_mean_ms REAL,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        );
+        let _ = conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_trt_result_device_model ON trt_result(device_id, model)",
+            [],
+        );
     }
 }
@@ -0,0 +1,547 @@
+//! Export of stored stats to CSV, NDJSON or Parquet files, and import back.
+//!
+//! Rows are streamed straight from the SQLite cursor into the file (Parquet
+//! buffers one row group at a time), so exports of any size run in bounded memory.
+//!
+//! Tables:
+//!   stats    device_stats, one row per sample
+//!   metrics  device_metrics in long form: ts, device_id, series, unit, value
+
+use crate::metrics;
+use crate::retention;
+use crate::types::{ExportReport, ImportReport};
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::reader::SerializedFileReader;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::record::Field;
+use parquet::schema::parser::parse_message_type;
+use rusqlite::types::{Value as SqlValue, ValueRef};
+use rusqlite::{params, params_from_iter, Connection};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+const ROW_GROUP_SIZE: usize = 65_536;
+const IMPORT_BATCH: usize = 10_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Int,
+    Real,
+    Text,
+}
+
+struct Table {
+    name: &'static str,
+    columns: &'static [(&'static str, Kind)],
+    // Indexes of columns that must not be NULL on import
+    required: &'static [usize],
+    select: &'static str,
+}
+
+const STATS: Table = Table {
+    name: "stats",
+    columns: &[
+        ("ts", Kind::Int),
+        ("device_id", Kind::Int),
+        ("cpu", Kind::Real),
+        ("ram_used_mb", Kind::Int),
+        ("ram_total_mb", Kind::Int),
+        ("gpu_util", Kind::Real),
+        ("gpu_temp_c", Kind::Real),
+        ("power_mode", Kind::Text),
+        ("clocks_locked", Kind::Int),
+        ("fan_rpm", Kind::Int),
+        ("fan_pwm", Kind::Int),
+    ],
+    required: &[0, 1, 2, 3, 4],
+    select: "SELECT ts, device_id, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode, clocks_locked, fan_rpm, fan_pwm FROM device_stats WHERE device_id = ?1 AND ts >= ?2 AND ts <= ?3 ORDER BY ts",
+};
+
+const METRICS: Table = Table {
+    name: "metrics",
+    columns: &[
+        ("ts", Kind::Int),
+        ("device_id", Kind::Int),
+        ("series", Kind::Text),
+        ("unit", Kind::Text),
+        ("value", Kind::Real),
+    ],
+    required: &[0, 1, 2, 4],
+    select: "SELECT m.ts, m.device_id, s.name, s.unit, m.value FROM device_metrics m JOIN metric_series s ON s.id = m.series_id WHERE m.device_id = ?1 AND m.ts >= ?2 AND m.ts <= ?3 ORDER BY m.ts, s.name",
+};
+
+fn table(name: &str) -> Result<&'static Table, String> {
+    match name {
+        "stats" => Ok(&STATS),
+        "metrics" => Ok(&METRICS),
+        other => Err(format!("unknown table: {}", other)),
+    }
+}
+
+/// "csv", "ndjson" or "parquet", given explicitly or taken from the file extension.
+fn format_of(format: Option<&str>, path: &Path) -> Result<String, String> {
+    let f = match format {
+        Some(f) => f.to_lowercase(),
+        None => path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase())
+            .ok_or_else(|| "format required".to_string())?,
+    };
+    match f.as_str() {
+        "csv" => Ok(f),
+        "ndjson" | "jsonl" => Ok("ndjson".to_string()),
+        "parquet" => Ok(f),
+        other => Err(format!("unsupported format: {}", other)),
+    }
+}
+
+// One cell on its way out; None is NULL
+enum Cell {
+    Int(Option<i64>),
+    Real(Option<f64>),
+    Text(Option<String>),
+}
+
+impl Cell {
+    fn to_json(&self) -> Value {
+        match self {
+            Cell::Int(v) => v.map(Value::from).unwrap_or(Value::Null),
+            Cell::Real(v) => v.map(Value::from).unwrap_or(Value::Null),
+            Cell::Text(v) => v.clone().map(Value::from).unwrap_or(Value::Null),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::Int(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+            Cell::Real(v) => v.map(|v| v.to_string()).unwrap_or_default(),
+            Cell::Text(v) => v.clone().unwrap_or_default(),
+        }
+    }
+}
+
+trait Sink {
+    fn write(&mut self, row: Vec<Cell>) -> Result<(), String>;
+    fn finish(self: Box<Self>) -> Result<(), String>;
+}
+
+struct CsvSink(csv::Writer<BufWriter<File>>);
+
+impl Sink for CsvSink {
+    fn write(&mut self, row: Vec<Cell>) -> Result<(), String> {
+        self.0
+            .write_record(row.iter().map(Cell::to_csv))
+            .map_err(|e| e.to_string())
+    }
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.0.flush().map_err(|e| e.to_string())
+    }
+}
+
+struct NdjsonSink {
+    out: BufWriter<File>,
+    columns: &'static [(&'static str, Kind)],
+}
+
+impl Sink for NdjsonSink {
+    fn write(&mut self, row: Vec<Cell>) -> Result<(), String> {
+        let obj: Map<String, Value> = self
+            .columns
+            .iter()
+            .zip(&row)
+            .map(|((name, _), cell)| (name.to_string(), cell.to_json()))
+            .collect();
+        serde_json::to_writer(&mut self.out, &obj).map_err(|e| e.to_string())?;
+        self.out.write_all(b"\n").map_err(|e| e.to_string())
+    }
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.out.flush().map_err(|e| e.to_string())
+    }
+}
+
+// Column buffers for the current row group: values of non-null cells and a
+// definition level per row (1 = present, 0 = NULL)
+enum ColumnBuf {
+    Int(Vec<i64>, Vec<i16>),
+    Real(Vec<f64>, Vec<i16>),
+    Text(Vec<ByteArray>, Vec<i16>),
+}
+
+struct ParquetSink {
+    writer: SerializedFileWriter<BufWriter<File>>,
+    buffers: Vec<ColumnBuf>,
+    rows: usize,
+}
+
+impl ParquetSink {
+    fn new(file: File, table: &Table) -> Result<Self, String> {
+        let fields: Vec<String> = table
+            .columns
+            .iter()
+            .map(|(name, kind)| match kind {
+                Kind::Int => format!("OPTIONAL INT64 {};", name),
+                Kind::Real => format!("OPTIONAL DOUBLE {};", name),
+                Kind::Text => format!("OPTIONAL BYTE_ARRAY {} (UTF8);", name),
+            })
+            .collect();
+        let schema = parse_message_type(&format!(
+            "message {} {{ {} }}",
+            table.name,
+            fields.join(" ")
+        ))
+        .map_err(|e| e.to_string())?;
+        let props = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let writer =
+            SerializedFileWriter::new(BufWriter::new(file), Arc::new(schema), Arc::new(props))
+                .map_err(|e| e.to_string())?;
+        let buffers = table
+            .columns
+            .iter()
+            .map(|(_, kind)| match kind {
+                Kind::Int => ColumnBuf::Int(Vec::new(), Vec::new()),
+                Kind::Real => ColumnBuf::Real(Vec::new(), Vec::new()),
+                Kind::Text => ColumnBuf::Text(Vec::new(), Vec::new()),
+            })
+            .collect();
+        Ok(ParquetSink {
+            writer,
+            buffers,
+            rows: 0,
+        })
+    }
+
+    fn flush_group(&mut self) -> Result<(), String> {
+        if self.rows == 0 {
+            return Ok(());
+        }
+        let mut group = self.writer.next_row_group().map_err(|e| e.to_string())?;
+        for buf in self.buffers.iter_mut() {
+            let mut col = group
+                .next_column()
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| "parquet schema mismatch".to_string())?;
+            match buf {
+                ColumnBuf::Int(values, defs) => {
+                    col.typed::<Int64Type>()
+                        .write_batch(values, Some(defs), None)
+                        .map_err(|e| e.to_string())?;
+                    values.clear();
+                    defs.clear();
+                }
+                ColumnBuf::Real(values, defs) => {
+                    col.typed::<DoubleType>()
+                        .write_batch(values, Some(defs), None)
+                        .map_err(|e| e.to_string())?;
+                    values.clear();
+                    defs.clear();
+                }
+                ColumnBuf::Text(values, defs) => {
+                    col.typed::<ByteArrayType>()
+                        .write_batch(values, Some(defs), None)
+                        .map_err(|e| e.to_string())?;
+                    values.clear();
+                    defs.clear();
+                }
+            }
+            col.close().map_err(|e| e.to_string())?;
+        }
+        group.close().map_err(|e| e.to_string())?;
+        self.rows = 0;
+        Ok(())
+    }
+}
+
+impl Sink for ParquetSink {
+    fn write(&mut self, row: Vec<Cell>) -> Result<(), String> {
+        for (buf, cell) in self.buffers.iter_mut().zip(row) {
+            match (buf, cell) {
+                (ColumnBuf::Int(values, defs), Cell::Int(v)) => {
+                    defs.push(v.is_some() as i16);
+                    values.extend(v);
+                }
+                (ColumnBuf::Real(values, defs), Cell::Real(v)) => {
+                    defs.push(v.is_some() as i16);
+                    values.extend(v);
+                }
+                (ColumnBuf::Text(values, defs), Cell::Text(v)) => {
+                    defs.push(v.is_some() as i16);
+                    values.extend(v.map(|s| ByteArray::from(s.into_bytes())));
+                }
+                _ => return Err("parquet column type mismatch".to_string()),
+            }
+        }
+        self.rows += 1;
+        if self.rows >= ROW_GROUP_SIZE {
+            self.flush_group()?;
+        }
+        Ok(())
+    }
+    fn finish(mut self: Box<Self>) -> Result<(), String> {
+        self.flush_group()?;
+        self.writer.close().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+/// Write a device's rows in [start_ts, end_ts] from `table` to `path`.
+pub fn export(
+    conn: &Connection,
+    device_id: i64,
+    start_ts: i64,
+    end_ts: i64,
+    table_name: &str,
+    format: Option<&str>,
+    path: &Path,
+) -> Result<ExportReport, String> {
+    let table = table(table_name)?;
+    let format = format_of(format, path)?;
+    let file =
+        File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+    let mut sink: Box<dyn Sink> = match format.as_str() {
+        "csv" => {
+            let mut w = csv::Writer::from_writer(BufWriter::new(file));
+            w.write_record(table.columns.iter().map(|(name, _)| *name))
+                .map_err(|e| e.to_string())?;
+            Box::new(CsvSink(w))
+        }
+        "ndjson" => Box::new(NdjsonSink {
+            out: BufWriter::new(file),
+            columns: table.columns,
+        }),
+        _ => Box::new(ParquetSink::new(file, table)?),
+    };
+
+    let mut stmt = conn.prepare(table.select).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(params![device_id, start_ts, end_ts])
+        .map_err(|e| e.to_string())?;
+    let mut count: u64 = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut cells = Vec::with_capacity(table.columns.len());
+        for (i, (_, kind)) in table.columns.iter().enumerate() {
+            let v = row.get_ref(i).map_err(|e| e.to_string())?;
+            cells.push(match (kind, v) {
+                (Kind::Int, ValueRef::Integer(n)) => Cell::Int(Some(n)),
+                (Kind::Int, _) => Cell::Int(None),
+                (Kind::Real, ValueRef::Real(f)) => Cell::Real(Some(f)),
+                (Kind::Real, ValueRef::Integer(n)) => Cell::Real(Some(n as f64)),
+                (Kind::Real, _) => Cell::Real(None),
+                (Kind::Text, ValueRef::Text(t)) => {
+                    Cell::Text(Some(String::from_utf8_lossy(t).into_owned()))
+                }
+                (Kind::Text, _) => Cell::Text(None),
+            });
+        }
+        sink.write(cells)?;
+        count += 1;
+    }
+    sink.finish()?;
+
+    Ok(ExportReport {
+        path: path.display().to_string(),
+        format,
+        table: table.name.to_string(),
+        rows: count,
+    })
+}
+
+/// JSON value for one parquet field.
+fn field_json(field: &Field) -> Value {
+    match field {
+        Field::Bool(b) => Value::from(*b as i64),
+        Field::Byte(n) => Value::from(*n),
+        Field::Short(n) => Value::from(*n),
+        Field::Int(n) => Value::from(*n),
+        Field::Long(n) => Value::from(*n),
+        Field::UByte(n) => Value::from(*n),
+        Field::UShort(n) => Value::from(*n),
+        Field::UInt(n) => Value::from(*n),
+        Field::ULong(n) => Value::from(*n),
+        Field::Float(f) => Value::from(*f as f64),
+        Field::Double(f) => Value::from(*f),
+        Field::Str(s) => Value::from(s.as_str()),
+        _ => Value::Null,
+    }
+}
+
+/// SQLite value for a cell read back from a file, typed by its column.
+fn sql_value(kind: Kind, v: Option<&Value>) -> SqlValue {
+    let Some(v) = v.filter(|v| !v.is_null()) else {
+        return SqlValue::Null;
+    };
+    // CSV cells arrive as strings
+    let text = v.as_str().map(str::trim);
+    match kind {
+        Kind::Int => v
+            .as_i64()
+            .or_else(|| v.as_f64().map(|f| f as i64))
+            .or_else(|| v.as_bool().map(i64::from))
+            .or_else(|| text.and_then(|t| t.parse().ok()))
+            .map(SqlValue::Integer)
+            .unwrap_or(SqlValue::Null),
+        Kind::Real => v
+            .as_f64()
+            .or_else(|| text.and_then(|t| t.parse().ok()))
+            .map(SqlValue::Real)
+            .unwrap_or(SqlValue::Null),
+        Kind::Text => match text {
+            Some("") => SqlValue::Null,
+            Some(t) => SqlValue::Text(t.to_string()),
+            None => SqlValue::Text(v.to_string()),
+        },
+    }
+}
+
+type Rows = Box<dyn Iterator<Item = Result<Map<String, Value>, String>>>;
+
+/// Rows of a file as JSON objects keyed by column name.
+fn read_rows(format: &str, path: &Path) -> Result<Rows, String> {
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    match format {
+        "csv" => {
+            let mut reader = csv::Reader::from_reader(BufReader::new(file));
+            let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+            Ok(Box::new(reader.into_records().map(move |rec| {
+                let rec = rec.map_err(|e| e.to_string())?;
+                Ok(headers
+                    .iter()
+                    .zip(rec.iter())
+                    .map(|(h, v)| (h.to_string(), Value::from(v)))
+                    .collect())
+            })))
+        }
+        "ndjson" => Ok(Box::new(
+            BufReader::new(file)
+                .lines()
+                .filter(|l| l.as_ref().map_or(true, |l| !l.trim().is_empty()))
+                .map(|line| {
+                    let line = line.map_err(|e| e.to_string())?;
+                    serde_json::from_str(&line).map_err(|e| e.to_string())
+                }),
+        )),
+        _ => {
+            let reader = SerializedFileReader::new(file).map_err(|e| e.to_string())?;
+            let rows = reader.into_iter();
+            Ok(Box::new(rows.map(|row| {
+                let row = row.map_err(|e| e.to_string())?;
+                Ok(row
+                    .get_column_iter()
+                    .map(|(name, field)| (name.clone(), field_json(field)))
+                    .collect())
+            })))
+        }
+    }
+}
+
+/// Load a file written by `export`. The table is recognised from its columns;
+/// `device_id` reassigns every row to that device. Rows that already exist
+/// (same device and ts, and series for metrics) are skipped.
+pub fn import(
+    conn: &mut Connection,
+    path: &Path,
+    format: Option<&str>,
+    device_id: Option<i64>,
+) -> Result<ImportReport, String> {
+    let format = format_of(format, path)?;
+    let mut rows = read_rows(&format, path)?.peekable();
+    let table = match rows.peek() {
+        Some(Ok(first)) if first.contains_key("series") => &METRICS,
+        Some(Ok(first)) if first.contains_key("cpu") => &STATS,
+        Some(Ok(_)) => return Err("unrecognised columns".to_string()),
+        Some(Err(e)) => return Err(e.clone()),
+        None => {
+            return Ok(ImportReport {
+                table: String::new(),
+                imported: 0,
+                skipped: 0,
+            })
+        }
+    };
+
+    let insert = match table.name {
+        "stats" => "INSERT INTO device_stats (ts, device_id, cpu, ram_used_mb, ram_total_mb, gpu_util, gpu_temp_c, power_mode, clocks_locked, fan_rpm, fan_pwm)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11
+             WHERE NOT EXISTS (SELECT 1 FROM device_stats WHERE device_id = ?2 AND ts = ?1)",
+        _ => "INSERT INTO device_metrics (ts, device_id, series_id, value)
+             SELECT ?1, ?2, ?3, ?4
+             WHERE NOT EXISTS (SELECT 1 FROM device_metrics WHERE device_id = ?2 AND series_id = ?3 AND ts = ?1)",
+    };
+
+    let mut report = ImportReport {
+        table: table.name.to_string(),
+        imported: 0,
+        skipped: 0,
+    };
+    let mut oldest: Option<i64> = None;
+    loop {
+        let batch: Vec<Map<String, Value>> =
+            rows.by_ref().take(IMPORT_BATCH).collect::<Result<_, _>>()?;
+        if batch.is_empty() {
+            break;
+        }
+        let mut parsed: Vec<Vec<SqlValue>> = Vec::with_capacity(batch.len());
+        for row in &batch {
+            let mut values: Vec<SqlValue> = table
+                .columns
+                .iter()
+                .map(|(name, kind)| sql_value(*kind, row.get(*name)))
+                .collect();
+            if let Some(id) = device_id {
+                values[1] = id.into();
+            }
+            // Rows missing a NOT NULL column can't be stored
+            if table.required.iter().any(|i| values[*i] == SqlValue::Null) {
+                report.skipped += 1;
+                continue;
+            }
+            if let SqlValue::Text(name) = &values[2] {
+                if table.name == "metrics" {
+                    // Resolved outside the transaction so a rollback can't leave a
+                    // stale id in the series cache; the unit follows from the name
+                    let series_id = metrics::series_id(conn, name)?;
+                    values = vec![
+                        values[0].clone(),
+                        values[1].clone(),
+                        series_id.into(),
+                        values[4].clone(),
+                    ];
+                }
+            }
+            parsed.push(values);
+        }
+
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        {
+            let mut stmt = tx.prepare_cached(insert).map_err(|e| e.to_string())?;
+            for values in parsed {
+                let SqlValue::Integer(ts) = values[0] else {
+                    continue;
+                };
+                let n = stmt
+                    .execute(params_from_iter(values))
+                    .map_err(|e| e.to_string())?;
+                if n == 0 {
+                    report.skipped += 1;
+                } else {
+                    report.imported += 1;
+                    oldest = Some(oldest.map_or(ts, |o| o.min(ts)));
+                }
+            }
+        }
+        tx.commit().map_err(|e| e.to_string())?;
+    }
+
+    // Make the next compaction roll up the imported range
+    if let Some(ts) = oldest {
+        retention::rewind(conn, ts)?;
+    }
+    Ok(report)
+}
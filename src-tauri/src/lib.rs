@@ -1,9 +1,23 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use log::info;
 
+mod aggregate;
+mod alerts;
+mod anomaly;
+mod collector;
 mod commands;
 mod db;
+mod export;
+mod metrics;
+mod prometheus;
+mod remote;
+mod retention;
+mod runs;
 mod session;
+mod sinks;
+mod tegrastats;
+mod throttle;
+mod trtexec;
 mod types;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -17,9 +31,24 @@ pub fn run() {
 
     // Ensure DB exists
     db::init_db();
+    // Roll up and prune old stats in the background
+    retention::start();
+    // Deliver queued samples to push sinks
+    sinks::start();
 
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .setup(|app| {
+            if let Ok(conn) = db::db_conn() {
+                alerts::init(app.handle().clone(), &conn);
+                throttle::close_stale(&conn);
+                runs::close_stale(&conn);
+                prometheus::start(&conn);
+            }
+            anomaly::start(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::greet,
             // Connection commands
@@ -35,13 +64,39 @@ pub fn run() {
             // Stats commands
             commands::stats::record_stat,
             commands::stats::get_stats,
+            commands::stats::get_stats_aggregated,
+            commands::stats::list_metric_series,
+            commands::stats::get_retention_policy,
+            commands::stats::set_retention_policy,
+            commands::stats::compact_stats,
+            commands::stats::list_throttle_events,
+            commands::stats::get_power_segments,
+            commands::stats::export_stats,
+            commands::stats::import_stats,
+            commands::stats::get_filesystem_overview,
             commands::stats::start_stats_stream,
             commands::stats::stop_stats_stream,
             commands::stats::stream_stats,
             commands::stats::stop_stream_stats,
+            commands::stats::list_stream_subscriptions,
+            // Remote exec commands
+            commands::exec::run_command,
+            commands::exec::run_command_streaming,
+            // Fleet commands
+            commands::fleet::stream_fleet,
+            commands::fleet::stop_fleet_stream,
             // System commands
             commands::system::get_power_mode,
             commands::system::set_power_mode,
+            commands::system::list_power_modes,
+            commands::system::get_power_mode_history,
+            commands::system::jetson_clocks_enable,
+            commands::system::jetson_clocks_store,
+            commands::system::jetson_clocks_restore,
+            commands::system::get_clock_state,
+            commands::system::get_fan_state,
+            commands::system::set_fan_profile,
+            commands::system::set_fan_pwm,
             commands::system::shutdown,
             commands::system::reboot,
             commands::system::fetch_and_store_sys_info,
@@ -68,6 +123,48 @@ pub fn run() {
             commands::packages::packages_list,
             commands::packages::packages_install,
             commands::packages::packages_remove,
+            commands::packages::packages_install_offline,
+            commands::packages::pip_install_requirements,
+            commands::packages::pip_venv_create,
+            commands::packages::pip_venv_delete,
+            commands::packages::pip_venv_list,
+            // Alert commands
+            commands::alerts::list_alert_rules,
+            commands::alerts::save_alert_rule,
+            commands::alerts::delete_alert_rule,
+            commands::alerts::list_alert_events,
+            commands::alerts::detect_anomalies,
+            commands::alerts::list_anomalies,
+            // Process commands
+            commands::processes::list_processes,
+            commands::processes::stream_processes,
+            commands::processes::stop_process_stream,
+            commands::processes::signal_process,
+            commands::processes::renice_process,
+            // Benchmark run commands
+            commands::runs::start_run,
+            commands::runs::cancel_run,
+            commands::runs::list_runs,
+            commands::runs::get_run,
+            commands::runs::get_run_stats,
+            commands::runs::compare_runs,
+            commands::runs::delete_run,
+            // TensorRT commands
+            commands::trt::upload_onnx_model,
+            commands::trt::list_onnx_models,
+            commands::trt::build_trt_engine,
+            commands::trt::list_trt_results,
+            commands::trt::download_trt_engine,
+            commands::trt::delete_trt_result,
+            // Prometheus commands
+            commands::prometheus::get_prometheus_config,
+            commands::prometheus::set_prometheus_config,
+            // Sink commands
+            commands::sinks::list_metric_sinks,
+            commands::sinks::save_metric_sink,
+            commands::sinks::delete_metric_sink,
+            commands::sinks::test_metric_sink,
+            commands::sinks::get_sink_status,
             // Credential commands
             commands::credentials::save_credential,
         ])
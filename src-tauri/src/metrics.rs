@@ -0,0 +1,249 @@
+//! Named metric series stored long/narrow in `device_metrics`.
+//!
+//! Every sample carries a map of series name to value. Names are dotted paths and
+//! the unit follows from the name, so adding a metric needs no schema change:
+//!
+//!   cpu                       overall CPU load, %
+//!   cpu.core<N>.load / .freq  per-core load (%) and frequency (MHz)
+//!   ram.used / ram.total      MB (swap.* likewise, iram.* in kB)
+//!   gpu.load / gpu.freq       GR3D load (%) and frequency (MHz); emc.* likewise
+//!   engine.<name>.load/.freq  NVENC, NVDEC, VIC, NVDLA0, APE, ...
+//!   temp.<zone>               thermal zone, C
+//!   power.<rail>[.avg]        rail power (instant / average), mW
+//!   fan.rpm / fan.pwm         fan tachometer and duty cycle (0-255)
+//!   fs.<mount>.used.pct       filesystem usage (%), also .avail.mb and .inodes.pct;
+//!                             "/" is named "root", other mounts use "_" for "/"
+//!   io.<disk>.read.bps        disk throughput (B/s), also write.bps, read.iops,
+//!                             write.iops and util.pct (time busy)
+//!   net.<iface>.rx.bps        interface throughput (B/s), also tx.bps, rx/tx.pps
+//!                             and rx/tx.errs; wireless adds signal.dbm and bitrate.mbps
+
+use crate::types::{MetricSeries, TegraStats};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::{BTreeMap, HashMap};
+
+// metric_series ids by name; series are never deleted so the cache never goes stale
+static SERIES_IDS: Lazy<Mutex<HashMap<String, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Unit of a series, derived from its name.
+pub fn unit_of(name: &str) -> &'static str {
+    const SUFFIXES: &[(&str, &str)] = &[
+        (".load", "%"),
+        (".pct", "%"),
+        (".freq", "MHz"),
+        (".bps", "B/s"),
+        (".pps", "pkt/s"),
+        (".iops", "ops/s"),
+        (".errs", "err/s"),
+        (".dbm", "dBm"),
+        (".mbps", "Mbit/s"),
+        (".mb", "MB"),
+        (".rpm", "rpm"),
+        (".pwm", "pwm"),
+    ];
+    const PREFIXES: &[(&str, &str)] = &[
+        ("temp.", "C"),
+        ("power.", "mW"),
+        ("iram.", "kB"),
+        ("ram.", "MB"),
+        ("swap.", "MB"),
+    ];
+    if name == "cpu" {
+        return "%";
+    }
+    SUFFIXES
+        .iter()
+        .find(|(s, _)| name.ends_with(s))
+        .or_else(|| PREFIXES.iter().find(|(p, _)| name.starts_with(p)))
+        .map(|(_, u)| *u)
+        .unwrap_or("")
+}
+
+/// Flatten a parsed tegrastats line into named series.
+pub fn add_tegrastats(t: &TegraStats, out: &mut BTreeMap<String, f64>) {
+    let mut put = |name: String, v: Option<f64>| {
+        if let Some(v) = v {
+            out.insert(name, v);
+        }
+    };
+    for core in &t.cpus {
+        put(format!("cpu.core{}.load", core.index), core.load_pct);
+        put(format!("cpu.core{}.freq", core.index), core.freq_mhz);
+    }
+    if let Some(swap) = &t.swap {
+        put("swap.used".into(), Some(swap.used_kb as f64 / 1024.0));
+        put("swap.total".into(), Some(swap.total_kb as f64 / 1024.0));
+    }
+    if let Some(iram) = &t.iram {
+        put("iram.used".into(), Some(iram.used_kb as f64));
+    }
+    if let Some(gpu) = &t.gpu {
+        put("gpu.load".into(), gpu.load_pct);
+        put("gpu.freq".into(), gpu.freq_mhz);
+    }
+    if let Some(emc) = &t.emc {
+        put("emc.load".into(), emc.load_pct);
+        put("emc.freq".into(), emc.freq_mhz);
+    }
+    for (name, e) in &t.engines {
+        let name = name.to_lowercase();
+        put(format!("engine.{}.load", name), e.load_pct);
+        put(format!("engine.{}.freq", name), e.freq_mhz);
+    }
+    for (zone, c) in &t.temps {
+        put(format!("temp.{}", zone.to_lowercase()), Some(*c));
+    }
+    for (rail, p) in &t.rails {
+        let rail = rail.to_lowercase();
+        put(format!("power.{}", rail), Some(p.current_mw));
+        put(format!("power.{}.avg", rail), Some(p.average_mw));
+    }
+}
+
+pub(crate) fn series_id(conn: &Connection, name: &str) -> Result<i64, String> {
+    if let Some(id) = SERIES_IDS.lock().get(name) {
+        return Ok(*id);
+    }
+    conn.execute(
+        "INSERT OR IGNORE INTO metric_series (name, unit) VALUES (?1, ?2)",
+        params![name, unit_of(name)],
+    )
+    .map_err(|e| e.to_string())?;
+    let id: i64 = conn
+        .query_row(
+            "SELECT id FROM metric_series WHERE name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    SERIES_IDS.lock().insert(name.to_string(), id);
+    Ok(id)
+}
+
+/// Series ids for one sample's values, skipping non-finite ones. Call this
+/// outside the transaction that stores them, so a rollback can't leave a stale
+/// id in the series cache.
+pub fn resolve(
+    conn: &Connection,
+    metrics: &BTreeMap<String, f64>,
+) -> Result<Vec<(i64, f64)>, String> {
+    metrics
+        .iter()
+        .filter(|(_, value)| value.is_finite())
+        .map(|(name, value)| Ok((series_id(conn, name)?, *value)))
+        .collect()
+}
+
+/// Store one sample's series values, as resolved by `resolve`.
+pub fn store(
+    conn: &Connection,
+    device_id: i64,
+    ts: i64,
+    values: &[(i64, f64)],
+) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare_cached(
+            "INSERT INTO device_metrics (device_id, ts, series_id, value) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .map_err(|e| e.to_string())?;
+    for (id, value) in values {
+        stmt.execute(params![device_id, ts, id, value])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// SQL condition matching series names. A trailing `*` matches a prefix,
+/// e.g. `temp.*` selects every thermal zone.
+pub(crate) fn name_filter(
+    column: &str,
+    names: &[String],
+    bind: &mut Vec<rusqlite::types::Value>,
+) -> String {
+    let clauses: Vec<String> = names
+        .iter()
+        .map(|n| {
+            let idx = bind.len() + 1;
+            match n.strip_suffix('*') {
+                Some(prefix) => {
+                    let escaped = prefix
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_");
+                    bind.push(format!("{}%", escaped).into());
+                    format!("{} LIKE ?{} ESCAPE '\\'", column, idx)
+                }
+                None => {
+                    bind.push(n.clone().into());
+                    format!("{} = ?{}", column, idx)
+                }
+            }
+        })
+        .collect();
+    format!("({})", clauses.join(" OR "))
+}
+
+/// Whether `name` is selected by `patterns` (exact names or `prefix*`).
+pub(crate) fn matches(name: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|p| match p.strip_suffix('*') {
+        Some(prefix) => name.starts_with(prefix),
+        None => name == p,
+    })
+}
+
+/// Values of the requested series between `start_ts` and `end_ts`, grouped by sample ts.
+pub fn query(
+    conn: &Connection,
+    device_id: i64,
+    names: &[String],
+    start_ts: i64,
+    end_ts: i64,
+) -> Result<HashMap<i64, BTreeMap<String, f64>>, String> {
+    let mut bind: Vec<rusqlite::types::Value> =
+        vec![device_id.into(), start_ts.into(), end_ts.into()];
+    let filter = name_filter("s.name", names, &mut bind);
+    let sql = format!(
+        "SELECT m.ts, s.name, m.value FROM device_metrics m JOIN metric_series s ON s.id = m.series_id WHERE m.device_id = ?1 AND m.ts >= ?2 AND m.ts <= ?3 AND {}",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params_from_iter(bind), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut out: HashMap<i64, BTreeMap<String, f64>> = HashMap::new();
+    for r in rows {
+        let (ts, name, value) = r.map_err(|e| e.to_string())?;
+        out.entry(ts).or_default().insert(name, value);
+    }
+    Ok(out)
+}
+
+/// Every series recorded for a device, with its unit and time span.
+pub fn list_series(conn: &Connection, device_id: i64) -> Result<Vec<MetricSeries>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT s.name, s.unit, MIN(m.ts), MAX(m.ts) FROM device_metrics m JOIN metric_series s ON s.id = m.series_id WHERE m.device_id = ?1 GROUP BY s.id ORDER BY s.name",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([device_id], |row| {
+            Ok(MetricSeries {
+                name: row.get(0)?,
+                unit: row.get(1)?,
+                first_ts: row.get(2)?,
+                last_ts: row.get(3)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,397 @@
+//! Optional OpenMetrics endpoint for Prometheus.
+//!
+//! Serves `GET /metrics` with the latest cached sample of every connected device.
+//! Scrapes only read the collector's cache and the device names from the local
+//! database; they never run anything over SSH. Off by default.
+
+use crate::collector;
+use crate::db::db_conn;
+use crate::metrics;
+use crate::session::SESSIONS;
+use crate::types::{PrometheusConfig, StatPoint};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use tiny_http::{Header, Response, Server};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+pub fn default_config() -> PrometheusConfig {
+    PrometheusConfig {
+        enabled: false,
+        bind_address: "127.0.0.1".to_string(),
+        port: 9464,
+    }
+}
+
+struct Running {
+    addr: SocketAddr,
+    server: Arc<Server>,
+    thread: JoinHandle<()>,
+}
+
+static SERVER: Lazy<Mutex<Option<Running>>> = Lazy::new(|| Mutex::new(None));
+
+pub fn load_config(conn: &Connection) -> Result<PrometheusConfig, String> {
+    let config = conn
+        .query_row(
+            "SELECT enabled, bind_address, port FROM prometheus_exporter WHERE id = 1",
+            [],
+            |row| {
+                Ok(PrometheusConfig {
+                    enabled: row.get(0)?,
+                    bind_address: row.get(1)?,
+                    port: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(config.unwrap_or_else(default_config))
+}
+
+pub fn save_config(conn: &Connection, config: &PrometheusConfig) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO prometheus_exporter (id, enabled, bind_address, port) VALUES (1, ?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET enabled = ?1, bind_address = ?2, port = ?3",
+        params![config.enabled, config.bind_address, config.port],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn resolve(config: &PrometheusConfig) -> Result<SocketAddr, String> {
+    (config.bind_address.trim(), config.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("invalid bind address {}: {}", config.bind_address, e))?
+        .next()
+        .ok_or_else(|| format!("invalid bind address: {}", config.bind_address))
+}
+
+/// The address the endpoint is listening on, if it is running.
+pub fn listening() -> Option<SocketAddr> {
+    SERVER.lock().as_ref().map(|r| r.addr)
+}
+
+/// Start, restart or stop the endpoint to match `config`.
+pub fn apply(config: &PrometheusConfig) -> Result<(), String> {
+    let addr = if config.enabled {
+        Some(resolve(config)?)
+    } else {
+        None
+    };
+    let mut running = SERVER.lock();
+    if addr.is_some() && running.as_ref().map(|r| r.addr) == addr {
+        return Ok(());
+    }
+    if let Some(r) = running.take() {
+        r.server.unblock();
+        let _ = r.thread.join();
+        info!("metrics endpoint on {} stopped", r.addr);
+    }
+    let Some(addr) = addr else {
+        return Ok(());
+    };
+
+    let server =
+        Arc::new(Server::http(addr).map_err(|e| format!("failed to listen on {}: {}", addr, e))?);
+    let srv = server.clone();
+    let thread = thread::spawn(move || serve(&srv));
+    info!("metrics endpoint listening on http://{}/metrics", addr);
+    *running = Some(Running {
+        addr,
+        server,
+        thread,
+    });
+    Ok(())
+}
+
+/// Start the endpoint at launch if it was left enabled.
+pub fn start(conn: &Connection) {
+    match load_config(conn) {
+        Ok(config) => {
+            if let Err(e) = apply(&config) {
+                warn!("metrics endpoint: {}", e);
+            }
+        }
+        Err(e) => warn!("metrics endpoint: {}", e),
+    }
+}
+
+fn serve(server: &Server) {
+    for request in server.incoming_requests() {
+        let path = request.url().split('?').next().unwrap_or("");
+        let response = if path != "/metrics" {
+            Response::from_string("not found\n").with_status_code(404)
+        } else if *request.method() != tiny_http::Method::Get {
+            Response::from_string("method not allowed\n").with_status_code(405)
+        } else {
+            let mut response = Response::from_string(render());
+            if let Ok(header) = Header::from_bytes("Content-Type", CONTENT_TYPE) {
+                response.add_header(header);
+            }
+            response
+        };
+        if let Err(e) = request.respond(response) {
+            warn!("metrics endpoint: {}", e);
+        }
+    }
+}
+
+fn device_names() -> HashMap<i64, String> {
+    let names = db_conn().and_then(|conn| {
+        let mut stmt = conn
+            .prepare("SELECT id, name FROM device")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<HashMap<_, _>, _>>()
+            .map_err(|e| e.to_string())
+    });
+    names.unwrap_or_else(|e| {
+        warn!("metrics endpoint: {}", e);
+        HashMap::new()
+    })
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Metric family: samples of one metric across devices must be written together.
+struct Family<'a> {
+    name: &'a str,
+    kind: &'a str,
+    unit: Option<&'a str>,
+    help: &'a str,
+}
+
+impl Family<'_> {
+    fn header(&self, out: &mut String) {
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        if let Some(unit) = self.unit {
+            let _ = writeln!(out, "# UNIT {} {}", self.name, unit);
+        }
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+    }
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, extra: &[(&str, &str)], value: f64) {
+    if !value.is_finite() {
+        return;
+    }
+    let mut labels = labels.to_string();
+    for (k, v) in extra {
+        let _ = write!(labels, ",{}=\"{}\"", k, escape(v));
+    }
+    let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+}
+
+fn gauge(
+    out: &mut String,
+    family: Family,
+    points: &[(String, &StatPoint)],
+    value: impl Fn(&StatPoint) -> Option<f64>,
+) {
+    family.header(out);
+    for (labels, p) in points {
+        if let Some(v) = value(p) {
+            sample(out, family.name, labels, &[], v);
+        }
+    }
+}
+
+/// OpenMetrics text for the latest sample of every connected device.
+pub fn render() -> String {
+    let names = device_names();
+    let sessions: Vec<String> = SESSIONS.lock().keys().cloned().collect();
+    let mut latest: Vec<StatPoint> = collector::latest()
+        .into_iter()
+        .filter(|p| {
+            collector::is_running(p.device_id) || sessions.contains(&p.device_id.to_string())
+        })
+        .collect();
+    latest.sort_by_key(|p| p.device_id);
+    let points: Vec<(String, &StatPoint)> = latest
+        .iter()
+        .map(|p| {
+            let name = names
+                .get(&p.device_id)
+                .cloned()
+                .unwrap_or_else(|| p.device_id.to_string());
+            let labels = format!("device_id=\"{}\",device=\"{}\"", p.device_id, escape(&name));
+            (labels, p)
+        })
+        .collect();
+
+    let mut out = String::new();
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_last_sample_timestamp_seconds",
+            kind: "gauge",
+            unit: Some("seconds"),
+            help: "Time of the device's latest sample.",
+        },
+        &points,
+        |p| Some(p.ts as f64 / 1000.0),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_cpu_usage_percent",
+            kind: "gauge",
+            unit: Some("percent"),
+            help: "CPU usage across all cores.",
+        },
+        &points,
+        |p| Some(p.cpu),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_memory_used_bytes",
+            kind: "gauge",
+            unit: Some("bytes"),
+            help: "RAM in use.",
+        },
+        &points,
+        |p| Some(p.ram_used_mb as f64 * 1024.0 * 1024.0),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_memory_total_bytes",
+            kind: "gauge",
+            unit: Some("bytes"),
+            help: "Total RAM.",
+        },
+        &points,
+        |p| Some(p.ram_total_mb as f64 * 1024.0 * 1024.0),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_gpu_usage_percent",
+            kind: "gauge",
+            unit: Some("percent"),
+            help: "GPU utilization.",
+        },
+        &points,
+        |p| p.gpu_util,
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_gpu_temperature_celsius",
+            kind: "gauge",
+            unit: Some("celsius"),
+            help: "GPU temperature.",
+        },
+        &points,
+        |p| p.gpu_temp_c,
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_fan_speed_rpm",
+            kind: "gauge",
+            unit: Some("rpm"),
+            help: "Fan speed.",
+        },
+        &points,
+        |p| p.fan_rpm.map(|v| v as f64),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_fan_pwm",
+            kind: "gauge",
+            unit: None,
+            help: "Fan PWM duty, 0-255.",
+        },
+        &points,
+        |p| p.fan_pwm.map(|v| v as f64),
+    );
+    gauge(
+        &mut out,
+        Family {
+            name: "orion_clocks_locked",
+            kind: "gauge",
+            unit: None,
+            help: "1 while jetson_clocks holds the clocks at max.",
+        },
+        &points,
+        |p| p.clocks_locked.map(|v| v as u8 as f64),
+    );
+
+    Family {
+        name: "orion_power_mode",
+        kind: "info",
+        unit: None,
+        help: "Active nvpmodel power mode.",
+    }
+    .header(&mut out);
+    for (labels, p) in &points {
+        if let Some(mode) = &p.power_mode {
+            sample(
+                &mut out,
+                "orion_power_mode_info",
+                labels,
+                &[("mode", mode)],
+                1.0,
+            );
+        }
+    }
+
+    Family {
+        name: "orion_throttling",
+        kind: "gauge",
+        unit: None,
+        help: "1 for each throttling cause active in the latest sample.",
+    }
+    .header(&mut out);
+    for (labels, p) in &points {
+        for c in &p.throttling {
+            sample(
+                &mut out,
+                "orion_throttling",
+                labels,
+                &[("cause", &c.cause), ("source", &c.source)],
+                1.0,
+            );
+        }
+    }
+
+    Family {
+        name: "orion_series",
+        kind: "gauge",
+        unit: None,
+        help: "Named series from the stats pipeline, as stored in device_metrics.",
+    }
+    .header(&mut out);
+    for (labels, p) in &points {
+        for (name, value) in &p.metrics {
+            sample(
+                &mut out,
+                "orion_series",
+                labels,
+                &[("series", name), ("unit", metrics::unit_of(name))],
+                *value,
+            );
+        }
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
@@ -0,0 +1,341 @@
+use crate::commands::connection::open_dedicated_session;
+use crate::session::{SessionHandle, SESSIONS};
+use ssh2::Channel;
+use std::io::{ErrorKind, Read, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Exit status of `timeout` when it stopped the command, and when it had to KILL it
+const TIMEOUT_EXIT: [i32; 2] = [124, 137];
+// Grace period between TERM and KILL once a timeout expires
+const KILL_AFTER_SECS: u32 = 5;
+
+/// Captured result of a command executed on a device.
+pub struct ExecOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32,
+    pub duration_ms: u64,
+    // Stopped by `ExecOptions::timeout`
+    pub timed_out: bool,
+}
+
+impl ExecOutput {
+    /// Turn a non-zero exit into an error carrying stderr (or stdout when stderr is empty).
+    pub fn into_result(self) -> Result<String, String> {
+        if self.exit_status == 0 {
+            return Ok(self.stdout);
+        }
+        if self.timed_out {
+            return Err(format!("timed out after {} ms", self.duration_ms));
+        }
+        let msg = if self.stderr.trim().is_empty() {
+            self.stdout.trim().to_string()
+        } else {
+            self.stderr.trim().to_string()
+        };
+        Err(format!("exit status {}: {}", self.exit_status, msg))
+    }
+}
+
+/// Settings for `exec_with` and `exec_streaming`; the default runs the command as is.
+#[derive(Default, Clone)]
+pub struct ExecOptions {
+    // Enforced on the device by coreutils `timeout`, so the command is stopped
+    // there too: TERM when it expires, KILL a few seconds later
+    pub timeout: Option<Duration>,
+    pub env: Vec<(String, String)>,
+    // Written to the command's stdin before its output is read
+    pub stdin: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+impl OutputStream {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputStream::Stdout => "stdout",
+            OutputStream::Stderr => "stderr",
+        }
+    }
+}
+
+pub fn session_handle(device_id: i64) -> Result<SessionHandle, String> {
+    session_by_key(&device_id.to_string())
+}
+
+/// Session by its key in `SESSIONS` (the device id, or a legacy token).
+pub fn session_by_key(key: &str) -> Result<SessionHandle, String> {
+    let map = SESSIONS.lock();
+    map.get(key)
+        .cloned()
+        .ok_or_else(|| "session not found".to_string())
+}
+
+/// The shell script that applies `opts` around `cmd`.
+fn wrap(cmd: &str, opts: &ExecOptions) -> Result<String, String> {
+    let mut script = String::new();
+    for (name, value) in &opts.env {
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("invalid environment variable name: {:?}", name));
+        }
+        script.push_str(&format!("export {}={}; ", name, shell_quote(value)));
+    }
+    match opts.timeout {
+        Some(t) if t.is_zero() => return Err("timeout must be greater than zero".to_string()),
+        // The login shell runs the command, as it would without a timeout
+        Some(t) => script.push_str(&format!(
+            "timeout -k {} {:.3} \"${{SHELL:-sh}}\" -c {}",
+            KILL_AFTER_SECS,
+            t.as_secs_f64(),
+            shell_quote(cmd)
+        )),
+        None => script.push_str(cmd),
+    }
+    Ok(script)
+}
+
+/// Write the configured stdin and close it, so commands that read stdin don't wait.
+fn feed_stdin(channel: &mut Channel, opts: &ExecOptions) -> Result<(), String> {
+    if let Some(data) = &opts.stdin {
+        channel.write_all(data).map_err(|e| e.to_string())?;
+    }
+    channel.send_eof().map_err(|e| e.to_string())
+}
+
+fn output(
+    opts: &ExecOptions,
+    started: Instant,
+    stdout: String,
+    stderr: String,
+    exit_status: i32,
+) -> ExecOutput {
+    ExecOutput {
+        stdout,
+        stderr,
+        exit_status,
+        duration_ms: started.elapsed().as_millis() as u64,
+        timed_out: opts.timeout.is_some() && TIMEOUT_EXIT.contains(&exit_status),
+    }
+}
+
+/// Run a command on the device and capture stdout, stderr and the exit status.
+pub fn exec(device_id: i64, cmd: &str) -> Result<ExecOutput, String> {
+    exec_with(device_id, cmd, &ExecOptions::default())
+}
+
+/// `exec` with a timeout, environment variables and/or stdin.
+pub fn exec_with(device_id: i64, cmd: &str, opts: &ExecOptions) -> Result<ExecOutput, String> {
+    exec_on(&session_handle(device_id)?, cmd, opts)
+}
+
+/// `exec_with` over a given session.
+pub fn exec_on(
+    handle: &SessionHandle,
+    cmd: &str,
+    opts: &ExecOptions,
+) -> Result<ExecOutput, String> {
+    let script = wrap(cmd, opts)?;
+    let started = Instant::now();
+    let mut channel = start(handle, &script, opts)?;
+    let (stdout, stderr, exit_status) = collect(&mut channel)?;
+    Ok(output(opts, started, stdout, stderr, exit_status))
+}
+
+/// `exec` for commands that may take the connection down with them, like a
+/// reboot. Errors up to sending the command are returned as usual; after that a
+/// dropped connection gives `None` rather than an error.
+pub fn exec_tolerating_drop(device_id: i64, cmd: &str) -> Result<Option<ExecOutput>, String> {
+    let handle = session_handle(device_id)?;
+    let opts = ExecOptions::default();
+    let started = Instant::now();
+    let mut channel = start(&handle, cmd, &opts)?;
+    Ok(collect(&mut channel)
+        .ok()
+        .map(|(stdout, stderr, exit_status)| output(&opts, started, stdout, stderr, exit_status)))
+}
+
+/// Open a channel on the session and send it `script`.
+fn start(handle: &SessionHandle, script: &str, opts: &ExecOptions) -> Result<Channel, String> {
+    let mut channel = {
+        let sess = handle.session.lock();
+        sess.channel_session().map_err(|e| e.to_string())?
+    };
+    channel.exec(script).map_err(|e| e.to_string())?;
+    feed_stdin(&mut channel, opts)?;
+    Ok(channel)
+}
+
+/// Read a started command to completion: stdout, stderr and the exit status.
+fn collect(channel: &mut Channel) -> Result<(String, String, i32), String> {
+    // libssh2 queues stderr while stdout is read, so reading them in turn can't stall
+    let mut stdout = String::new();
+    channel
+        .read_to_string(&mut stdout)
+        .map_err(|e| e.to_string())?;
+    let mut stderr = String::new();
+    channel
+        .stderr()
+        .read_to_string(&mut stderr)
+        .map_err(|e| e.to_string())?;
+    let _ = channel.wait_close();
+    let exit_status = channel.exit_status().map_err(|e| e.to_string())?;
+    Ok((stdout, stderr, exit_status))
+}
+
+/// Output of one stream, split into lines as it arrives.
+#[derive(Default)]
+struct Lines {
+    pending: Vec<u8>,
+    text: String,
+}
+
+impl Lines {
+    fn push(&mut self, data: &[u8], mut emit: impl FnMut(&str)) {
+        self.pending.extend_from_slice(data);
+        while let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.pending.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw);
+            self.text.push_str(&line);
+            emit(line.trim_end_matches(['\r', '\n']));
+        }
+    }
+
+    /// Emit a last line without a newline, and return everything.
+    fn finish(mut self, mut emit: impl FnMut(&str)) -> String {
+        if !self.pending.is_empty() {
+            let rest = String::from_utf8_lossy(&self.pending).into_owned();
+            self.text.push_str(&rest);
+            emit(rest.trim_end_matches('\r'));
+        }
+        self.text
+    }
+}
+
+/// `exec_with`, passing stdout and stderr lines to `on_line` as they arrive.
+/// The command runs over its own session, which is polled without blocking so
+/// both streams are read as they come and the shared session is not held.
+pub fn exec_streaming(
+    device_id: i64,
+    cmd: &str,
+    opts: &ExecOptions,
+    mut on_line: impl FnMut(OutputStream, &str),
+) -> Result<ExecOutput, String> {
+    let script = wrap(cmd, opts)?;
+    let started = Instant::now();
+    let sess = open_dedicated_session(device_id)?;
+    let mut channel = sess.channel_session().map_err(|e| e.to_string())?;
+    channel.exec(&script).map_err(|e| e.to_string())?;
+    feed_stdin(&mut channel, opts)?;
+
+    sess.set_blocking(false);
+    let mut stdout = Lines::default();
+    let mut stderr = Lines::default();
+    let mut buf = [0u8; 8192];
+    loop {
+        let mut progressed = false;
+        for stream in [OutputStream::Stdout, OutputStream::Stderr] {
+            let (read, lines) = match stream {
+                OutputStream::Stdout => (channel.read(&mut buf), &mut stdout),
+                OutputStream::Stderr => (channel.stderr().read(&mut buf), &mut stderr),
+            };
+            match read {
+                Ok(0) => {}
+                Ok(n) => {
+                    progressed = true;
+                    lines.push(&buf[..n], |l| on_line(stream, l));
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e.to_string()),
+            }
+        }
+        if !progressed {
+            // eof() only reports true once no data is left queued for either stream
+            if channel.eof() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+    sess.set_blocking(true);
+    let stdout = stdout.finish(|l| on_line(OutputStream::Stdout, l));
+    let stderr = stderr.finish(|l| on_line(OutputStream::Stderr, l));
+    let _ = channel.wait_close();
+    let exit_status = channel.exit_status().map_err(|e| e.to_string())?;
+
+    Ok(output(opts, started, stdout, stderr, exit_status))
+}
+
+/// Copy a local file to `remote_path` on the device over SCP. The file is
+/// streamed, so large files are fine.
+pub fn upload(device_id: i64, local_path: &Path, remote_path: &str) -> Result<(), String> {
+    let file = std::fs::File::open(local_path)
+        .map_err(|e| format!("failed to read {}: {}", local_path.display(), e))?;
+    let size = file
+        .metadata()
+        .map_err(|e| format!("failed to read {}: {}", local_path.display(), e))?
+        .len();
+    let handle = session_handle(device_id)?;
+
+    let mut channel = {
+        let sess = handle.session.lock();
+        sess.scp_send(Path::new(remote_path), 0o644, size, None)
+            .map_err(|e| e.to_string())?
+    };
+    let copied = std::io::copy(&mut file.take(size), &mut channel)
+        .map_err(|e| format!("failed to upload {}: {}", local_path.display(), e))?;
+    let _ = channel.send_eof();
+    let _ = channel.wait_eof();
+    let _ = channel.close();
+    let _ = channel.wait_close();
+    if copied != size {
+        return Err(format!(
+            "{} changed during the upload: sent {} of {} bytes",
+            local_path.display(),
+            copied,
+            size
+        ));
+    }
+    Ok(())
+}
+
+/// Copy `remote_path` from the device to a local file over SCP, returning the
+/// number of bytes written. The file is streamed, so large files are fine.
+pub fn download(device_id: i64, remote_path: &str, local_path: &Path) -> Result<u64, String> {
+    let handle = session_handle(device_id)?;
+
+    let (mut channel, stat) = {
+        let sess = handle.session.lock();
+        sess.scp_recv(Path::new(remote_path))
+            .map_err(|e| format!("failed to read {}: {}", remote_path, e))?
+    };
+    let mut file = std::fs::File::create(local_path)
+        .map_err(|e| format!("failed to create {}: {}", local_path.display(), e))?;
+    let copied = std::io::copy(&mut (&mut channel).take(stat.size()), &mut file)
+        .map_err(|e| e.to_string())?;
+    let _ = channel.send_eof();
+    let _ = channel.wait_eof();
+    let _ = channel.close();
+    let _ = channel.wait_close();
+    if copied != stat.size() {
+        return Err(format!(
+            "{} was cut short: got {} of {} bytes",
+            remote_path,
+            copied,
+            stat.size()
+        ));
+    }
+    Ok(copied)
+}
+
+/// Quote a value so it is passed to the remote shell as a single literal word.
+pub fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
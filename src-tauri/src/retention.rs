@@ -0,0 +1,382 @@
+//! Retention and downsampling of stored stats.
+//!
+//! Raw samples (`device_stats` / `device_metrics`) are rolled into 1-minute
+//! buckets and those into 1-hour buckets in `metric_rollup`, keeping min/avg/max
+//! per series. A background job rolls up settled buckets and prunes each level
+//! once it is older than the retention policy allows, but never before it has
+//! been rolled into the next level.
+
+use crate::db::db_conn;
+use crate::metrics;
+use crate::types::{CompactionReport, RetentionPolicy};
+use log::warn;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+pub const MINUTE_MS: i64 = 60_000;
+pub const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 86_400_000;
+
+// Buckets are only rolled once they are this far in the past so late samples still count
+const SETTLE_MS: i64 = 2 * MINUTE_MS;
+const COMPACT_EVERY: Duration = Duration::from_secs(600);
+// Work is split into windows of this size so writers aren't blocked for long
+const RAW_WINDOW_MS: i64 = HOUR_MS;
+const MINUTE_WINDOW_MS: i64 = DAY_MS;
+
+// Longest range get_stats serves from each resolution when picking automatically
+pub const RAW_MAX_SPAN_MS: i64 = 2 * HOUR_MS;
+const MINUTE_MAX_SPAN_MS: i64 = 2 * DAY_MS;
+
+pub const DEFAULT_POLICY: RetentionPolicy = RetentionPolicy {
+    raw_days: 7,
+    minute_days: Some(90),
+    hour_days: None,
+};
+
+// device_stats columns and the series they are stored as in device_metrics.
+// Rolling the columns too covers samples recorded before device_metrics existed.
+pub const CORE_SERIES: &[(&str, &str)] = &[
+    ("cpu", "cpu"),
+    ("ram_used_mb", "ram.used"),
+    ("ram_total_mb", "ram.total"),
+    ("gpu_util", "gpu.load"),
+    ("gpu_temp_c", "temp.gpu"),
+    ("fan_rpm", "fan.rpm"),
+    ("fan_pwm", "fan.pwm"),
+];
+
+/// Aggregate of one series over one bucket.
+pub struct Bucket {
+    pub samples: i64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+pub fn load_policy(conn: &Connection) -> Result<RetentionPolicy, String> {
+    let policy = conn
+        .query_row(
+            "SELECT raw_days, minute_days, hour_days FROM retention_policy WHERE id = 1",
+            [],
+            |row| {
+                Ok(RetentionPolicy {
+                    raw_days: row.get(0)?,
+                    minute_days: row.get(1)?,
+                    hour_days: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(policy.unwrap_or(DEFAULT_POLICY))
+}
+
+pub fn save_policy(conn: &Connection, policy: &RetentionPolicy) -> Result<(), String> {
+    if policy.raw_days < 1 {
+        return Err("raw samples must be kept at least 1 day".to_string());
+    }
+    if policy.minute_days.is_some_and(|d| d < 1) || policy.hour_days.is_some_and(|d| d < 1) {
+        return Err("rollups must be kept at least 1 day".to_string());
+    }
+    conn.execute(
+        "INSERT INTO retention_policy (id, raw_days, minute_days, hour_days) VALUES (1, ?1, ?2, ?3)
+         ON CONFLICT(id) DO UPDATE SET raw_days = ?1, minute_days = ?2, hour_days = ?3",
+        params![policy.raw_days, policy.minute_days, policy.hour_days],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Everything before the returned ts has been rolled up at `resolution_ms`.
+fn watermark(conn: &Connection, resolution_ms: i64) -> Result<Option<i64>, String> {
+    conn.query_row(
+        "SELECT rolled_until FROM rollup_watermark WHERE resolution_ms = ?1",
+        [resolution_ms],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+fn set_watermark(conn: &Connection, resolution_ms: i64, ts: i64) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO rollup_watermark (resolution_ms, rolled_until) VALUES (?1, ?2)
+         ON CONFLICT(resolution_ms) DO UPDATE SET rolled_until = ?2",
+        params![resolution_ms, ts],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Move watermarks back to `ts` so rows inserted behind them (e.g. by an import)
+/// get rolled up by the next compaction. Existing buckets are kept as they are.
+pub fn rewind(conn: &Connection, ts: i64) -> Result<(), String> {
+    for resolution_ms in [MINUTE_MS, HOUR_MS] {
+        conn.execute(
+            "UPDATE rollup_watermark SET rolled_until = ?2 WHERE resolution_ms = ?1 AND rolled_until > ?2",
+            params![resolution_ms, floor_to(ts, resolution_ms)],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn floor_to(ts: i64, step: i64) -> i64 {
+    ts.div_euclid(step) * step
+}
+
+/// Roll raw samples in [from, to) into 1-minute buckets.
+fn roll_raw(conn: &Connection, from: i64, to: i64) -> Result<usize, String> {
+    let mut rolled = conn
+        .execute(
+            "INSERT OR IGNORE INTO metric_rollup (resolution_ms, device_id, series_id, ts, samples, min, avg, max)
+             SELECT ?1, device_id, series_id, (ts / ?1) * ?1, COUNT(*), MIN(value), AVG(value), MAX(value)
+             FROM device_metrics WHERE ts >= ?2 AND ts < ?3
+             GROUP BY device_id, series_id, ts / ?1",
+            params![MINUTE_MS, from, to],
+        )
+        .map_err(|e| e.to_string())?;
+    // Fills in buckets that only have device_stats rows; the rest already exist
+    for (column, name) in CORE_SERIES {
+        let series_id = metrics::series_id(conn, name)?;
+        let sql = format!(
+            "INSERT OR IGNORE INTO metric_rollup (resolution_ms, device_id, series_id, ts, samples, min, avg, max)
+             SELECT ?1, device_id, ?2, (ts / ?1) * ?1, COUNT({c}), MIN({c}), AVG({c}), MAX({c})
+             FROM device_stats WHERE ts >= ?3 AND ts < ?4 AND {c} IS NOT NULL
+             GROUP BY device_id, ts / ?1",
+            c = column
+        );
+        rolled += conn
+            .execute(&sql, params![MINUTE_MS, series_id, from, to])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(rolled)
+}
+
+/// Roll 1-minute buckets in [from, to) into 1-hour buckets.
+fn roll_minutes(conn: &Connection, from: i64, to: i64) -> Result<usize, String> {
+    conn.execute(
+        "INSERT OR IGNORE INTO metric_rollup (resolution_ms, device_id, series_id, ts, samples, min, avg, max)
+         SELECT ?2, device_id, series_id, (ts / ?2) * ?2, SUM(samples), MIN(min), SUM(avg * samples) / SUM(samples), MAX(max)
+         FROM metric_rollup WHERE resolution_ms = ?1 AND ts >= ?3 AND ts < ?4
+         GROUP BY device_id, series_id, ts / ?2",
+        params![MINUTE_MS, HOUR_MS, from, to],
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Advance one resolution's watermark to `target`, window by window.
+fn advance(
+    conn: &mut Connection,
+    resolution_ms: i64,
+    window_ms: i64,
+    target: i64,
+    first_ts: &str,
+    roll: fn(&Connection, i64, i64) -> Result<usize, String>,
+) -> Result<(usize, i64), String> {
+    let start = match watermark(conn, resolution_ms)? {
+        Some(wm) => wm,
+        // First run: start at the oldest data instead of the epoch
+        None => {
+            let oldest: Option<i64> = conn
+                .query_row(first_ts, [], |row| row.get(0))
+                .map_err(|e| e.to_string())?;
+            floor_to(oldest.unwrap_or(target).min(target), resolution_ms)
+        }
+    };
+
+    let mut rolled = 0;
+    let mut wm = start;
+    while wm < target {
+        let end = (wm + window_ms).min(target);
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        rolled += roll(&tx, wm, end)?;
+        set_watermark(&tx, resolution_ms, end)?;
+        tx.commit().map_err(|e| e.to_string())?;
+        wm = end;
+    }
+    if start >= target {
+        // Nothing to roll yet, but record where to start next time
+        set_watermark(conn, resolution_ms, start)?;
+    }
+    Ok((rolled, wm))
+}
+
+/// Roll up every settled bucket, then prune what the policy no longer keeps.
+pub fn compact(conn: &mut Connection) -> Result<CompactionReport, String> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let policy = load_policy(conn)?;
+    // Resolve ids outside the rollup transactions so a rollback can't leave a
+    // stale id in the series cache
+    for (_, name) in CORE_SERIES {
+        metrics::series_id(conn, name)?;
+    }
+
+    let (rolled_minute, minute_wm) = advance(
+        conn,
+        MINUTE_MS,
+        RAW_WINDOW_MS,
+        floor_to(now - SETTLE_MS, MINUTE_MS),
+        "SELECT MIN(ts) FROM (SELECT MIN(ts) AS ts FROM device_stats UNION ALL SELECT MIN(ts) FROM device_metrics)",
+        roll_raw,
+    )?;
+    let (rolled_hour, hour_wm) = advance(
+        conn,
+        HOUR_MS,
+        MINUTE_WINDOW_MS,
+        floor_to(minute_wm, HOUR_MS),
+        "SELECT MIN(ts) FROM metric_rollup WHERE resolution_ms = 60000",
+        roll_minutes,
+    )?;
+
+    let mut report = CompactionReport {
+        rolled_minute,
+        rolled_hour,
+        pruned_raw: 0,
+        pruned_minute: 0,
+        pruned_hour: 0,
+    };
+
+    let raw_cutoff = (now - policy.raw_days * DAY_MS).min(minute_wm);
+    let devices: Vec<i64> = {
+        let mut stmt = conn
+            .prepare("SELECT DISTINCT device_id FROM device_stats UNION SELECT DISTINCT device_id FROM device_metrics")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?
+    };
+    // Per device so each delete walks the (device_id, ts) indexes
+    for device_id in devices {
+        for table in ["device_stats", "device_metrics"] {
+            report.pruned_raw += conn
+                .execute(
+                    &format!("DELETE FROM {} WHERE device_id = ?1 AND ts < ?2", table),
+                    params![device_id, raw_cutoff],
+                )
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    if let Some(days) = policy.minute_days {
+        report.pruned_minute = conn
+            .execute(
+                "DELETE FROM metric_rollup WHERE resolution_ms = ?1 AND ts < ?2",
+                params![MINUTE_MS, (now - days * DAY_MS).min(hour_wm)],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(days) = policy.hour_days {
+        report.pruned_hour = conn
+            .execute(
+                "DELETE FROM metric_rollup WHERE resolution_ms = ?1 AND ts < ?2",
+                params![HOUR_MS, now - days * DAY_MS],
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(report)
+}
+
+/// Start the background compaction job.
+pub fn start() {
+    thread::spawn(|| loop {
+        match db_conn() {
+            Ok(mut conn) => {
+                if let Err(e) = compact(&mut conn) {
+                    warn!("stats compaction failed: {}", e);
+                }
+            }
+            Err(e) => warn!("stats compaction: {}", e),
+        }
+        thread::sleep(COMPACT_EVERY);
+    });
+}
+
+/// Finest resolution that still holds data for `start_ts` without returning an
+/// unreasonable number of points. None means raw samples, which are only used
+/// for ranges up to `raw_max_span_ms`.
+pub fn pick_resolution(
+    conn: &Connection,
+    device_id: i64,
+    start_ts: i64,
+    end_ts: i64,
+    raw_max_span_ms: i64,
+) -> Result<Option<i64>, String> {
+    let span = end_ts - start_ts;
+    let oldest_raw: Option<i64> = conn
+        .query_row(
+            "SELECT MIN(ts) FROM device_stats WHERE device_id = ?1",
+            [device_id],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    if span <= raw_max_span_ms && oldest_raw.is_some_and(|t| t <= start_ts) {
+        return Ok(None);
+    }
+    let policy = load_policy(conn)?;
+    let now = chrono::Utc::now().timestamp_millis();
+    let minute_kept = policy
+        .minute_days
+        .is_none_or(|d| start_ts >= now - d * DAY_MS);
+    if span <= MINUTE_MAX_SPAN_MS && minute_kept {
+        return Ok(Some(MINUTE_MS));
+    }
+    Ok(Some(HOUR_MS))
+}
+
+/// Buckets of the requested series between `start_ts` and `end_ts`. Stored rollups
+/// are used up to the watermark; the not yet rolled tail is aggregated on the fly.
+pub fn query(
+    conn: &Connection,
+    device_id: i64,
+    resolution_ms: i64,
+    names: &[String],
+    start_ts: i64,
+    end_ts: i64,
+) -> Result<BTreeMap<i64, BTreeMap<String, Bucket>>, String> {
+    let wm = watermark(conn, resolution_ms)?.unwrap_or(i64::MIN);
+    let mut bind: Vec<rusqlite::types::Value> = vec![
+        resolution_ms.into(),
+        device_id.into(),
+        floor_to(start_ts, resolution_ms).into(),
+        end_ts.into(),
+        wm.into(),
+        start_ts.max(wm).into(),
+    ];
+    let stored_filter = metrics::name_filter("s.name", names, &mut bind);
+    let live_filter = metrics::name_filter("s.name", names, &mut bind);
+    let sql = format!(
+        "SELECT r.ts, s.name, r.samples, r.min, r.avg, r.max FROM metric_rollup r JOIN metric_series s ON s.id = r.series_id
+         WHERE r.resolution_ms = ?1 AND r.device_id = ?2 AND r.ts >= ?3 AND r.ts <= ?4 AND r.ts < ?5 AND {}
+         UNION ALL
+         SELECT (m.ts / ?1) * ?1, s.name, COUNT(*), MIN(m.value), AVG(m.value), MAX(m.value) FROM device_metrics m JOIN metric_series s ON s.id = m.series_id
+         WHERE m.device_id = ?2 AND m.ts >= ?6 AND m.ts <= ?4 AND {}
+         GROUP BY m.ts / ?1, s.id",
+        stored_filter, live_filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params_from_iter(bind), |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                Bucket {
+                    samples: row.get(2)?,
+                    min: row.get(3)?,
+                    avg: row.get(4)?,
+                    max: row.get(5)?,
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut out: BTreeMap<i64, BTreeMap<String, Bucket>> = BTreeMap::new();
+    for r in rows {
+        let (ts, name, bucket) = r.map_err(|e| e.to_string())?;
+        out.entry(ts).or_default().insert(name, bucket);
+    }
+    Ok(out)
+}
@@ -0,0 +1,536 @@
+//! Benchmark runs: a command executed on the device while its stats are recorded.
+//!
+//! A run subscribes to the device's collector at a high sampling rate for as long
+//! as the command runs. Samples stored meanwhile are tagged with the run id in
+//! `device_stats`, and a summary (power, temperatures, clocks, load) is kept with
+//! the run in `benchmark_run`. The raw samples follow the normal retention policy;
+//! the summary stays until the run is deleted.
+
+use crate::commands::connection::open_dedicated_session;
+use crate::types::{BenchmarkRun, RunComparison, RunMetricDelta, RunSummary, StatPoint};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Combined output kept per run; longer output keeps its tail
+const MAX_OUTPUT_BYTES: usize = 1 << 20;
+// How often a blocked read gives up to check for cancellation and new samples
+const POLL_MS: u32 = 200;
+// Rails that measure the whole board's input, preferred over summing rails
+const INPUT_RAILS: &[&str] = &["power.vdd_in", "power.pom_5v_in"];
+
+// Run in progress per device; samples stored meanwhile are tagged with it
+static ACTIVE: Lazy<Mutex<HashMap<i64, i64>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// The run in progress on a device, if any.
+pub fn active(device_id: i64) -> Option<i64> {
+    ACTIVE.lock().get(&device_id).copied()
+}
+
+/// Create the run row and make it the device's active run.
+pub fn begin(
+    conn: &Connection,
+    device_id: i64,
+    name: Option<&str>,
+    command: &str,
+    sample_interval_ms: u64,
+) -> Result<i64, String> {
+    let mut active = ACTIVE.lock();
+    if let Some(id) = active.get(&device_id) {
+        return Err(format!("run {} is still in progress on this device", id));
+    }
+    conn.execute(
+        "INSERT INTO benchmark_run (device_id, name, command, status, started_at, sample_interval_ms) VALUES (?1, ?2, ?3, 'running', ?4, ?5)",
+        params![
+            device_id,
+            name,
+            command,
+            chrono::Utc::now().timestamp_millis(),
+            sample_interval_ms as i64
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid();
+    active.insert(device_id, id);
+    Ok(id)
+}
+
+/// Release the device held by a run, so new runs can start and samples are no
+/// longer tagged with it.
+pub fn release(run_id: i64) {
+    ACTIVE.lock().retain(|_, id| *id != run_id);
+}
+
+/// How a run's command ended.
+pub enum Outcome {
+    Exited(i32),
+    Cancelled,
+    Failed(String),
+}
+
+/// Record the outcome and summary and release the device.
+pub fn finish(
+    conn: &Connection,
+    run_id: i64,
+    outcome: &Outcome,
+    output: &OutputTail,
+    summary: &RunSummary,
+) -> Result<BenchmarkRun, String> {
+    release(run_id);
+    let (status, exit_status, error) = match outcome {
+        Outcome::Exited(0) => ("succeeded", Some(0), None),
+        Outcome::Exited(code) => ("failed", Some(*code), None),
+        Outcome::Cancelled => ("cancelled", None, None),
+        Outcome::Failed(e) => ("error", None, Some(e.as_str())),
+    };
+    conn.execute(
+        "UPDATE benchmark_run SET status = ?2, ended_at = ?3, exit_status = ?4, output = ?5, output_truncated = ?6, error = ?7,
+            samples = ?8, avg_power_mw = ?9, peak_power_mw = ?10, energy_j = ?11, avg_cpu = ?12, peak_cpu = ?13,
+            avg_gpu_util = ?14, peak_gpu_util = ?15, avg_gpu_temp_c = ?16, peak_gpu_temp_c = ?17, peak_temp_c = ?18,
+            avg_cpu_freq_mhz = ?19, avg_gpu_freq_mhz = ?20, avg_emc_freq_mhz = ?21, peak_ram_used_mb = ?22,
+            throttled = ?23, power_mode = ?24, clocks_locked = ?25
+         WHERE id = ?1",
+        params![
+            run_id,
+            status,
+            chrono::Utc::now().timestamp_millis(),
+            exit_status,
+            output.text(),
+            output.truncated,
+            error,
+            summary.samples,
+            summary.avg_power_mw,
+            summary.peak_power_mw,
+            summary.energy_j,
+            summary.avg_cpu,
+            summary.peak_cpu,
+            summary.avg_gpu_util,
+            summary.peak_gpu_util,
+            summary.avg_gpu_temp_c,
+            summary.peak_gpu_temp_c,
+            summary.peak_temp_c,
+            summary.avg_cpu_freq_mhz,
+            summary.avg_gpu_freq_mhz,
+            summary.avg_emc_freq_mhz,
+            summary.peak_ram_used_mb,
+            summary.throttled,
+            summary.power_mode,
+            summary.clocks_locked
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    load(conn, run_id, false)
+}
+
+/// Mark runs left "running" by a previous session as interrupted.
+pub fn close_stale(conn: &Connection) {
+    let _ = conn.execute(
+        "UPDATE benchmark_run SET status = 'error', error = 'interrupted: the app exited during the run',
+            ended_at = COALESCE((SELECT MAX(ts) FROM device_stats WHERE device_stats.run_id = benchmark_run.id), started_at)
+         WHERE status = 'running'",
+        [],
+    );
+}
+
+/// Output of a run, keeping at most the last `MAX_OUTPUT_BYTES`.
+#[derive(Default)]
+pub struct OutputTail {
+    text: String,
+    truncated: bool,
+}
+
+impl OutputTail {
+    pub fn push_line(&mut self, line: &str) {
+        self.text.push_str(line);
+        self.text.push('\n');
+        if self.text.len() > MAX_OUTPUT_BYTES {
+            // Drop whole lines from the front. The excess may fall inside a
+            // multi-byte character, so the newline is found in bytes; the byte
+            // after it always starts a character
+            let excess = self.text.len() - MAX_OUTPUT_BYTES;
+            let cut = self.text.as_bytes()[excess..]
+                .iter()
+                .position(|&b| b == b'\n')
+                .map(|i| excess + i + 1)
+                .unwrap_or(self.text.len());
+            self.text.drain(..cut);
+            self.truncated = true;
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// Run `command` on the device over a dedicated session, passing each output
+/// line to `on_line`. `on_idle` is called between reads, at least every
+/// `POLL_MS`. A pty is requested so tools line-buffer their output and the
+/// command gets SIGHUP when a cancelled run closes the channel.
+pub fn execute(
+    device_id: i64,
+    command: &str,
+    cancel: &AtomicBool,
+    mut on_line: impl FnMut(&str),
+    mut on_idle: impl FnMut(),
+) -> Outcome {
+    let mut run = || -> Result<Outcome, String> {
+        let sess = open_dedicated_session(device_id)?;
+        let mut channel = sess.channel_session().map_err(|e| e.to_string())?;
+        channel
+            .request_pty("dumb", None, None)
+            .map_err(|e| e.to_string())?;
+        channel.exec(command).map_err(|e| e.to_string())?;
+        sess.set_timeout(POLL_MS);
+
+        let mut pending: Vec<u8> = Vec::new();
+        let mut buf = [0u8; 8192];
+        loop {
+            if cancel.load(Ordering::Relaxed) {
+                let _ = channel.close();
+                return Ok(Outcome::Cancelled);
+            }
+            match channel.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    pending.extend_from_slice(&buf[..n]);
+                    while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                        let line: Vec<u8> = pending.drain(..=pos).collect();
+                        on_line(String::from_utf8_lossy(&line).trim_end_matches(['\r', '\n']));
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => {}
+                Err(e) => return Err(e.to_string()),
+            }
+            on_idle();
+        }
+        if !pending.is_empty() {
+            on_line(String::from_utf8_lossy(&pending).trim_end_matches('\r'));
+        }
+        sess.set_timeout(10_000);
+        let _ = channel.wait_close();
+        let code = channel.exit_status().map_err(|e| e.to_string())?;
+        Ok(Outcome::Exited(code))
+    };
+    run().unwrap_or_else(Outcome::Failed)
+}
+
+#[derive(Default, Clone, Copy)]
+struct Stat {
+    count: i64,
+    sum: f64,
+    max: f64,
+}
+
+impl Stat {
+    fn add(&mut self, v: f64) {
+        if !v.is_finite() {
+            return;
+        }
+        self.max = if self.count == 0 { v } else { self.max.max(v) };
+        self.count += 1;
+        self.sum += v;
+    }
+
+    fn avg(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
+
+    fn peak(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+}
+
+/// Board input power of a sample, mW.
+pub fn board_power_mw(p: &StatPoint) -> Option<f64> {
+    if let Some(v) = INPUT_RAILS.iter().find_map(|r| p.metrics.get(*r)) {
+        return Some(*v);
+    }
+    let rails: Vec<f64> = p
+        .metrics
+        .iter()
+        .filter(|(k, _)| k.starts_with("power.") && !k.ends_with(".avg"))
+        .map(|(_, v)| *v)
+        .collect();
+    (!rails.is_empty()).then(|| rails.iter().sum())
+}
+
+/// Accumulates a run's samples into a `RunSummary`.
+#[derive(Default)]
+pub struct Recorder {
+    samples: i64,
+    power: Stat,
+    cpu: Stat,
+    gpu_util: Stat,
+    gpu_temp: Stat,
+    temp: Stat,
+    cpu_freq: Stat,
+    gpu_freq: Stat,
+    emc_freq: Stat,
+    ram: Stat,
+    // Integrated power, mW * ms
+    energy: f64,
+    last_power: Option<(i64, f64)>,
+    throttled: bool,
+    power_mode: Option<String>,
+    clocks_locked: Option<bool>,
+}
+
+impl Recorder {
+    pub fn add(&mut self, p: &StatPoint) {
+        if self.samples == 0 {
+            self.power_mode = p.power_mode.clone();
+            self.clocks_locked = p.clocks_locked;
+        }
+        self.samples += 1;
+        if let Some(mw) = board_power_mw(p) {
+            self.power.add(mw);
+            if let Some((ts, prev)) = self.last_power {
+                if p.ts > ts {
+                    self.energy += (prev + mw) / 2.0 * (p.ts - ts) as f64;
+                }
+            }
+            self.last_power = Some((p.ts, mw));
+        }
+        self.cpu.add(p.cpu);
+        if let Some(v) = p.gpu_util {
+            self.gpu_util.add(v);
+        }
+        if let Some(v) = p.gpu_temp_c {
+            self.gpu_temp.add(v);
+        }
+        // PMIC reports a fixed placeholder on some boards
+        let hottest = p
+            .metrics
+            .iter()
+            .filter(|(k, _)| k.starts_with("temp.") && k.as_str() != "temp.pmic")
+            .map(|(_, v)| *v)
+            .fold(None, |acc: Option<f64>, v| {
+                Some(acc.map_or(v, |a| a.max(v)))
+            });
+        if let Some(v) = hottest {
+            self.temp.add(v);
+        }
+        let cores: Vec<f64> = p
+            .metrics
+            .iter()
+            .filter(|(k, _)| k.starts_with("cpu.core") && k.ends_with(".freq"))
+            .map(|(_, v)| *v)
+            .collect();
+        if !cores.is_empty() {
+            self.cpu_freq
+                .add(cores.iter().sum::<f64>() / cores.len() as f64);
+        }
+        if let Some(v) = p.metrics.get("gpu.freq") {
+            self.gpu_freq.add(*v);
+        }
+        if let Some(v) = p.metrics.get("emc.freq") {
+            self.emc_freq.add(*v);
+        }
+        self.ram.add(p.ram_used_mb as f64);
+        self.throttled |= !p.throttling.is_empty();
+    }
+
+    pub fn summary(&self) -> RunSummary {
+        RunSummary {
+            samples: self.samples,
+            avg_power_mw: self.power.avg(),
+            peak_power_mw: self.power.peak(),
+            energy_j: (self.power.count > 1).then_some(self.energy / 1e6),
+            avg_cpu: self.cpu.avg(),
+            peak_cpu: self.cpu.peak(),
+            avg_gpu_util: self.gpu_util.avg(),
+            peak_gpu_util: self.gpu_util.peak(),
+            avg_gpu_temp_c: self.gpu_temp.avg(),
+            peak_gpu_temp_c: self.gpu_temp.peak(),
+            peak_temp_c: self.temp.peak(),
+            avg_cpu_freq_mhz: self.cpu_freq.avg(),
+            avg_gpu_freq_mhz: self.gpu_freq.avg(),
+            avg_emc_freq_mhz: self.emc_freq.avg(),
+            peak_ram_used_mb: self.ram.peak(),
+            throttled: self.throttled,
+            power_mode: self.power_mode.clone(),
+            clocks_locked: self.clocks_locked,
+        }
+    }
+}
+
+const RUN_COLUMNS: &str = "id, device_id, name, command, status, started_at, ended_at, exit_status, output_truncated, error, sample_interval_ms,
+    samples, avg_power_mw, peak_power_mw, energy_j, avg_cpu, peak_cpu, avg_gpu_util, peak_gpu_util, avg_gpu_temp_c, peak_gpu_temp_c,
+    peak_temp_c, avg_cpu_freq_mhz, avg_gpu_freq_mhz, avg_emc_freq_mhz, peak_ram_used_mb, throttled, power_mode, clocks_locked";
+
+fn run_from_row(row: &Row) -> rusqlite::Result<BenchmarkRun> {
+    let status: String = row.get(4)?;
+    let summary = if status == "running" {
+        None
+    } else {
+        Some(RunSummary {
+            samples: row.get::<_, Option<i64>>(11)?.unwrap_or(0),
+            avg_power_mw: row.get(12)?,
+            peak_power_mw: row.get(13)?,
+            energy_j: row.get(14)?,
+            avg_cpu: row.get(15)?,
+            peak_cpu: row.get(16)?,
+            avg_gpu_util: row.get(17)?,
+            peak_gpu_util: row.get(18)?,
+            avg_gpu_temp_c: row.get(19)?,
+            peak_gpu_temp_c: row.get(20)?,
+            peak_temp_c: row.get(21)?,
+            avg_cpu_freq_mhz: row.get(22)?,
+            avg_gpu_freq_mhz: row.get(23)?,
+            avg_emc_freq_mhz: row.get(24)?,
+            peak_ram_used_mb: row.get(25)?,
+            throttled: row.get::<_, Option<bool>>(26)?.unwrap_or(false),
+            power_mode: row.get(27)?,
+            clocks_locked: row.get(28)?,
+        })
+    };
+    Ok(BenchmarkRun {
+        id: row.get(0)?,
+        device_id: row.get(1)?,
+        name: row.get(2)?,
+        command: row.get(3)?,
+        status,
+        started_at: row.get(5)?,
+        ended_at: row.get(6)?,
+        exit_status: row.get(7)?,
+        output: None,
+        output_truncated: row.get(8)?,
+        error: row.get(9)?,
+        sample_interval_ms: row.get(10)?,
+        summary,
+    })
+}
+
+/// A run, with its output when `with_output` is set.
+pub fn load(conn: &Connection, run_id: i64, with_output: bool) -> Result<BenchmarkRun, String> {
+    let mut run = conn
+        .query_row(
+            &format!("SELECT {} FROM benchmark_run WHERE id = ?1", RUN_COLUMNS),
+            [run_id],
+            run_from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("run {} not found", run_id))?;
+    if with_output {
+        run.output = conn
+            .query_row(
+                "SELECT output FROM benchmark_run WHERE id = ?1",
+                [run_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(run)
+}
+
+/// Runs, newest first, optionally for one device.
+pub fn list(
+    conn: &Connection,
+    device_id: Option<i64>,
+    limit: i64,
+) -> Result<Vec<BenchmarkRun>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM benchmark_run WHERE ?1 IS NULL OR device_id = ?1 ORDER BY started_at DESC LIMIT ?2",
+            RUN_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![device_id, limit], run_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Delete a run and untag its samples.
+pub fn delete(conn: &Connection, run_id: i64) -> Result<(), String> {
+    if ACTIVE.lock().values().any(|id| *id == run_id) {
+        return Err(format!("run {} is still in progress", run_id));
+    }
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE device_stats SET run_id = NULL WHERE run_id = ?1",
+        [run_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let n = tx
+        .execute("DELETE FROM benchmark_run WHERE id = ?1", [run_id])
+        .map_err(|e| e.to_string())?;
+    if n == 0 {
+        return Err(format!("run {} not found", run_id));
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// First and last timestamp and count of the samples tagged with a run.
+pub fn sample_span(conn: &Connection, run_id: i64) -> Result<Option<(i64, i64, i64)>, String> {
+    conn.query_row(
+        "SELECT MIN(ts), MAX(ts), COUNT(*) FROM device_stats WHERE run_id = ?1",
+        [run_id],
+        |row| {
+            Ok((
+                row.get::<_, Option<i64>>(0)?,
+                row.get::<_, Option<i64>>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        },
+    )
+    .map(|(first, last, n)| first.zip(last).map(|(f, l)| (f, l, n)))
+    .map_err(|e| e.to_string())
+}
+
+// Name, unit and accessor of a compared summary metric
+type SummaryField = (&'static str, &'static str, fn(&RunSummary) -> Option<f64>);
+
+fn delta(metric: &str, unit: &str, a: Option<f64>, b: Option<f64>) -> RunMetricDelta {
+    let delta = a.zip(b).map(|(a, b)| b - a);
+    RunMetricDelta {
+        metric: metric.to_string(),
+        unit: unit.to_string(),
+        a,
+        b,
+        delta,
+        delta_pct: a
+            .zip(delta)
+            .filter(|(a, _)| *a != 0.0)
+            .map(|(a, d)| d / a.abs() * 100.0),
+    }
+}
+
+/// Two runs side by side, with the change of every summary metric from `a` to `b`.
+pub fn compare(conn: &Connection, a: i64, b: i64) -> Result<RunComparison, String> {
+    let a = load(conn, a, false)?;
+    let b = load(conn, b, false)?;
+    let (sa, sb) = (
+        a.summary.clone().unwrap_or_default(),
+        b.summary.clone().unwrap_or_default(),
+    );
+    let duration = |r: &BenchmarkRun| r.ended_at.map(|e| (e - r.started_at) as f64);
+    let mut metrics = vec![delta("duration", "ms", duration(&a), duration(&b))];
+    let fields: [SummaryField; 15] = [
+        ("avgPower", "mW", |s| s.avg_power_mw),
+        ("peakPower", "mW", |s| s.peak_power_mw),
+        ("energy", "J", |s| s.energy_j),
+        ("avgCpu", "%", |s| s.avg_cpu),
+        ("peakCpu", "%", |s| s.peak_cpu),
+        ("avgGpuUtil", "%", |s| s.avg_gpu_util),
+        ("peakGpuUtil", "%", |s| s.peak_gpu_util),
+        ("avgGpuTemp", "C", |s| s.avg_gpu_temp_c),
+        ("peakGpuTemp", "C", |s| s.peak_gpu_temp_c),
+        ("peakTemp", "C", |s| s.peak_temp_c),
+        ("avgCpuFreq", "MHz", |s| s.avg_cpu_freq_mhz),
+        ("avgGpuFreq", "MHz", |s| s.avg_gpu_freq_mhz),
+        ("avgEmcFreq", "MHz", |s| s.avg_emc_freq_mhz),
+        ("peakRamUsed", "MB", |s| s.peak_ram_used_mb),
+        ("samples", "", |s| Some(s.samples as f64)),
+    ];
+    for (name, unit, value) in fields {
+        metrics.push(delta(name, unit, value(&sa), value(&sb)));
+    }
+    Ok(RunComparison { a, b, metrics })
+}
@@ -0,0 +1,539 @@
+//! Push sinks: forward collected samples to InfluxDB (line protocol over HTTP
+//! or UDP) or an MQTT broker.
+//!
+//! Every sample is first written to the `sink_outbox` table, once per sink that
+//! applies to its device. A background worker drains the outbox in batches and
+//! only deletes messages the destination accepted, so nothing is lost while a
+//! broker is offline or the app is closed. Failed sends back off exponentially.
+
+use crate::db::db_conn;
+use crate::types::{MetricSink, SinkStatus, StatPoint};
+use log::warn;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rumqttc::{Client, Event, MqttOptions, Outgoing, Packet, QoS};
+use rusqlite::{params, Connection, Row};
+use std::collections::HashMap;
+use std::net::UdpSocket;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const KINDS: &[&str] = &["influx_http", "influx_udp", "mqtt"];
+pub const DEFAULT_TOPIC: &str = "orion/{device}/stats";
+
+const TICK: Duration = Duration::from_secs(1);
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BACKOFF_MS: i64 = 5 * 60 * 1000;
+// Oldest messages are dropped beyond this many per sink
+const MAX_OUTBOX: i64 = 100_000;
+// MQTT packet size limit; a full sample with per-core, rail, disk and network
+// metrics runs to tens of kB on large boards, well past rumqttc's 10 kB default
+const MAX_MQTT_PACKET: usize = 1024 * 1024;
+// Fixed header, topic length and packet id on top of topic and payload
+const MQTT_OVERHEAD: usize = 16;
+
+// Enabled sinks, reloaded after any sink change
+static SINKS: Lazy<Mutex<Option<Vec<MetricSink>>>> = Lazy::new(|| Mutex::new(None));
+// Delivery state per sink id
+static STATE: Lazy<Mutex<HashMap<i64, SinkState>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Default, Clone)]
+struct SinkState {
+    last_flush: i64,
+    failures: u32,
+    retry_at: i64,
+    last_error: Option<String>,
+    last_success: Option<i64>,
+}
+
+pub(crate) const SINK_COLUMNS: &str =
+    "id, device_id, name, kind, url, token, username, password, topic, qos, batch_size, flush_interval_ms, enabled";
+
+pub(crate) fn sink_from_row(row: &Row) -> rusqlite::Result<MetricSink> {
+    Ok(MetricSink {
+        id: row.get(0)?,
+        device_id: row.get(1)?,
+        name: row.get(2)?,
+        kind: row.get(3)?,
+        url: row.get(4)?,
+        token: row.get(5)?,
+        username: row.get(6)?,
+        password: row.get(7)?,
+        topic: row.get(8)?,
+        qos: row.get(9)?,
+        batch_size: row.get(10)?,
+        flush_interval_ms: row.get(11)?,
+        enabled: row.get(12)?,
+    })
+}
+
+/// Drop the cached sinks after they change.
+pub fn invalidate_sinks() {
+    *SINKS.lock() = None;
+}
+
+/// Forget a deleted sink's delivery state.
+pub fn forget(sink_id: i64) {
+    STATE.lock().remove(&sink_id);
+}
+
+pub fn validate(sink: &MetricSink) -> Result<(), String> {
+    if !KINDS.contains(&sink.kind.as_str()) {
+        return Err(format!("unknown sink kind: {}", sink.kind));
+    }
+    if sink.url.trim().is_empty() {
+        return Err("url is required".to_string());
+    }
+    if sink.qos > 2 {
+        return Err("qos must be 0, 1 or 2".to_string());
+    }
+    if sink.batch_size < 1 {
+        return Err("batch size must be at least 1".to_string());
+    }
+    if sink.flush_interval_ms < 100 {
+        return Err("flush interval must be at least 100 ms".to_string());
+    }
+    if sink.kind == "mqtt" {
+        broker_addr(&sink.url)?;
+    }
+    Ok(())
+}
+
+fn load_sinks(conn: &Connection) -> Result<Vec<MetricSink>, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM metric_sink WHERE enabled = 1",
+            SINK_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], sink_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn enabled_sinks(conn: &Connection) -> Result<Vec<MetricSink>, String> {
+    let mut cached = SINKS.lock();
+    if cached.is_none() {
+        *cached = Some(load_sinks(conn)?);
+    }
+    Ok(cached.clone().unwrap_or_default())
+}
+
+// Line protocol escaping for measurement names, tag keys/values and field keys
+fn escape_key(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn escape_str(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// One InfluxDB line for a sample: core values and every named series as
+/// fields of the "orion" measurement, tagged with the device.
+pub fn line_protocol(point: &StatPoint, device: &str) -> String {
+    let mut fields: Vec<String> = Vec::new();
+    // NaN and infinities can't be written
+    let mut float = |name: &str, v: f64| {
+        if v.is_finite() {
+            fields.push(format!("{}={}", escape_key(name), v));
+        }
+    };
+    float("cpu", point.cpu);
+    if let Some(v) = point.gpu_util {
+        float("gpu_util", v);
+    }
+    if let Some(v) = point.gpu_temp_c {
+        float("gpu_temp_c", v);
+    }
+    for (name, value) in &point.metrics {
+        float(name, *value);
+    }
+    fields.push(format!("ram_used_mb={}i", point.ram_used_mb));
+    fields.push(format!("ram_total_mb={}i", point.ram_total_mb));
+    if let Some(v) = point.fan_rpm {
+        fields.push(format!("fan_rpm={}i", v));
+    }
+    if let Some(v) = point.fan_pwm {
+        fields.push(format!("fan_pwm={}i", v));
+    }
+    if let Some(v) = point.clocks_locked {
+        fields.push(format!("clocks_locked={}", v));
+    }
+    if let Some(v) = &point.power_mode {
+        fields.push(format!("power_mode=\"{}\"", escape_str(v)));
+    }
+    format!(
+        "orion,device_id={},device={} {} {}",
+        point.device_id,
+        escape_key(device),
+        fields.join(","),
+        point.ts * 1_000_000
+    )
+}
+
+/// Topic for a sample: `{device}` is the device name (with MQTT wildcards and
+/// separators replaced), `{device_id}` its id.
+pub fn topic_for(template: Option<&str>, device_id: i64, device: &str) -> String {
+    let name: String = device
+        .chars()
+        .map(|c| if matches!(c, '/' | '+' | '#') { '_' } else { c })
+        .collect();
+    template
+        .filter(|t| !t.trim().is_empty())
+        .unwrap_or(DEFAULT_TOPIC)
+        .replace("{device_id}", &device_id.to_string())
+        .replace("{device}", &name)
+}
+
+fn json_payload(point: &StatPoint, device: &str) -> Result<String, String> {
+    let mut value = serde_json::to_value(point).map_err(|e| e.to_string())?;
+    if let Some(obj) = value.as_object_mut() {
+        obj.insert("deviceName".to_string(), device.into());
+    }
+    serde_json::to_string(&value).map_err(|e| e.to_string())
+}
+
+/// (topic, payload) of a sample for a sink; the topic is only used by MQTT.
+fn encode(
+    sink: &MetricSink,
+    point: &StatPoint,
+    device: &str,
+) -> Result<(Option<String>, String), String> {
+    if sink.kind == "mqtt" {
+        Ok((
+            Some(topic_for(sink.topic.as_deref(), point.device_id, device)),
+            json_payload(point, device)?,
+        ))
+    } else {
+        Ok((None, line_protocol(point, device)))
+    }
+}
+
+fn device_name(conn: &Connection, device_id: i64) -> String {
+    conn.query_row(
+        "SELECT name FROM device WHERE id = ?1",
+        [device_id],
+        |row| row.get(0),
+    )
+    .unwrap_or_else(|_| device_id.to_string())
+}
+
+/// Queue a stored sample for every sink that applies to its device.
+pub fn enqueue(conn: &Connection, point: &StatPoint) {
+    let sinks = match enabled_sinks(conn) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("failed to load metric sinks: {}", e);
+            return;
+        }
+    };
+    let sinks: Vec<&MetricSink> = sinks
+        .iter()
+        .filter(|s| s.device_id.is_none_or(|d| d == point.device_id))
+        .collect();
+    if sinks.is_empty() {
+        return;
+    }
+    let device = device_name(conn, point.device_id);
+    for sink in sinks {
+        let Some(sink_id) = sink.id else {
+            continue;
+        };
+        let queued = encode(sink, point, &device).and_then(|(topic, payload)| {
+            conn.execute(
+                "INSERT INTO sink_outbox (sink_id, device_id, topic, payload, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![sink_id, point.device_id, topic, payload, point.ts],
+            )
+            .map_err(|e| e.to_string())
+        });
+        if let Err(e) = queued {
+            warn!("failed to queue sample for sink {}: {}", sink_id, e);
+        }
+    }
+}
+
+/// "mqtt://host:1883", "tcp://host" or "host:port" -> (host, port)
+fn broker_addr(url: &str) -> Result<(String, u16), String> {
+    let url = url.trim();
+    if url.starts_with("mqtts://") || url.starts_with("ssl://") {
+        return Err("TLS brokers are not supported".to_string());
+    }
+    let rest = url
+        .strip_prefix("mqtt://")
+        .or_else(|| url.strip_prefix("tcp://"))
+        .unwrap_or(url)
+        .trim_end_matches('/');
+    match rest.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() => port
+            .parse()
+            .map(|p| (host.to_string(), p))
+            .map_err(|_| format!("invalid broker port: {}", port)),
+        None if !rest.is_empty() => Ok((rest.to_string(), 1883)),
+        _ => Err(format!("invalid broker url: {}", url)),
+    }
+}
+
+fn send_influx_http(sink: &MetricSink, payloads: &[String]) -> Result<(), String> {
+    let mut req = ureq::post(sink.url.trim()).timeout(SEND_TIMEOUT);
+    if let Some(token) = sink.token.as_deref().filter(|t| !t.is_empty()) {
+        req = req.set("Authorization", &format!("Token {}", token));
+    }
+    match req
+        .set("Content-Type", "text/plain; charset=utf-8")
+        .send_string(&payloads.join("\n"))
+    {
+        Ok(_) => Ok(()),
+        // Rejected data won't be accepted on retry either
+        Err(ureq::Error::Status(code, resp)) if (400..500).contains(&code) && code != 429 => {
+            let body = resp.into_string().unwrap_or_default();
+            warn!(
+                "sink {:?} rejected {} lines ({}): {}",
+                sink.name,
+                payloads.len(),
+                code,
+                body.trim()
+            );
+            Ok(())
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn send_influx_udp(sink: &MetricSink, payloads: &[String]) -> Result<(), String> {
+    let target = sink.url.trim().trim_start_matches("udp://");
+    let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+    for line in payloads {
+        socket
+            .send_to(line.as_bytes(), target)
+            .map_err(|e| format!("failed to send to {}: {}", target, e))?;
+    }
+    Ok(())
+}
+
+/// Publish a batch over a fresh connection and wait until the broker has
+/// acknowledged all of it (or, at QoS 0, until it has all been written).
+fn send_mqtt(sink: &MetricSink, messages: &[(String, String)]) -> Result<(), String> {
+    let (host, port) = broker_addr(&sink.url)?;
+    let client_id = format!("orion-{}-{}", sink.id.unwrap_or(0), std::process::id());
+    let mut opts = MqttOptions::new(client_id, host, port);
+    opts.set_keep_alive(Duration::from_secs(30));
+    opts.set_max_packet_size(MAX_MQTT_PACKET, MAX_MQTT_PACKET);
+    if let Some(user) = sink.username.as_deref().filter(|u| !u.is_empty()) {
+        opts.set_credentials(user, sink.password.clone().unwrap_or_default());
+    }
+    let qos = match sink.qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    };
+
+    // Oversized messages would fail the batch on every retry, so like rejected
+    // HTTP writes they are dropped
+    let (messages, oversized): (Vec<_>, Vec<_>) = messages.iter().partition(|(topic, payload)| {
+        topic.len() + payload.len() + MQTT_OVERHEAD <= MAX_MQTT_PACKET
+    });
+    if !oversized.is_empty() {
+        warn!(
+            "sink {:?} dropped {} messages over the {} byte MQTT packet limit",
+            sink.name,
+            oversized.len(),
+            MAX_MQTT_PACKET
+        );
+    }
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let (client, mut connection) = Client::new(opts, messages.len() + 1);
+    for (topic, payload) in &messages {
+        client
+            .publish(topic.as_str(), qos, false, payload.as_bytes())
+            .map_err(|e| e.to_string())?;
+    }
+
+    let deadline = Instant::now() + SEND_TIMEOUT;
+    let mut done = 0;
+    while done < messages.len() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let event = connection
+            .recv_timeout(left)
+            .map_err(|_| "timed out waiting for the broker".to_string())?
+            .map_err(|e| e.to_string())?;
+        match (qos, event) {
+            (QoS::AtMostOnce, Event::Outgoing(Outgoing::Publish(_)))
+            | (QoS::AtLeastOnce, Event::Incoming(Packet::PubAck(_)))
+            | (QoS::ExactlyOnce, Event::Incoming(Packet::PubComp(_))) => done += 1,
+            _ => {}
+        }
+    }
+    let _ = client.disconnect();
+    let _ = connection.recv_timeout(Duration::from_millis(500));
+    Ok(())
+}
+
+fn send(sink: &MetricSink, batch: &[(Option<String>, String)]) -> Result<(), String> {
+    match sink.kind.as_str() {
+        "influx_http" | "influx_udp" => {
+            let lines: Vec<String> = batch.iter().map(|(_, p)| p.clone()).collect();
+            if sink.kind == "influx_http" {
+                send_influx_http(sink, &lines)
+            } else {
+                send_influx_udp(sink, &lines)
+            }
+        }
+        _ => {
+            let messages: Vec<(String, String)> = batch
+                .iter()
+                .map(|(topic, p)| (topic.clone().unwrap_or_default(), p.clone()))
+                .collect();
+            send_mqtt(sink, &messages)
+        }
+    }
+}
+
+/// Send one sample directly, bypassing the outbox, to check a sink's settings.
+pub fn test(sink: &MetricSink, point: &StatPoint, device: &str) -> Result<(), String> {
+    validate(sink)?;
+    let message = encode(sink, point, device)?;
+    send(sink, &[message])
+}
+
+fn pending(conn: &Connection, sink_id: i64) -> i64 {
+    conn.query_row(
+        "SELECT COUNT(*) FROM sink_outbox WHERE sink_id = ?1",
+        [sink_id],
+        |row| row.get(0),
+    )
+    .unwrap_or(0)
+}
+
+/// Send the sink's oldest queued batch if it is due. Returns true when a full
+/// batch went out and more may be waiting.
+fn flush(conn: &Connection, sink: &MetricSink, now: i64) -> bool {
+    let Some(sink_id) = sink.id else {
+        return false;
+    };
+    let st = STATE.lock().get(&sink_id).cloned().unwrap_or_default();
+    if now < st.retry_at {
+        return false;
+    }
+    let waiting = pending(conn, sink_id);
+    if waiting == 0 || (waiting < sink.batch_size && now - st.last_flush < sink.flush_interval_ms) {
+        return false;
+    }
+
+    let batch = conn
+        .prepare_cached(
+            "SELECT id, topic, payload FROM sink_outbox WHERE sink_id = ?1 ORDER BY id LIMIT ?2",
+        )
+        .and_then(|mut stmt| {
+            stmt.query_map(params![sink_id, sink.batch_size], |row| {
+                Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?)))
+            })?
+            .collect::<rusqlite::Result<Vec<(i64, (Option<String>, String))>>>()
+        });
+    let batch = match batch {
+        Ok(b) => b,
+        Err(e) => {
+            warn!("failed to read outbox for sink {}: {}", sink_id, e);
+            return false;
+        }
+    };
+    let Some(last_id) = batch.last().map(|(id, _)| *id) else {
+        return false;
+    };
+    let messages: Vec<(Option<String>, String)> = batch.into_iter().map(|(_, m)| m).collect();
+    let result = send(sink, &messages);
+
+    let mut state = STATE.lock();
+    let st = state.entry(sink_id).or_default();
+    st.last_flush = now;
+    match result {
+        Ok(()) => {
+            if let Err(e) = conn.execute(
+                "DELETE FROM sink_outbox WHERE sink_id = ?1 AND id <= ?2",
+                params![sink_id, last_id],
+            ) {
+                warn!("failed to clear outbox for sink {}: {}", sink_id, e);
+            }
+            st.failures = 0;
+            st.retry_at = 0;
+            st.last_error = None;
+            st.last_success = Some(now);
+            messages.len() as i64 >= sink.batch_size
+        }
+        Err(e) => {
+            if st.failures == 0 {
+                warn!("sink {:?} unavailable: {}", sink.name, e);
+            }
+            st.failures += 1;
+            let backoff = (1000i64 << st.failures.min(16)).min(MAX_BACKOFF_MS);
+            st.retry_at = now + backoff;
+            st.last_error = Some(e);
+            false
+        }
+    }
+}
+
+fn trim_outbox(conn: &Connection, sink_id: i64) {
+    let _ = conn.execute(
+        "DELETE FROM sink_outbox WHERE sink_id = ?1 AND id <= (SELECT id FROM sink_outbox WHERE sink_id = ?1 ORDER BY id DESC LIMIT 1 OFFSET ?2)",
+        params![sink_id, MAX_OUTBOX],
+    );
+}
+
+/// Delivery state and queue length of every sink.
+pub fn status(conn: &Connection) -> Result<Vec<SinkStatus>, String> {
+    let mut stmt = conn
+        .prepare("SELECT s.id, (SELECT COUNT(*) FROM sink_outbox o WHERE o.sink_id = s.id) FROM metric_sink s ORDER BY s.id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?)))
+        .map_err(|e| e.to_string())?;
+    let state = STATE.lock();
+    rows.map(|r| {
+        let (sink_id, pending) = r.map_err(|e| e.to_string())?;
+        let st = state.get(&sink_id).cloned().unwrap_or_default();
+        Ok(SinkStatus {
+            sink_id,
+            pending,
+            failures: st.failures,
+            last_error: st.last_error,
+            last_success: st.last_success,
+            retry_at: (st.retry_at > 0).then_some(st.retry_at),
+        })
+    })
+    .collect()
+}
+
+/// Drain the outbox in the background.
+pub fn start() {
+    thread::spawn(|| {
+        let conn = match db_conn() {
+            Ok(c) => c,
+            Err(e) => {
+                warn!("metric sinks: {}", e);
+                return;
+            }
+        };
+        loop {
+            match enabled_sinks(&conn) {
+                Ok(sinks) => {
+                    for sink in &sinks {
+                        while flush(&conn, sink, chrono::Utc::now().timestamp_millis()) {}
+                        if let Some(id) = sink.id {
+                            trim_outbox(&conn, id);
+                        }
+                    }
+                }
+                Err(e) => warn!("failed to load metric sinks: {}", e),
+            }
+            thread::sleep(TICK);
+        }
+    });
+}
@@ -0,0 +1,399 @@
+//! Parser for tegrastats output lines.
+//!
+//! The format drifts between releases and boards, for example:
+//!
+//! JetPack 4 (Nano):
+//!   RAM 1765/3964MB (lfb 6x4MB) SWAP 0/1982MB (cached 0MB) IRAM 0/252kB(lfb 252kB)
+//!   CPU [3%@102,1%@102,off,off] EMC_FREQ 0%@1600 GR3D_FREQ 0%@76 APE 25 PLL@28C
+//!   CPU@30.5C PMIC@100C GPU@29C POM_5V_IN 1197/1197 POM_5V_GPU 40/40
+//! JetPack 4 (Xavier):
+//!   ... GR3D_FREQ 0%@1377 APE 150 MTS fg 0% bg 0% AO@38C GPU@38.5C ... GPU 0/0 CPU 155/155 SOC 1244/1244
+//! JetPack 5/6 (Orin), optionally prefixed with a date and time:
+//!   ... CPU [0%@729,off,...] EMC_FREQ 0%@2133 GR3D_FREQ 0%@[305,305] NVENC off NVDEC off
+//!   VIC_FREQ 0%@115 NVDLA0 off PVA0_FREQ off APE 174 cpu@44.718C tj@44.718C
+//!   VDD_IN 5010mW/5010mW VDD_CPU_GPU_CV 795mW/795mW VDD_SOC 1430mW/1430mW
+//!
+//! Rather than matching each layout, tokens are classified by shape, so unknown
+//! engines, thermal zones and rails are picked up as well.
+
+use crate::types::{CpuCore, EngineUsage, MemUsage, PowerRail, TegraStats};
+
+/// Parse one tegrastats line. Unrecognised tokens are skipped.
+pub fn parse(line: &str) -> TegraStats {
+    let mut stats = TegraStats::default();
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let mut i = 0;
+
+    while i < tokens.len() {
+        let tok = tokens[i];
+        let next = tokens.get(i + 1).copied();
+
+        match tok {
+            "RAM" | "SWAP" | "IRAM" => {
+                let (usage, used) = parse_mem(&tokens[i + 1..]);
+                match tok {
+                    "RAM" => stats.ram = usage,
+                    "SWAP" => stats.swap = usage,
+                    _ => stats.iram = usage,
+                }
+                i += 1 + used;
+                continue;
+            }
+            "CPU" if next.is_some_and(|n| n.starts_with('[')) => {
+                stats.cpus = parse_cpus(next.unwrap_or(""));
+                i += 2;
+                continue;
+            }
+            // Xavier: "MTS fg 0% bg 0%"
+            "MTS" => {
+                let mut j = i + 1;
+                while j + 1 < tokens.len() && (tokens[j] == "fg" || tokens[j] == "bg") {
+                    let name = format!("MTS_{}", tokens[j].to_uppercase());
+                    stats.engines.insert(name, parse_engine(tokens[j + 1]));
+                    j += 2;
+                }
+                i = j;
+                continue;
+            }
+            _ => {}
+        }
+
+        if let Some((name, temp)) = parse_temp(tok) {
+            stats.temps.insert(name.to_string(), temp);
+            i += 1;
+            continue;
+        }
+
+        if !is_name(tok) {
+            i += 1;
+            continue;
+        }
+        let Some(value) = next else {
+            break;
+        };
+
+        if let Some(rail) = parse_rail(value) {
+            stats.rails.insert(tok.to_string(), rail);
+            i += 2;
+            continue;
+        }
+        if value == "off" || value.contains('%') || value.parse::<f64>().is_ok() {
+            let engine = parse_engine(value);
+            let name = tok.strip_suffix("_FREQ").unwrap_or(tok);
+            match name {
+                "EMC" => stats.emc = Some(engine),
+                "GR3D" => stats.gpu = Some(engine),
+                _ => {
+                    stats.engines.insert(name.to_string(), engine);
+                }
+            }
+            i += 2;
+            continue;
+        }
+        i += 1;
+    }
+
+    stats
+}
+
+impl TegraStats {
+    /// GPU load in percent (GR3D).
+    pub fn gpu_util(&self) -> Option<f64> {
+        self.gpu.as_ref().and_then(|g| g.load_pct)
+    }
+
+    /// GPU thermal zone, printed as "GPU@" on JetPack 4/5 and "gpu@" on JetPack 6.
+    pub fn gpu_temp_c(&self) -> Option<f64> {
+        self.temps
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("gpu"))
+            .map(|(_, v)| *v)
+    }
+}
+
+/// Engine and rail names are upper-case identifiers like GR3D_FREQ or VDD_IN.
+fn is_name(tok: &str) -> bool {
+    tok.chars().next().is_some_and(|c| c.is_ascii_uppercase())
+        && tok
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// "used/totalMB" followed by an optional "(lfb NxMB)" or "(cached NMB)".
+/// Returns the usage and how many tokens were consumed.
+fn parse_mem(tokens: &[&str]) -> (Option<MemUsage>, usize) {
+    let Some(first) = tokens.first() else {
+        return (None, 0);
+    };
+    // IRAM glues the parenthesis on: "0/252kB(lfb"
+    let (amount, glued) = match first.split_once('(') {
+        Some((a, rest)) => (a, Some(rest)),
+        None => (*first, None),
+    };
+    let mut used = 1;
+    let Some((u, t)) = amount.split_once('/') else {
+        return (None, used);
+    };
+    let unit_kb = unit_to_kb(t);
+    let num = |s: &str| {
+        s.trim_end_matches(|c: char| c.is_ascii_alphabetic())
+            .parse::<f64>()
+            .ok()
+    };
+    let mut usage = MemUsage {
+        used_kb: (num(u).unwrap_or(0.0) * unit_kb) as u64,
+        total_kb: (num(t).unwrap_or(0.0) * unit_kb) as u64,
+        lfb: None,
+        cached_kb: None,
+    };
+
+    let label = match glued {
+        Some(l) => Some(l),
+        None => match tokens.get(1) {
+            Some(t) if t.starts_with('(') => {
+                used += 1;
+                Some(t.trim_start_matches('('))
+            }
+            _ => None,
+        },
+    };
+    if let Some(label) = label {
+        if let Some(value) = tokens.get(used) {
+            let value = value.trim_end_matches(')');
+            used += 1;
+            match label {
+                "lfb" => usage.lfb = Some(value.to_string()),
+                "cached" => usage.cached_kb = num(value).map(|v| (v * unit_to_kb(value)) as u64),
+                _ => {}
+            }
+        }
+    }
+    (Some(usage), used)
+}
+
+fn unit_to_kb(s: &str) -> f64 {
+    if s.ends_with("GB") {
+        1024.0 * 1024.0
+    } else if s.ends_with("kB") || s.ends_with("KB") {
+        1.0
+    } else {
+        1024.0
+    }
+}
+
+/// "[3%@102,1%@102,off,off]", older releases use "[35%,0%,off,off]@1734".
+fn parse_cpus(tok: &str) -> Vec<CpuCore> {
+    let (list, shared_freq) = match tok.rsplit_once(']') {
+        Some((l, rest)) => (
+            l,
+            rest.strip_prefix('@').and_then(|f| f.parse::<f64>().ok()),
+        ),
+        None => (tok, None),
+    };
+    list.trim_start_matches('[')
+        .split(',')
+        .enumerate()
+        .map(|(index, core)| {
+            if core == "off" {
+                return CpuCore {
+                    index,
+                    online: false,
+                    load_pct: None,
+                    freq_mhz: None,
+                };
+            }
+            let engine = parse_engine(core);
+            CpuCore {
+                index,
+                online: true,
+                load_pct: engine.load_pct,
+                freq_mhz: engine.freq_mhz.or(shared_freq),
+            }
+        })
+        .collect()
+}
+
+/// "off", "12%", "12%@1300", "0%@[305,305]" or a bare frequency like "174".
+fn parse_engine(value: &str) -> EngineUsage {
+    if value == "off" {
+        return EngineUsage::default();
+    }
+    let (load, freq) = match value.split_once('@') {
+        Some((l, f)) => (Some(l), Some(f)),
+        None if value.contains('%') => (Some(value), None),
+        None => (None, Some(value)),
+    };
+    let load_pct = load.and_then(|l| l.trim_end_matches('%').parse::<f64>().ok());
+    let freq_mhz = freq.and_then(|f| {
+        f.trim_matches(|c| c == '[' || c == ']')
+            .split(',')
+            .filter_map(|v| v.parse::<f64>().ok())
+            .reduce(f64::max)
+    });
+    EngineUsage {
+        online: true,
+        load_pct,
+        freq_mhz,
+    }
+}
+
+/// "GPU@38.5C", "tj@44.718C". Disabled sensors print -256C and are dropped.
+fn parse_temp(tok: &str) -> Option<(&str, f64)> {
+    let (name, value) = tok.split_once('@')?;
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    let temp = value.strip_suffix('C')?.parse::<f64>().ok()?;
+    if temp <= -100.0 {
+        return None;
+    }
+    Some((name, temp))
+}
+
+/// "1197/1197" (JetPack 4) or "5010mW/5010mW" (JetPack 5+).
+fn parse_rail(value: &str) -> Option<PowerRail> {
+    let (cur, avg) = value.split_once('/')?;
+    let num = |s: &str| s.trim_end_matches("mW").parse::<f64>().ok();
+    Some(PowerRail {
+        current_mw: num(cur)?,
+        average_mw: num(avg)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nano_jetpack4() {
+        let s = parse(
+            "RAM 1765/3964MB (lfb 6x4MB) SWAP 0/1982MB (cached 0MB) IRAM 0/252kB(lfb 252kB) \
+             CPU [3%@102,1%@102,off,off] EMC_FREQ 0%@1600 GR3D_FREQ 0%@76 APE 25 PLL@28C \
+             CPU@30.5C PMIC@100C GPU@29C AO@36C thermal@29.75C POM_5V_IN 1197/1197 \
+             POM_5V_GPU 40/40 POM_5V_CPU 158/158",
+        );
+
+        let ram = s.ram.as_ref().unwrap();
+        assert_eq!(ram.used_kb, 1765 * 1024);
+        assert_eq!(ram.total_kb, 3964 * 1024);
+        assert_eq!(ram.lfb.as_deref(), Some("6x4MB"));
+        let swap = s.swap.as_ref().unwrap();
+        assert_eq!(swap.total_kb, 1982 * 1024);
+        assert_eq!(swap.cached_kb, Some(0));
+        let iram = s.iram.as_ref().unwrap();
+        assert_eq!((iram.used_kb, iram.total_kb), (0, 252));
+        assert_eq!(iram.lfb.as_deref(), Some("252kB"));
+
+        assert_eq!(s.cpus.len(), 4);
+        assert!(s.cpus[0].online);
+        assert_eq!(s.cpus[0].load_pct, Some(3.0));
+        assert_eq!(s.cpus[0].freq_mhz, Some(102.0));
+        assert!(!s.cpus[2].online);
+        assert_eq!(s.cpus[3].load_pct, None);
+
+        assert_eq!(s.emc.as_ref().unwrap().freq_mhz, Some(1600.0));
+        assert_eq!(s.gpu_util(), Some(0.0));
+        assert_eq!(s.gpu.as_ref().unwrap().freq_mhz, Some(76.0));
+        assert_eq!(s.engines["APE"].freq_mhz, Some(25.0));
+
+        assert_eq!(s.temps["CPU"], 30.5);
+        assert_eq!(s.temps["thermal"], 29.75);
+        assert_eq!(s.temps.len(), 6);
+        assert_eq!(s.rails["POM_5V_IN"].current_mw, 1197.0);
+        assert_eq!(s.rails["POM_5V_GPU"].average_mw, 40.0);
+        assert_eq!(s.rails.len(), 3);
+    }
+
+    #[test]
+    fn xavier_jetpack4() {
+        let s = parse(
+            "RAM 2415/7772MB (lfb 860x4MB) SWAP 0/3886MB (cached 0MB) \
+             CPU [2%@1190,1%@1190,off,off,off,off] EMC_FREQ 0%@1600 GR3D_FREQ 0%@114 APE 150 \
+             MTS fg 0% bg 3% AO@38C GPU@38.5C PMIC@100C AUX@37C CPU@39.5C thermal@38.4C \
+             GPU 0/0 CPU 155/155 SOC 1244/1244 CV 0/0 VDDRQ 310/310 SYS5V 1815/1815",
+        );
+
+        assert!(s.iram.is_none());
+        assert_eq!(s.cpus.len(), 6);
+        assert_eq!(s.cpus[1].freq_mhz, Some(1190.0));
+        assert_eq!(s.engines["MTS_FG"].load_pct, Some(0.0));
+        assert_eq!(s.engines["MTS_BG"].load_pct, Some(3.0));
+        assert_eq!(s.gpu_temp_c(), Some(38.5));
+
+        // Bare-number rails share names with thermal zones and the CPU list
+        assert_eq!(s.rails["GPU"].current_mw, 0.0);
+        assert_eq!(s.rails["CPU"].current_mw, 155.0);
+        assert_eq!(s.rails["SOC"].average_mw, 1244.0);
+        assert_eq!(s.rails["SYS5V"].current_mw, 1815.0);
+        assert_eq!(s.rails.len(), 6);
+    }
+
+    #[test]
+    fn orin_jetpack5() {
+        let s = parse(
+            "RAM 3218/30536MB (lfb 6282x4MB) SWAP 0/15268MB (cached 0MB) \
+             CPU [0%@729,0%@729,off,off] EMC_FREQ 0%@2133 GR3D_FREQ 12%@[305,612] \
+             NVENC off NVDEC off VIC_FREQ 0%@115 NVDLA0 off PVA0_FREQ off APE 174 \
+             cpu@44.718C soc2@41.343C soc0@42.187C gpu@43.2C tj@44.718C soc1@41.468C \
+             CV0@-256C CV1@-256C CV2@-256C VDD_IN 5010mW/4990mW VDD_CPU_GPU_CV 795mW/795mW \
+             VDD_SOC 1430mW/1430mW",
+        );
+
+        assert_eq!(s.ram.as_ref().unwrap().lfb.as_deref(), Some("6282x4MB"));
+        let gpu = s.gpu.as_ref().unwrap();
+        assert_eq!(gpu.load_pct, Some(12.0));
+        // Highest of the per-GPC frequencies
+        assert_eq!(gpu.freq_mhz, Some(612.0));
+        assert_eq!(s.gpu_temp_c(), Some(43.2));
+
+        assert!(!s.engines["NVENC"].online);
+        assert!(!s.engines["NVDLA0"].online);
+        assert!(!s.engines["PVA0"].online);
+        assert_eq!(s.engines["VIC"].load_pct, Some(0.0));
+        assert_eq!(s.engines["VIC"].freq_mhz, Some(115.0));
+
+        // Offline sensors report -256C and are left out
+        assert!(!s.temps.contains_key("CV0"));
+        assert!(!s.temps.contains_key("CV2"));
+        assert_eq!(s.temps["tj"], 44.718);
+        assert_eq!(s.temps.len(), 6);
+
+        let vdd_in = &s.rails["VDD_IN"];
+        assert_eq!((vdd_in.current_mw, vdd_in.average_mw), (5010.0, 4990.0));
+        assert_eq!(s.rails["VDD_SOC"].current_mw, 1430.0);
+        assert_eq!(s.rails.len(), 3);
+    }
+
+    #[test]
+    fn orin_jetpack6_dated() {
+        let s = parse(
+            "10-19-2026 12:00:00 RAM 1503/7620MB (lfb 2x4MB) SWAP 0/3810MB (cached 0MB) \
+             CPU [1%@729,0%@729,0%@729,0%@729,off,off] EMC_FREQ 0%@2133 GR3D_FREQ 0%@[305] \
+             NVENC off NVDEC off NVJPG off VIC off OFA off APE 200 cpu@47.5C soc2@45.1C \
+             soc0@46.2C gpu@46.1C tj@47.5C soc1@45.3C VDD_IN 4571mW/4571mW \
+             VDD_CPU_GPU_CV 480mW/480mW VDD_SOC 1438mW/1438mW",
+        );
+
+        assert_eq!(s.ram.as_ref().unwrap().used_kb, 1503 * 1024);
+        assert_eq!(s.cpus.len(), 6);
+        assert_eq!(s.cpus.iter().filter(|c| c.online).count(), 4);
+        assert_eq!(s.gpu.as_ref().unwrap().freq_mhz, Some(305.0));
+        assert_eq!(s.gpu_temp_c(), Some(46.1));
+        assert!(!s.engines["OFA"].online);
+        assert_eq!(s.engines["APE"].freq_mhz, Some(200.0));
+        assert_eq!(s.temps.len(), 6);
+        assert_eq!(s.rails["VDD_IN"].current_mw, 4571.0);
+        assert_eq!(s.rails.len(), 3);
+    }
+
+    #[test]
+    fn shared_cpu_frequency() {
+        let s = parse("RAM 1021/3964MB (lfb 4x4MB) CPU [35%,0%,off,off]@1734 GR3D 0%@76");
+
+        assert_eq!(s.cpus.len(), 4);
+        assert_eq!(s.cpus[0].load_pct, Some(35.0));
+        assert_eq!(s.cpus[0].freq_mhz, Some(1734.0));
+        assert_eq!(s.cpus[2].freq_mhz, None);
+        assert_eq!(s.gpu_util(), Some(0.0));
+    }
+}
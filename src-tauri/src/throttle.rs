@@ -0,0 +1,326 @@
+//! Throttling detection and the `throttle_event` history.
+//!
+//! Each sample is checked for:
+//!   thermal       a thermal zone at or above its lowest passive trip point
+//!   overcurrent   an OC event counter (hwmon ocN_event_cnt) that went up
+//!   cpu_freq_cap  a loaded CPU cluster running below its configured max frequency
+//!   gpu_freq_cap  a loaded GPU running below its configured max frequency
+//! Consecutive samples with the same cause and source form one event.
+
+use crate::types::{StatPoint, TegraStats, ThrottleCause, ThrottleEvent};
+use log::warn;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashMap};
+
+// Probe lines for the collector, as "path:value" from grep -H:
+//   Z  thermal zone type, temp and trip points
+//   O  OC event counters
+//   Q  cpufreq configured max and related CPUs, devfreq configured max
+pub(crate) const PROBE_SH: &str = "grep -H . /sys/class/thermal/thermal_zone*/type /sys/class/thermal/thermal_zone*/temp /sys/class/thermal/thermal_zone*/trip_point_*_type /sys/class/thermal/thermal_zone*/trip_point_*_temp 2>/dev/null | sed 's/^/Z /'; \
+grep -H . /sys/class/hwmon/hwmon*/oc*_event_cnt 2>/dev/null | sed 's/^/O /'; \
+grep -H . /sys/devices/system/cpu/cpufreq/policy*/scaling_max_freq /sys/devices/system/cpu/cpufreq/policy*/related_cpus /sys/class/devfreq/*/max_freq 2>/dev/null | sed 's/^/Q /'";
+
+// Below this load a low clock is just the governor saving power
+const LOAD_PCT: f64 = 90.0;
+// Clocks within this fraction of the max count as running at max
+const FREQ_RATIO: f64 = 0.95;
+
+// Open event ids per device, keyed by (cause, source), plus the last sample ts
+#[derive(Default)]
+struct DeviceState {
+    open: HashMap<(String, String), i64>,
+    last_ts: Option<i64>,
+}
+
+static STATE: Lazy<Mutex<HashMap<i64, DeviceState>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// "/sys/class/thermal/thermal_zone3/temp:45500" -> ("thermal_zone3", "temp", "45500")
+fn split_probe(line: &str) -> Option<(&str, &str, &str)> {
+    let (path, value) = line.split_once(':')?;
+    let (dir, file) = path.rsplit_once('/')?;
+    let node = dir.rsplit('/').next()?;
+    Some((node, file, value.trim()))
+}
+
+fn is_gpu_devfreq(node: &str) -> bool {
+    ["gpu", "gp10b", "gv11b", "ga10b"]
+        .iter()
+        .any(|n| node.contains(n))
+}
+
+/// OC event counters from the probe's O lines, as (source, count).
+pub(crate) fn oc_counters(probe: &[String]) -> Vec<(String, u64)> {
+    probe
+        .iter()
+        .filter_map(|line| line.strip_prefix("O "))
+        .filter_map(split_probe)
+        .filter_map(|(node, file, value)| {
+            let channel = file.strip_suffix("_event_cnt")?;
+            Some((format!("{}/{}", node, channel), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Throttling visible in one sample. `probe` holds the Z/Q lines with their tags,
+/// `oc_raised` the OC counters that increased since the previous sample.
+pub(crate) fn detect(
+    probe: &[String],
+    tegra: Option<&TegraStats>,
+    oc_raised: &[String],
+) -> Vec<ThrottleCause> {
+    let mut out = Vec::new();
+
+    #[derive(Default)]
+    struct Zone {
+        kind: String,
+        temp: Option<i64>,
+        // trip index -> (type, temp)
+        trips: BTreeMap<String, (Option<String>, Option<i64>)>,
+    }
+    let mut zones: BTreeMap<String, Zone> = BTreeMap::new();
+    // policy -> (max kHz, cpus)
+    let mut policies: BTreeMap<String, (Option<f64>, Vec<usize>)> = BTreeMap::new();
+    let mut gpu_max_hz: Option<(String, f64)> = None;
+
+    for line in probe {
+        let Some((tag, rest)) = line.split_once(' ') else {
+            continue;
+        };
+        let Some((node, file, value)) = split_probe(rest) else {
+            continue;
+        };
+        match tag {
+            "Z" => {
+                let zone = zones.entry(node.to_string()).or_default();
+                if file == "type" {
+                    zone.kind = value.to_string();
+                } else if file == "temp" {
+                    zone.temp = value.parse().ok();
+                } else if let Some(trip) = file.strip_prefix("trip_point_") {
+                    if let Some(idx) = trip.strip_suffix("_type") {
+                        zone.trips.entry(idx.to_string()).or_default().0 = Some(value.to_string());
+                    } else if let Some(idx) = trip.strip_suffix("_temp") {
+                        zone.trips.entry(idx.to_string()).or_default().1 = value.parse().ok();
+                    }
+                }
+            }
+            "Q" if file == "scaling_max_freq" => {
+                policies.entry(node.to_string()).or_default().0 = value.parse().ok();
+            }
+            "Q" if file == "related_cpus" => {
+                policies.entry(node.to_string()).or_default().1 = value
+                    .split_whitespace()
+                    .filter_map(|c| c.parse().ok())
+                    .collect();
+            }
+            "Q" if file == "max_freq" && is_gpu_devfreq(node) => {
+                if let Ok(hz) = value.parse() {
+                    gpu_max_hz = Some((node.to_string(), hz));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for (name, zone) in &zones {
+        let Some(temp) = zone.temp else {
+            continue;
+        };
+        // Lowest passive trip; disabled trips report nonsense like 0 or negative values
+        let trip = zone
+            .trips
+            .values()
+            .filter(|(kind, _)| kind.as_deref() == Some("passive"))
+            .filter_map(|(_, t)| t.filter(|t| *t > 0))
+            .min();
+        if let Some(trip) = trip.filter(|trip| temp >= *trip) {
+            out.push(ThrottleCause {
+                cause: "thermal".to_string(),
+                source: if zone.kind.is_empty() {
+                    name.clone()
+                } else {
+                    zone.kind.clone()
+                },
+                detail: Some(format!(
+                    "{:.1}C >= passive trip {:.1}C",
+                    temp as f64 / 1000.0,
+                    trip as f64 / 1000.0
+                )),
+            });
+        }
+    }
+
+    for source in oc_raised {
+        out.push(ThrottleCause {
+            cause: "overcurrent".to_string(),
+            source: source.clone(),
+            detail: None,
+        });
+    }
+
+    let Some(t) = tegra else {
+        return out;
+    };
+    for (policy, (max_khz, cpus)) in &policies {
+        let Some(max_mhz) = max_khz.map(|k| k / 1000.0) else {
+            continue;
+        };
+        let cores: Vec<_> = t.cpus.iter().filter(|c| cpus.contains(&c.index)).collect();
+        let load = cores.iter().filter_map(|c| c.load_pct).reduce(f64::max);
+        let freq = cores.iter().filter_map(|c| c.freq_mhz).reduce(f64::max);
+        if let (Some(load), Some(freq)) = (load, freq) {
+            if load >= LOAD_PCT && freq < max_mhz * FREQ_RATIO {
+                out.push(ThrottleCause {
+                    cause: "cpu_freq_cap".to_string(),
+                    source: policy.clone(),
+                    detail: Some(format!(
+                        "{:.0} of {:.0} MHz at {:.0}% load",
+                        freq, max_mhz, load
+                    )),
+                });
+            }
+        }
+    }
+    if let (Some((node, max_hz)), Some(load), Some(freq)) = (
+        gpu_max_hz,
+        t.gpu_util(),
+        t.gpu.as_ref().and_then(|g| g.freq_mhz),
+    ) {
+        let max_mhz = max_hz / 1_000_000.0;
+        if load >= LOAD_PCT && freq < max_mhz * FREQ_RATIO {
+            out.push(ThrottleCause {
+                cause: "gpu_freq_cap".to_string(),
+                source: node,
+                detail: Some(format!(
+                    "{:.0} of {:.0} MHz at {:.0}% load",
+                    freq, max_mhz, load
+                )),
+            });
+        }
+    }
+    out
+}
+
+/// Open and close events for a stored sample.
+pub fn track(conn: &Connection, point: &StatPoint) {
+    let mut state = STATE.lock();
+    let dev = state.entry(point.device_id).or_default();
+    let prev_ts = dev.last_ts.replace(point.ts);
+
+    let active: Vec<(String, String)> = point
+        .throttling
+        .iter()
+        .map(|c| (c.cause.clone(), c.source.clone()))
+        .collect();
+    let ended: Vec<(String, String)> = dev
+        .open
+        .keys()
+        .filter(|k| !active.contains(k))
+        .cloned()
+        .collect();
+    for key in ended {
+        if let Some(id) = dev.open.remove(&key) {
+            // The event ended with the previous (last throttled) sample
+            let end = prev_ts.unwrap_or(point.ts);
+            if let Err(e) = conn.execute(
+                "UPDATE throttle_event SET end_ts = ?1 WHERE id = ?2",
+                params![end, id],
+            ) {
+                warn!("failed to close throttle event: {}", e);
+            }
+        }
+    }
+
+    for c in &point.throttling {
+        let key = (c.cause.clone(), c.source.clone());
+        if dev.open.contains_key(&key) {
+            continue;
+        }
+        match conn.execute(
+            "INSERT INTO throttle_event (device_id, cause, source, detail, start_ts) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![point.device_id, c.cause, c.source, c.detail, point.ts],
+        ) {
+            Ok(_) => {
+                dev.open.insert(key, conn.last_insert_rowid());
+            }
+            Err(e) => warn!("failed to store throttle event: {}", e),
+        }
+    }
+}
+
+/// Close a device's open events at its last sample, once collection stops.
+pub fn close_device(conn: &Connection, device_id: i64) {
+    let Some(dev) = STATE.lock().remove(&device_id) else {
+        return;
+    };
+    let Some(end) = dev.last_ts else {
+        return;
+    };
+    for id in dev.open.into_values() {
+        let _ = conn.execute(
+            "UPDATE throttle_event SET end_ts = ?1 WHERE id = ?2",
+            params![end, id],
+        );
+    }
+}
+
+/// Close events left open by a previous run at the device's last stored sample.
+pub fn close_stale(conn: &Connection) {
+    let _ = conn.execute(
+        "UPDATE throttle_event SET end_ts = MAX(start_ts, COALESCE((SELECT MAX(ts) FROM device_stats WHERE device_stats.device_id = throttle_event.device_id), start_ts)) WHERE end_ts IS NULL",
+        [],
+    );
+}
+
+/// Events overlapping [start_ts, end_ts], oldest first.
+pub fn query(
+    conn: &Connection,
+    device_id: i64,
+    start_ts: i64,
+    end_ts: i64,
+) -> Result<Vec<ThrottleEvent>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, device_id, cause, source, detail, start_ts, end_ts FROM throttle_event
+             WHERE device_id = ?1 AND start_ts <= ?3 AND (end_ts IS NULL OR end_ts >= ?2)
+             ORDER BY start_ts",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![device_id, start_ts, end_ts], |row| {
+            Ok(ThrottleEvent {
+                id: row.get(0)?,
+                device_id: row.get(1)?,
+                cause: row.get(2)?,
+                source: row.get(3)?,
+                detail: row.get(4)?,
+                start_ts: row.get(5)?,
+                end_ts: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Mark each point with the events overlapping it. Rollup points cover
+/// [ts, ts + resolution_ms).
+pub fn annotate(points: &mut [StatPoint], events: &[ThrottleEvent]) {
+    for p in points.iter_mut() {
+        let end = p.ts + p.resolution_ms.unwrap_or(1) - 1;
+        for ev in events {
+            if ev.start_ts <= end && ev.end_ts.is_none_or(|e| e >= p.ts) {
+                let cause = ThrottleCause {
+                    cause: ev.cause.clone(),
+                    source: ev.source.clone(),
+                    detail: ev.detail.clone(),
+                };
+                if !p.throttling.contains(&cause) {
+                    p.throttling.push(cause);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Parser for the performance summary `trtexec` prints after a build.
+//!
+//! Every line carries a timestamp and severity prefix, for example (TensorRT 8.5):
+//!   [10/19/2026-12:00:00] [I] TensorRT version: 8.5.2
+//!   [10/19/2026-12:00:03] [I] === Performance summary ===
+//!   [10/19/2026-12:00:03] [I] Throughput: 1052.31 qps
+//!   [10/19/2026-12:00:03] [I] Latency: min = 0.92 ms, max = 1.40 ms, mean = 0.95 ms,
+//!     median = 0.94 ms, percentile(90%) = 0.97 ms, percentile(95%) = 0.99 ms, percentile(99%) = 1.10 ms
+//!   [10/19/2026-12:00:03] [I] H2D Latency: min = 0.02 ms, ...
+//!   [10/19/2026-12:00:03] [I] GPU Compute Time: min = 0.88 ms, ...
+//!   [10/19/2026-12:00:03] [I] D2H Latency: min = 0.01 ms, ...
+//! TensorRT 8.0 writes a single percentile as `percentile = 1.10 ms at 99%`.
+
+use crate::types::TrtLatency;
+
+#[derive(Default)]
+pub struct Summary {
+    pub trt_version: Option<String>,
+    pub throughput_qps: Option<f64>,
+    pub latency: TrtLatency,
+    pub gpu_compute: TrtLatency,
+    pub h2d_mean_ms: Option<f64>,
+    pub d2h_mean_ms: Option<f64>,
+}
+
+/// Parse a trtexec log. Missing lines leave the fields empty.
+pub fn parse(output: &str) -> Summary {
+    let mut summary = Summary::default();
+    for line in output.lines() {
+        // Drop the "[date] [I] " prefix
+        let text = line.rsplit("] ").next().unwrap_or(line).trim();
+        let Some((key, rest)) = text.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "TensorRT version" => summary.trt_version = Some(rest.trim().to_string()),
+            "Throughput" => summary.throughput_qps = first_number(rest),
+            "Latency" => summary.latency = parse_latency(rest),
+            "GPU Compute Time" => summary.gpu_compute = parse_latency(rest),
+            "H2D Latency" => summary.h2d_mean_ms = parse_latency(rest).mean,
+            "D2H Latency" => summary.d2h_mean_ms = parse_latency(rest).mean,
+            _ => {}
+        }
+    }
+    summary
+}
+
+fn first_number(s: &str) -> Option<f64> {
+    s.split_whitespace().next()?.parse().ok()
+}
+
+/// "min = 0.92 ms, max = 1.40 ms, ..., percentile(99%) = 1.10 ms"
+fn parse_latency(s: &str) -> TrtLatency {
+    let mut out = TrtLatency::default();
+    for part in s.split(',') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        let v = first_number(value);
+        let key = key.trim();
+        let pct = if let Some(p) = key.strip_prefix("percentile(") {
+            p.trim_end_matches([')', '%'])
+        } else if key == "percentile" {
+            value
+                .split("at")
+                .nth(1)
+                .map(|p| p.trim().trim_end_matches('%'))
+                .unwrap_or("")
+        } else {
+            ""
+        };
+        match (key, pct) {
+            ("min", _) => out.min = v,
+            ("max", _) => out.max = v,
+            ("mean", _) => out.mean = v,
+            ("median", _) => out.median = v,
+            (_, "90") => out.p90 = v,
+            (_, "95") => out.p95 = v,
+            (_, "99") => out.p99 = v,
+            _ => {}
+        }
+    }
+    out
+}
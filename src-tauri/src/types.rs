@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize)]
 pub struct SshConfig {
@@ -29,6 +30,8 @@ pub struct SystemInfo {
     pub jetpack: Option<String>,
     #[serde(rename = "uptimeSec")]
     pub uptime_sec: u64,
+    // Board model from the device tree, e.g. "NVIDIA Jetson AGX Orin Developer Kit"
+    pub model: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -45,6 +48,38 @@ pub struct StatPoint {
     pub gpu_temp_c: Option<f64>,
     #[serde(rename = "powerMode")]
     pub power_mode: Option<String>,
+    #[serde(rename = "clocksLocked")]
+    pub clocks_locked: Option<bool>,
+    #[serde(rename = "fanRpm")]
+    pub fan_rpm: Option<i64>,
+    #[serde(rename = "fanPwm")]
+    pub fan_pwm: Option<i64>,
+    // Full tegrastats breakdown; only present on live samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tegrastats: Option<TegraStats>,
+    // Named series values (see metrics.rs for naming); on live samples this holds
+    // every series, from get_stats only the requested ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metrics: BTreeMap<String, f64>,
+    // Bucket width for downsampled points, which carry averages in the fields
+    // above and the bucket extremes below; None for raw samples
+    #[serde(rename = "resolutionMs", skip_serializing_if = "Option::is_none")]
+    pub resolution_ms: Option<i64>,
+    #[serde(
+        rename = "metricsMin",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub metrics_min: BTreeMap<String, f64>,
+    #[serde(
+        rename = "metricsMax",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub metrics_max: BTreeMap<String, f64>,
+    // Throttling active during this sample (or overlapping this bucket)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub throttling: Vec<ThrottleCause>,
     #[serde(rename = "deviceId")]
     pub device_id: i64,
 }
@@ -56,3 +91,836 @@ pub struct DirEntry {
     pub is_dir: bool,
     pub size: u64,
 }
+
+#[derive(Serialize, Deserialize)]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    pub kind: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VenvInfo {
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "pythonVersion")]
+    pub python_version: Option<String>,
+    #[serde(rename = "systemSitePackages")]
+    pub system_site_packages: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OfflineInstallReport {
+    pub kind: String,
+    pub uploaded: Vec<String>,
+    pub installed: bool,
+    #[serde(rename = "missingDependencies")]
+    pub missing_dependencies: Vec<String>,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PowerModeDef {
+    pub id: i32,
+    pub name: String,
+    #[serde(rename = "onlineCpus")]
+    pub online_cpus: u32,
+    // Frequency caps as written in nvpmodel.conf; None means uncapped
+    #[serde(rename = "cpuMaxFreqKhz")]
+    pub cpu_max_freq_khz: Option<i64>,
+    #[serde(rename = "gpuMaxFreqHz")]
+    pub gpu_max_freq_hz: Option<i64>,
+    #[serde(rename = "emcMaxFreqHz")]
+    pub emc_max_freq_hz: Option<i64>,
+    #[serde(rename = "powerBudgetW")]
+    pub power_budget_w: Option<f64>,
+    #[serde(rename = "isDefault")]
+    pub is_default: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SetPowerModeResult {
+    pub mode: PowerModeDef,
+    pub applied: bool,
+    #[serde(rename = "rebootRequired")]
+    pub reboot_required: bool,
+    pub output: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PowerModeChange {
+    pub id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    pub ts: i64,
+    #[serde(rename = "modeId")]
+    pub mode_id: i32,
+    #[serde(rename = "modeName")]
+    pub mode_name: String,
+    #[serde(rename = "previousModeId")]
+    pub previous_mode_id: Option<i32>,
+    #[serde(rename = "rebootRequired")]
+    pub reboot_required: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClockDomain {
+    pub name: String,
+    #[serde(rename = "curHz")]
+    pub cur_hz: Option<i64>,
+    #[serde(rename = "minHz")]
+    pub min_hz: Option<i64>,
+    #[serde(rename = "maxHz")]
+    pub max_hz: Option<i64>,
+    // Highest frequency the hardware supports, independent of the current cap
+    #[serde(rename = "hwMaxHz")]
+    pub hw_max_hz: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClockState {
+    // True when jetson_clocks has pinned every CPU cluster and the GPU to their max
+    pub locked: bool,
+    pub cpu: Vec<ClockDomain>,
+    pub gpu: Option<ClockDomain>,
+    pub emc: Option<ClockDomain>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FanState {
+    // Raw PWM duty cycle, 0-255
+    pub pwm: Option<i64>,
+    pub rpm: Option<i64>,
+    // Active nvfancontrol profile; None when nvfancontrol is not running
+    pub profile: Option<String>,
+    pub profiles: Vec<String>,
+    // "auto" while nvfancontrol drives the fan, "manual" otherwise
+    pub control: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct MemUsage {
+    #[serde(rename = "usedKb")]
+    pub used_kb: u64,
+    #[serde(rename = "totalKb")]
+    pub total_kb: u64,
+    // Largest free block, e.g. "6x4MB" for RAM or "252kB" for IRAM
+    pub lfb: Option<String>,
+    // Only reported for SWAP
+    #[serde(rename = "cachedKb")]
+    pub cached_kb: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CpuCore {
+    pub index: usize,
+    pub online: bool,
+    #[serde(rename = "loadPct")]
+    pub load_pct: Option<f64>,
+    #[serde(rename = "freqMhz")]
+    pub freq_mhz: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct EngineUsage {
+    pub online: bool,
+    #[serde(rename = "loadPct")]
+    pub load_pct: Option<f64>,
+    // Highest frequency when several are reported (e.g. one per GPC on Orin)
+    #[serde(rename = "freqMhz")]
+    pub freq_mhz: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct PowerRail {
+    #[serde(rename = "currentMw")]
+    pub current_mw: f64,
+    #[serde(rename = "averageMw")]
+    pub average_mw: f64,
+}
+
+/// Everything reported by one tegrastats line.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TegraStats {
+    pub ram: Option<MemUsage>,
+    pub swap: Option<MemUsage>,
+    pub iram: Option<MemUsage>,
+    pub cpus: Vec<CpuCore>,
+    pub emc: Option<EngineUsage>,
+    pub gpu: Option<EngineUsage>,
+    // Other engines keyed by name without the _FREQ suffix: NVENC, NVDEC, NVJPG,
+    // VIC, APE, NVDLA0, PVA0, OFA, MTS_FG, MTS_BG, ...
+    pub engines: BTreeMap<String, EngineUsage>,
+    // Thermal zones in degrees C keyed by name as printed (CPU, GPU, tj, soc0, ...)
+    pub temps: BTreeMap<String, f64>,
+    // Power rails keyed by name as printed (VDD_IN, VDD_CPU_GPU_CV, POM_5V_IN, ...)
+    pub rails: BTreeMap<String, PowerRail>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Subscription {
+    // "channel:<token>", "event:<token>" or "fleet:<token>:<device id>"
+    pub key: String,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    // "channel", "event" or "internal"
+    pub kind: String,
+    // Interval the subscriber asked for
+    #[serde(rename = "intervalMs")]
+    pub interval_ms: u64,
+    // Interval the device's collector actually samples at
+    #[serde(rename = "samplingIntervalMs")]
+    pub sampling_interval_ms: u64,
+    pub since: i64,
+    // Samples delivered so far
+    pub delivered: u64,
+}
+
+// Core values of a sample, for views that show many devices at once
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FleetPoint {
+    pub ts: i64,
+    pub cpu: f64,
+    #[serde(rename = "ramUsedMb")]
+    pub ram_used_mb: i64,
+    #[serde(rename = "ramTotalMb")]
+    pub ram_total_mb: i64,
+    #[serde(rename = "gpuUtil")]
+    pub gpu_util: Option<f64>,
+    #[serde(rename = "gpuTempC")]
+    pub gpu_temp_c: Option<f64>,
+    #[serde(rename = "fanRpm")]
+    pub fan_rpm: Option<i64>,
+    #[serde(rename = "powerMode")]
+    pub power_mode: Option<String>,
+    // Causes of throttling active in the sample
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub throttling: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct FleetDevice {
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    // "online", "pending" (no sample yet) or "offline"
+    pub status: String,
+    // Latest sample; kept while offline so the last known values can be shown
+    pub point: Option<FleetPoint>,
+    #[serde(rename = "lastSeen")]
+    pub last_seen: Option<i64>,
+    // Why the device is offline
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FleetUpdate {
+    pub ts: i64,
+    pub devices: Vec<FleetDevice>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct MetricSeries {
+    pub name: String,
+    pub unit: String,
+    #[serde(rename = "firstTs")]
+    pub first_ts: i64,
+    #[serde(rename = "lastTs")]
+    pub last_ts: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilesystemInfo {
+    pub source: String,
+    pub fstype: String,
+    pub mount: String,
+    #[serde(rename = "sizeKb")]
+    pub size_kb: u64,
+    #[serde(rename = "usedKb")]
+    pub used_kb: u64,
+    #[serde(rename = "availKb")]
+    pub avail_kb: u64,
+    #[serde(rename = "inodesTotal")]
+    pub inodes_total: Option<u64>,
+    #[serde(rename = "inodesUsed")]
+    pub inodes_used: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BlockDeviceInfo {
+    pub name: String,
+    // "emmc", "sd", "nvme" or "disk"
+    pub kind: String,
+    pub model: Option<String>,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+    // eMMC wear estimates (JEDEC life_time / pre_eol_info), e.g. "0x01"
+    #[serde(rename = "lifeTimeEst")]
+    pub life_time_est: Option<String>,
+    #[serde(rename = "preEol")]
+    pub pre_eol: Option<String>,
+    // NVMe smart-log values
+    #[serde(rename = "percentUsed")]
+    pub percent_used: Option<f64>,
+    #[serde(rename = "tempC")]
+    pub temp_c: Option<f64>,
+    #[serde(rename = "mediaErrors")]
+    pub media_errors: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FilesystemOverview {
+    pub filesystems: Vec<FilesystemInfo>,
+    pub disks: Vec<BlockDeviceInfo>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProcessInfo {
+    pub pid: i64,
+    pub ppid: i64,
+    pub user: String,
+    // Executable name from /proc/<pid>/stat
+    pub name: String,
+    // Full command line; kernel threads show as "[name]"
+    pub command: String,
+    pub state: String,
+    pub nice: i32,
+    pub threads: u32,
+    // Share of one core over the sampling window, like top (can exceed 100)
+    #[serde(rename = "cpuPct")]
+    pub cpu_pct: f64,
+    #[serde(rename = "rssKb")]
+    pub rss_kb: u64,
+    #[serde(rename = "memPct")]
+    pub mem_pct: f64,
+    // nvmap allocations; None when the platform doesn't report them (needs root)
+    #[serde(rename = "gpuMemKb")]
+    pub gpu_mem_kb: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RetentionPolicy {
+    // Raw samples are kept this many days (at least 1)
+    #[serde(rename = "rawDays")]
+    pub raw_days: i64,
+    // 1-minute rollups; None keeps them forever
+    #[serde(rename = "minuteDays")]
+    pub minute_days: Option<i64>,
+    // 1-hour rollups; None keeps them forever
+    #[serde(rename = "hourDays")]
+    pub hour_days: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrometheusConfig {
+    pub enabled: bool,
+    // Interface to listen on; 127.0.0.1 keeps the endpoint local,
+    // 0.0.0.0 exposes it to the network
+    #[serde(rename = "bindAddress")]
+    pub bind_address: String,
+    pub port: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PrometheusStatus {
+    #[serde(flatten)]
+    pub config: PrometheusConfig,
+    // Address actually listened on, None while stopped
+    pub listening: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CompactionReport {
+    // Rollup rows written
+    #[serde(rename = "rolledMinute")]
+    pub rolled_minute: usize,
+    #[serde(rename = "rolledHour")]
+    pub rolled_hour: usize,
+    // Rows deleted
+    #[serde(rename = "prunedRaw")]
+    pub pruned_raw: usize,
+    #[serde(rename = "prunedMinute")]
+    pub pruned_minute: usize,
+    #[serde(rename = "prunedHour")]
+    pub pruned_hour: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ExportReport {
+    pub path: String,
+    // "csv", "ndjson" or "parquet"
+    pub format: String,
+    // "stats" or "metrics"
+    pub table: String,
+    pub rows: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ImportReport {
+    pub table: String,
+    pub imported: u64,
+    // Rows already present, or without a timestamp
+    pub skipped: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AggregatePoint {
+    // Bucket start, or the sample time for LTTB
+    pub ts: i64,
+    // Samples in the bucket
+    pub count: i64,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    // Only available when aggregating raw samples
+    pub p95: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AggregatedSeries {
+    pub name: String,
+    pub unit: String,
+    pub points: Vec<AggregatePoint>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct AggregatedStats {
+    #[serde(rename = "startTs")]
+    pub start_ts: i64,
+    #[serde(rename = "endTs")]
+    pub end_ts: i64,
+    // "buckets" or "lttb"
+    pub method: String,
+    // None for LTTB, which keeps original sample times
+    #[serde(rename = "bucketMs")]
+    pub bucket_ms: Option<i64>,
+    // Data the points were computed from: "raw", "1m" or "1h"
+    pub source: String,
+    pub series: Vec<AggregatedSeries>,
+    // Throttle events overlapping the range, for chart annotations
+    #[serde(rename = "throttleEvents")]
+    pub throttle_events: Vec<ThrottleEvent>,
+}
+
+/// A stretch of consecutive samples taken under one power configuration.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PowerSegment {
+    #[serde(rename = "powerMode")]
+    pub power_mode: Option<String>,
+    #[serde(rename = "clocksLocked")]
+    pub clocks_locked: Option<bool>,
+    #[serde(rename = "startTs")]
+    pub start_ts: i64,
+    #[serde(rename = "endTs")]
+    pub end_ts: i64,
+    pub samples: i64,
+}
+
+/// Time spent in one power configuration across a range.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PowerModeTime {
+    #[serde(rename = "powerMode")]
+    pub power_mode: Option<String>,
+    #[serde(rename = "clocksLocked")]
+    pub clocks_locked: Option<bool>,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: i64,
+    // Fraction of the covered time, 0-1
+    pub share: f64,
+    pub samples: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PowerSegments {
+    #[serde(rename = "startTs")]
+    pub start_ts: i64,
+    #[serde(rename = "endTs")]
+    pub end_ts: i64,
+    // Time backed by samples; gaps in collection are not counted
+    #[serde(rename = "coveredMs")]
+    pub covered_ms: i64,
+    pub segments: Vec<PowerSegment>,
+    // Longest first
+    pub totals: Vec<PowerModeTime>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct MetricSink {
+    // None when creating a sink
+    pub id: Option<i64>,
+    // None forwards every device
+    #[serde(rename = "deviceId")]
+    pub device_id: Option<i64>,
+    pub name: Option<String>,
+    // "influx_http", "influx_udp" or "mqtt"
+    pub kind: String,
+    // influx_http: write endpoint, e.g. http://host:8086/api/v2/write?org=lab&bucket=orion
+    // influx_udp: host:port
+    // mqtt: mqtt://host:port
+    pub url: String,
+    // InfluxDB API token
+    pub token: Option<String>,
+    // MQTT credentials
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // MQTT topic template with {device} and {device_id}; default "orion/{device}/stats"
+    pub topic: Option<String>,
+    #[serde(default = "default_qos")]
+    pub qos: u8,
+    // Messages per send
+    #[serde(rename = "batchSize", default = "default_batch_size")]
+    pub batch_size: i64,
+    // Longest a message waits for its batch to fill up
+    #[serde(rename = "flushIntervalMs", default = "default_flush_interval_ms")]
+    pub flush_interval_ms: i64,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_qos() -> u8 {
+    1
+}
+
+fn default_batch_size() -> i64 {
+    500
+}
+
+fn default_flush_interval_ms() -> i64 {
+    5000
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SinkStatus {
+    #[serde(rename = "sinkId")]
+    pub sink_id: i64,
+    // Messages waiting in the outbox
+    pub pending: i64,
+    // Consecutive failed sends
+    pub failures: u32,
+    #[serde(rename = "lastError")]
+    pub last_error: Option<String>,
+    #[serde(rename = "lastSuccess")]
+    pub last_success: Option<i64>,
+    // Next attempt while backing off
+    #[serde(rename = "retryAt")]
+    pub retry_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlertRule {
+    // None when creating a rule
+    pub id: Option<i64>,
+    // None applies the rule to every device
+    #[serde(rename = "deviceId")]
+    pub device_id: Option<i64>,
+    pub name: Option<String>,
+    // StatPoint field (e.g. "gpu_temp_c", "ram_used_pct") or metric series name (e.g. "temp.cpu")
+    pub metric: String,
+    // ">", ">=", "<", "<=", "==" or "!="
+    pub comparator: String,
+    pub threshold: f64,
+    // How long the condition must hold before the alert opens
+    #[serde(rename = "durationSec", default)]
+    pub duration_sec: i64,
+    // "info", "warning" or "critical"
+    #[serde(default)]
+    pub severity: String,
+    // Also show a desktop notification when the alert opens
+    #[serde(default)]
+    pub notify: bool,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AlertEvent {
+    pub id: i64,
+    #[serde(rename = "ruleId")]
+    pub rule_id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    #[serde(rename = "ruleName")]
+    pub rule_name: Option<String>,
+    pub metric: String,
+    pub comparator: String,
+    pub threshold: f64,
+    pub severity: String,
+    // Value when the alert opened, and the worst value seen while open
+    pub value: f64,
+    pub peak: f64,
+    #[serde(rename = "openedAt")]
+    pub opened_at: i64,
+    // None while the alert is open
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct AnomalyEvent {
+    pub id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    // "baseline", "trend" or "fleet"
+    pub kind: String,
+    // device_stats column, e.g. "gpu_temp_c"
+    pub metric: String,
+    // Recent average; for "trend" the growth in MB per hour
+    pub value: f64,
+    // Hour-of-day mean or fleet median; 0 for "trend"
+    pub expected: f64,
+    // z-score for "baseline" and "fleet", r² of the fit for "trend"
+    pub score: f64,
+    pub detail: Option<String>,
+    #[serde(rename = "detectedAt")]
+    pub detected_at: i64,
+    // Latest run that still found it
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: i64,
+    #[serde(rename = "resolvedAt")]
+    pub resolved_at: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ThrottleCause {
+    // "thermal", "overcurrent", "cpu_freq_cap" or "gpu_freq_cap"
+    pub cause: String,
+    // Thermal zone, OC channel, cpufreq policy or GPU devfreq node
+    pub source: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ThrottleEvent {
+    pub id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    pub cause: String,
+    pub source: String,
+    // Detail from the sample that opened the event
+    pub detail: Option<String>,
+    // Timestamps of the first and last throttled samples in device_stats;
+    // end is None while the event is ongoing
+    #[serde(rename = "startTs")]
+    pub start_ts: i64,
+    #[serde(rename = "endTs")]
+    pub end_ts: Option<i64>,
+}
+
+/// Telemetry over a benchmark run. Averages and peaks are None when no sample
+/// carried the metric.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct RunSummary {
+    pub samples: i64,
+    // Board input power (VDD_IN / POM_5V_IN, else the sum of the rails), mW
+    #[serde(rename = "avgPowerMw")]
+    pub avg_power_mw: Option<f64>,
+    #[serde(rename = "peakPowerMw")]
+    pub peak_power_mw: Option<f64>,
+    #[serde(rename = "energyJ")]
+    pub energy_j: Option<f64>,
+    #[serde(rename = "avgCpu")]
+    pub avg_cpu: Option<f64>,
+    #[serde(rename = "peakCpu")]
+    pub peak_cpu: Option<f64>,
+    #[serde(rename = "avgGpuUtil")]
+    pub avg_gpu_util: Option<f64>,
+    #[serde(rename = "peakGpuUtil")]
+    pub peak_gpu_util: Option<f64>,
+    #[serde(rename = "avgGpuTempC")]
+    pub avg_gpu_temp_c: Option<f64>,
+    #[serde(rename = "peakGpuTempC")]
+    pub peak_gpu_temp_c: Option<f64>,
+    // Hottest thermal zone seen
+    #[serde(rename = "peakTempC")]
+    pub peak_temp_c: Option<f64>,
+    // Mean over online cores
+    #[serde(rename = "avgCpuFreqMhz")]
+    pub avg_cpu_freq_mhz: Option<f64>,
+    #[serde(rename = "avgGpuFreqMhz")]
+    pub avg_gpu_freq_mhz: Option<f64>,
+    #[serde(rename = "avgEmcFreqMhz")]
+    pub avg_emc_freq_mhz: Option<f64>,
+    #[serde(rename = "peakRamUsedMb")]
+    pub peak_ram_used_mb: Option<f64>,
+    // Any throttling cause seen during the run
+    pub throttled: bool,
+    // Power configuration of the first sample
+    #[serde(rename = "powerMode")]
+    pub power_mode: Option<String>,
+    #[serde(rename = "clocksLocked")]
+    pub clocks_locked: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BenchmarkRun {
+    pub id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    pub name: Option<String>,
+    pub command: String,
+    // "running", "succeeded", "failed" (non-zero exit), "cancelled" or "error"
+    pub status: String,
+    #[serde(rename = "startedAt")]
+    pub started_at: i64,
+    #[serde(rename = "endedAt")]
+    pub ended_at: Option<i64>,
+    #[serde(rename = "exitStatus")]
+    pub exit_status: Option<i32>,
+    // Combined stdout/stderr; only filled in by get_run
+    pub output: Option<String>,
+    // Output was longer than what is kept; the tail is stored
+    #[serde(rename = "outputTruncated")]
+    pub output_truncated: bool,
+    pub error: Option<String>,
+    #[serde(rename = "sampleIntervalMs")]
+    pub sample_interval_ms: i64,
+    // None while running
+    pub summary: Option<RunSummary>,
+}
+
+/// One message of a run's output stream. The last one has `run` set.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RunOutput {
+    #[serde(rename = "runId")]
+    pub run_id: i64,
+    pub ts: i64,
+    pub line: Option<String>,
+    pub run: Option<BenchmarkRun>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RunMetricDelta {
+    pub metric: String,
+    pub unit: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+    // b - a
+    pub delta: Option<f64>,
+    #[serde(rename = "deltaPct")]
+    pub delta_pct: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RunComparison {
+    pub a: BenchmarkRun,
+    pub b: BenchmarkRun,
+    pub metrics: Vec<RunMetricDelta>,
+}
+
+/// ONNX model uploaded to a device for TensorRT builds.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct OnnxModel {
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    pub name: String,
+    // Relative to the login user's home
+    #[serde(rename = "remotePath")]
+    pub remote_path: String,
+    #[serde(rename = "sizeBytes")]
+    pub size_bytes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TrtBuildOptions {
+    // "fp32" (default), "fp16", "int8" or "best"
+    pub precision: Option<String>,
+    #[serde(rename = "workspaceMb")]
+    pub workspace_mb: Option<u32>,
+    // trtexec shape specs for dynamic inputs, e.g. "input:1x3x224x224"
+    #[serde(rename = "minShapes")]
+    pub min_shapes: Option<String>,
+    #[serde(rename = "optShapes")]
+    pub opt_shapes: Option<String>,
+    #[serde(rename = "maxShapes")]
+    pub max_shapes: Option<String>,
+    // Layers DLA can't run fall back to the GPU
+    #[serde(rename = "dlaCore")]
+    pub dla_core: Option<u32>,
+    #[serde(rename = "sampleIntervalMs")]
+    pub sample_interval_ms: Option<u64>,
+}
+
+/// Latency distribution from a trtexec performance summary, ms.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TrtLatency {
+    pub min: Option<f64>,
+    pub mean: Option<f64>,
+    pub median: Option<f64>,
+    pub p90: Option<f64>,
+    pub p95: Option<f64>,
+    pub p99: Option<f64>,
+    pub max: Option<f64>,
+}
+
+/// One trtexec build of a model on a device, with its profiling results.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrtResult {
+    pub id: i64,
+    #[serde(rename = "deviceId")]
+    pub device_id: i64,
+    // Benchmark run the build was recorded as
+    #[serde(rename = "runId")]
+    pub run_id: i64,
+    pub model: String,
+    #[serde(rename = "onnxPath")]
+    pub onnx_path: String,
+    // None when the build failed
+    #[serde(rename = "enginePath")]
+    pub engine_path: Option<String>,
+    pub precision: String,
+    #[serde(rename = "workspaceMb")]
+    pub workspace_mb: Option<u32>,
+    #[serde(rename = "minShapes")]
+    pub min_shapes: Option<String>,
+    #[serde(rename = "optShapes")]
+    pub opt_shapes: Option<String>,
+    #[serde(rename = "maxShapes")]
+    pub max_shapes: Option<String>,
+    #[serde(rename = "dlaCore")]
+    pub dla_core: Option<u32>,
+    // "built", or the run status ("failed", "cancelled", "error")
+    pub status: String,
+    #[serde(rename = "trtVersion")]
+    pub trt_version: Option<String>,
+    #[serde(rename = "throughputQps")]
+    pub throughput_qps: Option<f64>,
+    // End-to-end latency including host/device copies
+    pub latency: TrtLatency,
+    #[serde(rename = "gpuCompute")]
+    pub gpu_compute: TrtLatency,
+    #[serde(rename = "h2dMeanMs")]
+    pub h2d_mean_ms: Option<f64>,
+    #[serde(rename = "d2hMeanMs")]
+    pub d2h_mean_ms: Option<f64>,
+    #[serde(rename = "createdAt")]
+    pub created_at: i64,
+}
+
+/// Result of `run_command`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CommandResult {
+    pub stdout: String,
+    pub stderr: String,
+    #[serde(rename = "exitStatus")]
+    pub exit_status: i32,
+    #[serde(rename = "durationMs")]
+    pub duration_ms: u64,
+    #[serde(rename = "timedOut")]
+    pub timed_out: bool,
+}
+
+/// One message of `run_command_streaming` output. Lines carry `stream` and
+/// `line`; the last message has `result` set, or `error` if the command could
+/// not be run to completion.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CommandOutput {
+    pub ts: i64,
+    // "stdout" or "stderr"
+    pub stream: Option<String>,
+    pub line: Option<String>,
+    pub result: Option<CommandResult>,
+    pub error: Option<String>,
+}